@@ -1,61 +1,57 @@
-global kernel_code, gdt_start, gdt_pointer, stack_bottom, stack_top, start
+global stack_guard, stack_bottom, stack_top, start, enable_pae_paging
 extern check_cpuid, check_multiboot, kernel_main, error
 
+KERNEL_OFFSET equ 0xC0000000
+HUGE_PAGE_SIZE equ 0x400000
 
-section .text
+; Identity mapped trampoline: runs before paging is enabled, so every
+; address of the higher half has to be converted to its physical address.
+section .boot exec
 bits 32
 
 start:
-    mov esp, stack_top
+    mov esp, stack_top - KERNEL_OFFSET
+    mov esi, eax ; bootloader magic, kept until kernel_main
     call check_multiboot
     call check_cpuid
     call set_up_page_tables
     call enable_paging
-    lgdt [gdt_pointer]
-    call set_protected_mode ; obligatory ?
-
-    jmp kernel_code:flush_cpu
-
-set_protected_mode:
-    mov eax, cr0
-    or eax, 1
-    mov cr0, eax
-    ret
-
-flush_cpu:
-    mov ax, kernel_data
-    mov ss, ax
-    mov ds, ax
-    mov es, ax
-    mov fs, ax
-    mov gs, ax
 
-    push ebx
-    ; we have to push something on top, but why?
-    push 0x69420
-
-    jmp kernel_main
+    mov ecx, higher_half
+    jmp ecx
 
 set_up_page_tables:
     ; map page_directory table recursively
-    mov eax, page_directory
+    mov eax, page_directory - KERNEL_OFFSET
     or eax, 0b11 ; present + writable
-    mov [page_directory + 1023 * 4], eax
+    mov [page_directory - KERNEL_OFFSET + 1023 * 4], eax
 
+    ; identity map the lower 3 GiB, for the trampoline and for building the real page tables
     mov ecx, 0
-    .map_page_directory:
-        mov eax, 0x400000
+    .map_lower_half:
+        mov eax, HUGE_PAGE_SIZE
         mul ecx
         or eax, 0b10000011 ; present + writable + huge
-        mov [page_directory + ecx * 4], eax
+        mov [page_directory - KERNEL_OFFSET + ecx * 4], eax
         inc ecx
-        cmp ecx, 1024
-        jne .map_page_directory
+        cmp ecx, KERNEL_OFFSET / HUGE_PAGE_SIZE
+        jne .map_lower_half
+
+    ; map the start of physical memory to the higher half
+    .map_higher_half:
+        mov eax, ecx
+        sub eax, KERNEL_OFFSET / HUGE_PAGE_SIZE
+        shl eax, 22
+        or eax, 0b10000011 ; present + writable + huge
+        mov [page_directory - KERNEL_OFFSET + ecx * 4], eax
+        inc ecx
+        cmp ecx, 1023 ; don't overwrite the recursive entry
+        jne .map_higher_half
     ret
 
 enable_paging:
     ; load page_directory to cr3 register
-    mov eax, page_directory
+    mov eax, page_directory - KERNEL_OFFSET
     mov cr3, eax
     ; enable PSE-flag in cr4 (Page Size Extension)
     mov eax, cr4
@@ -67,11 +63,62 @@ enable_paging:
     mov cr0, eax
     ret
 
+; void enable_pae_paging(uint32_t pdpt)
+; Paging has to be disabled to switch to PAE, so this must stay identity mapped
+; in the new page tables. The stack can't be used until paging is enabled again.
+enable_pae_paging:
+    mov ecx, [esp + 4]
+    mov eax, cr0
+    and eax, 0x7fffffff
+    mov cr0, eax
+    mov cr3, ecx
+    mov eax, cr4
+    or eax, 1 << 5
+    mov cr4, eax
+    mov eax, cr0
+    or eax, 1 << 31
+    mov cr0, eax
+    ret
+
+
+section .text
+
+higher_half:
+    mov esp, stack_top
+    lgdt [gdt_pointer]
+    call set_protected_mode ; obligatory ?
+
+    jmp kernel_code:flush_cpu
+
+set_protected_mode:
+    mov eax, cr0
+    or eax, 1
+    mov cr0, eax
+    ret
+
+flush_cpu:
+    mov ax, kernel_data
+    mov ss, ax
+    mov ds, ax
+    mov es, ax
+    mov fs, ax
+    mov gs, ax
+
+    push ebx ; physical address of the multiboot information structure
+    push esi ; multiboot1 or multiboot2 magic
+    ; we have to push something on top, but why?
+    push 0x69420
+    xor ebp, ebp ; ends the chain of frame pointers walked by the panic backtrace
+
+    jmp kernel_main
+
 
 section .bss
 align 4096
 page_directory:
     resb 4096
+stack_guard: ; unmapped by the kernel, so that a stack overflow double faults
+    resb 4096
 stack_bottom:
     resb 4096 * 1024
 stack_top:
@@ -87,16 +134,13 @@ section .rodata
     db 0x00       ; base high
 %endmacro
 
+; only used to reach kernel_main, which loads the GDT of src/gdt.rs
 gdt_start:
     dq 0
 kernel_code: equ $ - gdt_start
     DEFINE_GDT_SEGMENT 0b10011011
 kernel_data: equ $ - gdt_start
     DEFINE_GDT_SEGMENT 0b10010011
-; user_code: equ $ - gdt_start
-;     DEFINE_GDT_SEGMENT 0b11111011
-; user_data: equ $ - gdt_start
-;     DEFINE_GDT_SEGMENT 0b11110011
 gdt_pointer:
     dw $ - gdt_start - 1
     dd gdt_start
\ No newline at end of file
@@ -1,10 +1,16 @@
 global check_multiboot, check_cpuid
 extern error
 
+section .boot exec
+bits 32
+
 check_multiboot:
-    cmp eax, 0x36d76289
+    cmp eax, 0x36d76289 ; multiboot2
+    je .multiboot
+    cmp eax, 0x2badb002 ; multiboot1
     jne .no_multiboot
-    ret
+    .multiboot:
+        ret
     .no_multiboot:
         mov al, '0'
         jmp error
@@ -1,12 +1,11 @@
 bits 32
 global error
 
-section .data
+; called before paging is enabled
+section .boot exec
 
 hex_chars db "0123456789ABCDEF"
 
-section .text
-
 %macro WRITE_CHAR 2
     mov byte [%1], %2
     mov byte [%1 + 1], 0x4f
@@ -7,4 +7,12 @@ header_start:
     dw 0 ; type (of what?)
     dw 0 ; flags (of what?)
     dd 8 ; size (of what?)
-header_end:
\ No newline at end of file
+header_end:
+
+; multiboot1 header, for bootloaders such as `qemu -kernel`
+MULTIBOOT1_MAGIC equ 0x1badb002
+MULTIBOOT1_FLAGS equ 0b11 ; page aligned modules + memory information
+align 4
+    dd MULTIBOOT1_MAGIC
+    dd MULTIBOOT1_FLAGS
+    dd 0x100000000 - (MULTIBOOT1_MAGIC + MULTIBOOT1_FLAGS) ; checksum
@@ -0,0 +1,18 @@
+//! Feeds arbitrary bytes to the parser, which must neither panic nor read
+//! out of the buffer. Run with `cargo +nightly fuzz run boot_information`
+//! from the `multiboot` directory, with the address sanitizer that
+//! `cargo fuzz` enables by default.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use multiboot::BootInformation;
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(boot_info) = BootInformation::from_bytes(data) {
+        common::exercise(&boot_info);
+    }
+});
@@ -0,0 +1,256 @@
+use super::{read_u32, read_u64};
+
+/// Size of a section header of a 32-bit ELF.
+const ENTRY_SIZE_32: usize = 40;
+/// Size of a section header of a 64-bit ELF.
+const ENTRY_SIZE_64: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSectionsTag<'a> {
+    number_of_sections: u32,
+    entry_size: u32,
+    sections: &'a [u8],
+}
+
+impl<'a> ElfSectionsTag<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Option<ElfSectionsTag<'a>> {
+        Some(ElfSectionsTag {
+            number_of_sections: read_u32(data, 0)?,
+            entry_size: read_u32(data, 4)?,
+            // followed by the index of the string table
+            sections: data.get(12..)?,
+        })
+    }
+
+    /// Get an iterator of loaded ELF sections.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// if let Some(elf_tag) = boot_info.elf_sections_tag() {
+    ///     let mut total = 0;
+    ///     for section in elf_tag.sections() {
+    ///         println!("Section: {:?}", section);
+    ///         total += 1;
+    ///     }
+    /// }
+    /// ```
+    pub fn sections(&self) -> ElfSectionIter<'a> {
+        let entry_size = self.entry_size as usize;
+        ElfSectionIter {
+            sections: self.sections,
+            // only the two ELF classes are known
+            remaining_sections: if entry_size == ENTRY_SIZE_32 || entry_size == ENTRY_SIZE_64 {
+                self.number_of_sections
+            } else {
+                0
+            },
+            entry_size,
+        }
+    }
+
+    /// The section at `index` in the section header table, the one that
+    /// `link` refers to. Unlike `sections`, the null section is included.
+    pub fn section(&self, index: u32) -> Option<ElfSection<'a>> {
+        let entry_size = self.entry_size as usize;
+        if index >= self.number_of_sections
+            || (entry_size != ENTRY_SIZE_32 && entry_size != ENTRY_SIZE_64)
+        {
+            return None;
+        }
+        let start = (index as usize).checked_mul(entry_size)?;
+        let inner = self.sections.get(start..start.checked_add(entry_size)?)?;
+        Some(ElfSection { inner })
+    }
+}
+
+/// An iterator over some ELF sections.
+#[derive(Clone, Debug)]
+pub struct ElfSectionIter<'a> {
+    sections: &'a [u8],
+    remaining_sections: u32,
+    entry_size: usize,
+}
+
+impl<'a> Iterator for ElfSectionIter<'a> {
+    type Item = ElfSection<'a>;
+
+    fn next(&mut self) -> Option<ElfSection<'a>> {
+        while self.remaining_sections != 0 {
+            let inner = self.sections.get(..self.entry_size)?;
+            self.sections = &self.sections[self.entry_size..];
+            self.remaining_sections -= 1;
+
+            let section = ElfSection { inner };
+            if section.section_type() != ElfSectionType::Unused {
+                return Some(section);
+            }
+        }
+        None
+    }
+}
+
+/// A single generic ELF Section.
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSection<'a> {
+    /// The section header, of `ENTRY_SIZE_32` or `ENTRY_SIZE_64` bytes.
+    inner: &'a [u8],
+}
+
+impl ElfSection<'_> {
+    fn is_64(&self) -> bool {
+        self.inner.len() == ENTRY_SIZE_64
+    }
+
+    /// A field that is 32 bits wide in both classes.
+    fn word(&self, offset_32: usize, offset_64: usize) -> u32 {
+        let offset = if self.is_64() { offset_64 } else { offset_32 };
+        read_u32(self.inner, offset).unwrap()
+    }
+
+    /// A field that is 64 bits wide in 64-bit ELFs.
+    fn address(&self, offset_32: usize, offset_64: usize) -> u64 {
+        if self.is_64() {
+            read_u64(self.inner, offset_64).unwrap()
+        } else {
+            read_u32(self.inner, offset_32).unwrap().into()
+        }
+    }
+
+    /// Get the section type as a `ElfSectionType` enum variant.
+    pub fn section_type(&self) -> ElfSectionType {
+        match self.word(4, 4) {
+            0 => ElfSectionType::Unused,
+            1 => ElfSectionType::ProgramSection,
+            2 => ElfSectionType::LinkerSymbolTable,
+            3 => ElfSectionType::StringTable,
+            4 => ElfSectionType::RelaRelocation,
+            5 => ElfSectionType::SymbolHashTable,
+            6 => ElfSectionType::DynamicLinkingTable,
+            7 => ElfSectionType::Note,
+            8 => ElfSectionType::Uninitialized,
+            9 => ElfSectionType::RelRelocation,
+            10 => ElfSectionType::Reserved,
+            11 => ElfSectionType::DynamicLoaderSymbolTable,
+            0x6000_0000..=0x6FFF_FFFF => ElfSectionType::EnvironmentSpecific,
+            0x7000_0000..=0x7FFF_FFFF => ElfSectionType::ProcessorSpecific,
+            typ => ElfSectionType::Unknown(typ),
+        }
+    }
+
+    /// Get the physical start address of the section.
+    pub fn start_address(&self) -> u64 {
+        self.address(12, 16)
+    }
+
+    /// Get the physical end address of the section.
+    ///
+    /// This is the same as doing `section.start_address() + section.size()`,
+    /// saturated for malformed sections.
+    pub fn end_address(&self) -> u64 {
+        self.start_address().saturating_add(self.size())
+    }
+
+    /// Get the section's size in bytes.
+    pub fn size(&self) -> u64 {
+        self.address(20, 32)
+    }
+
+    /// Get the section's flags.
+    pub fn flags(&self) -> ElfSectionFlags {
+        ElfSectionFlags::from_bits_truncate(self.address(8, 8))
+    }
+
+    /// Index of the section this one refers to, such as the string table
+    /// of the names of a symbol table.
+    pub fn link(&self) -> u32 {
+        self.word(24, 40)
+    }
+
+    /// Size of the entries of a section that holds a table, such as a
+    /// symbol table, or 0.
+    pub fn entry_size(&self) -> u64 {
+        self.address(36, 56)
+    }
+
+    /// Check if the `ALLOCATED` flag is set in the section flags.
+    pub fn is_allocated(&self) -> bool {
+        self.flags().contains(ElfSectionFlags::ALLOCATED)
+    }
+}
+
+/// An enum abstraction over raw ELF section types.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[repr(u32)]
+pub enum ElfSectionType {
+    /// This value marks the section header as inactive; it does not have an
+    /// associated section. Other members of the section header have undefined
+    /// values.
+    Unused = 0,
+
+    /// The section holds information defined by the program, whose format and
+    /// meaning are determined solely by the program.
+    ProgramSection = 1,
+
+    /// This section holds a linker symbol table.
+    LinkerSymbolTable = 2,
+
+    /// The section holds a string table.
+    StringTable = 3,
+
+    /// The section holds relocation entries with explicit addends, such as type
+    /// Elf32_Rela for the 32-bit class of object files. An object file may have
+    /// multiple relocation sections.
+    RelaRelocation = 4,
+
+    /// The section holds a symbol hash table.
+    SymbolHashTable = 5,
+
+    /// The section holds dynamic linking tables.
+    DynamicLinkingTable = 6,
+
+    /// This section holds information that marks the file in some way.
+    Note = 7,
+
+    /// A section of this type occupies no space in the file but otherwise resembles
+    /// `ProgramSection`. Although this section contains no bytes, the
+    /// sh_offset member contains the conceptual file offset.
+    Uninitialized = 8,
+
+    /// The section holds relocation entries without explicit addends, such as type
+    /// Elf32_Rel for the 32-bit class of object files. An object file may have
+    /// multiple relocation sections.
+    RelRelocation = 9,
+
+    /// This section type is reserved but has unspecified semantics.
+    Reserved = 10,
+
+    /// This section holds a dynamic loader symbol table.
+    DynamicLoaderSymbolTable = 11,
+
+    /// Values in this inclusive range (`[0x6000_0000, 0x6FFF_FFFF)`) are
+    /// reserved for environment-specific semantics.
+    EnvironmentSpecific = 0x6000_0000,
+
+    /// Values in this inclusive range (`[0x7000_0000, 0x7FFF_FFFF)`) are
+    /// reserved for processor-specific semantics.
+    ProcessorSpecific = 0x7000_0000,
+
+    /// A value that is neither a known type nor in one of the ranges above.
+    Unknown(u32),
+}
+
+bitflags! {
+    /// ELF Section bitflags.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ElfSectionFlags: u64 {
+        /// The section contains data that should be writable during program execution.
+        const WRITABLE = 0x1;
+
+        /// The section occupies memory during the process execution.
+        const ALLOCATED = 0x2;
+
+        /// The section contains executable machine instructions.
+        const EXECUTABLE = 0x4;
+    }
+}
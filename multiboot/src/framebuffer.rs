@@ -0,0 +1,86 @@
+use super::{read_u16, read_u32, read_u64, read_u8};
+
+/// Offset of the color information in the tag.
+const COLOR_INFO: usize = 24;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferInfo<'a> {
+    /// Physical address of the framebuffer.
+    pub address: u64,
+    /// Bytes per line.
+    pub pitch: u32,
+    /// In pixels, or in characters for EGA text.
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u8,
+    pub typ: FramebufferType<'a>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FramebufferType<'a> {
+    /// Every pixel is an index in the palette.
+    Indexed { palette: &'a [FramebufferColor] },
+    /// Every pixel holds its color components.
+    Rgb {
+        red: ColorField,
+        green: ColorField,
+        blue: ColorField,
+    },
+    /// EGA text mode, like the VGA text buffer.
+    Text,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FramebufferColor {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+/// Bits of a pixel holding one color component.
+#[derive(Debug, Clone, Copy)]
+pub struct ColorField {
+    pub position: u8,
+    pub size: u8,
+}
+
+impl<'a> FramebufferInfo<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Option<FramebufferInfo<'a>> {
+        let typ = match read_u8(data, 21)? {
+            0 => {
+                // GRUB writes a 16-bit count, the specification says 32 bits
+                let count = read_u16(data, COLOR_INFO)? as usize;
+                let start = COLOR_INFO + 2;
+                let bytes = data.get(start..start + count * 3)?;
+                // `FramebufferColor` is made of bytes, so it has no alignment
+                let palette =
+                    unsafe { core::slice::from_raw_parts(bytes.as_ptr() as *const _, count) };
+                FramebufferType::Indexed { palette }
+            }
+            1 => {
+                let field = |index: usize| {
+                    Some(ColorField {
+                        position: read_u8(data, COLOR_INFO + 2 * index)?,
+                        size: read_u8(data, COLOR_INFO + 2 * index + 1)?,
+                    })
+                };
+                FramebufferType::Rgb {
+                    red: field(0)?,
+                    green: field(1)?,
+                    blue: field(2)?,
+                }
+            }
+            2 => FramebufferType::Text,
+            _ => return None,
+        };
+        Some(FramebufferInfo {
+            address: read_u64(data, 0)?,
+            pitch: read_u32(data, 8)?,
+            width: read_u32(data, 12)?,
+            height: read_u32(data, 16)?,
+            bits_per_pixel: read_u8(data, 20)?,
+            typ,
+        })
+    }
+}
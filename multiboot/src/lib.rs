@@ -0,0 +1,321 @@
+//! Parser of the multiboot2 information structure.
+//!
+//! Every tag is read out of the byte slice of the structure with bounds
+//! checks, so a malformed structure ends the iteration instead of reading
+//! out of it. Nothing depends on the kernel, so that the parser can be tested
+//! and fuzzed on the host.
+//!
+//! The information given by a multiboot1 bootloader is converted to a
+//! multiboot2 structure by `v1::convert`, so that both are read the same way.
+
+#![no_std]
+
+#[macro_use]
+extern crate bitflags;
+
+pub mod elf_sections;
+pub mod framebuffer;
+pub mod memory_map;
+pub mod rsdp;
+pub mod v1;
+mod writer;
+
+pub use self::elf_sections::ElfSectionFlags;
+pub use self::elf_sections::{ElfSection, ElfSectionIter, ElfSectionType, ElfSectionsTag};
+pub use self::framebuffer::{FramebufferInfo, FramebufferType};
+pub use self::memory_map::{
+    AllMemoryAreaIter, MemoryArea, MemoryAreaIter, MemoryAreaType, MemoryMapTag,
+};
+pub use self::rsdp::Rsdp;
+use self::tag_type::*;
+pub use self::writer::InfoWriter;
+use core::{slice, str};
+
+/// Given in EAX by a multiboot2 bootloader.
+pub const MAGIC: u32 = 0x36d7_6289;
+
+/// Type of every tag that the parser reads.
+pub mod tag_type {
+    pub const END: u32 = 0;
+    pub const COMMAND_LINE: u32 = 1;
+    pub const BOOT_LOADER_NAME: u32 = 2;
+    pub const MODULE: u32 = 3;
+    pub const BASIC_MEMORY_INFO: u32 = 4;
+    pub const BOOT_DEVICE: u32 = 5;
+    pub const MEMORY_MAP: u32 = 6;
+    pub const FRAMEBUFFER: u32 = 8;
+    pub const ELF_SECTIONS: u32 = 9;
+    pub const APM: u32 = 10;
+    pub const ACPI_OLD_RSDP: u32 = 14;
+    pub const ACPI_NEW_RSDP: u32 = 15;
+    pub const LOAD_BASE_ADDRESS: u32 = 21;
+}
+
+/// Type and size of a tag.
+const TAG_HEADER_SIZE: usize = 8;
+const TAG_ALIGN: usize = 8;
+
+fn read<const N: usize>(bytes: &[u8], offset: usize) -> Option<[u8; N]> {
+    bytes.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+fn read_u8(bytes: &[u8], offset: usize) -> Option<u8> {
+    bytes.get(offset).copied()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    read(bytes, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    read(bytes, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    read(bytes, offset).map(u64::from_le_bytes)
+}
+
+/// A null terminated string, empty if it is not valid UTF-8.
+fn read_str(bytes: &[u8]) -> &str {
+    let len = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    str::from_utf8(&bytes[..len]).unwrap_or("")
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tag<'a> {
+    pub typ: u32,
+    /// What follows the type and size of the tag.
+    data: &'a [u8],
+}
+
+/// Iterates over the tags until the end tag, or until a tag that doesn't fit
+/// in the structure.
+#[derive(Debug, Clone)]
+pub struct TagIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> TagIter<'a> {
+    fn parse(&self) -> Option<Tag<'a>> {
+        let typ = read_u32(self.bytes, self.offset)?;
+        let size = read_u32(self.bytes, self.offset + 4)? as usize;
+        if typ == END || size < TAG_HEADER_SIZE {
+            return None;
+        }
+        let data = self
+            .bytes
+            .get(self.offset + TAG_HEADER_SIZE..self.offset.checked_add(size)?)?;
+        Some(Tag { typ, data })
+    }
+}
+
+impl<'a> Iterator for TagIter<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Tag<'a>> {
+        match self.parse() {
+            Some(tag) => {
+                let end = self.offset + TAG_HEADER_SIZE + tag.data.len();
+                // tags are 8 byte aligned
+                self.offset = end.next_multiple_of(TAG_ALIGN);
+                Some(tag)
+            }
+            None => {
+                self.offset = self.bytes.len();
+                None
+            }
+        }
+    }
+}
+
+/// A boot module loaded by the bootloader, such as an initrd.
+#[derive(Debug, Clone, Copy)]
+pub struct ModuleTag<'a> {
+    /// Physical address of the first byte of the module.
+    pub start: u32,
+    /// Physical address right after the last byte of the module.
+    pub end: u32,
+    pub command_line: &'a str,
+}
+
+/// Amount of lower and upper memory, in KiB.
+#[derive(Debug, Clone, Copy)]
+pub struct BasicMemoryInfo {
+    /// Starting at address 0, at most 640 KiB.
+    pub lower: u32,
+    /// Starting at 1 MiB, up to the first memory hole.
+    pub upper: u32,
+}
+
+/// The BIOS disk the kernel was loaded from.
+#[derive(Debug, Clone, Copy)]
+pub struct BootDevice {
+    pub bios_device: u32,
+    /// `0xffff_ffff` if the kernel is not in a partition.
+    pub partition: u32,
+    pub sub_partition: u32,
+}
+
+/// APM table of the BIOS, for the protected mode interface.
+#[derive(Debug, Clone, Copy)]
+pub struct ApmTable {
+    pub version: u16,
+    pub code_segment: u16,
+    pub offset: u32,
+    pub code_segment_16: u16,
+    pub data_segment: u16,
+    pub flags: u16,
+    pub code_segment_length: u16,
+    pub code_segment_16_length: u16,
+    pub data_segment_length: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BootInformation<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> BootInformation<'a> {
+    /// Checks that `bytes` start with a multiboot information structure
+    /// ending with an end tag.
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<BootInformation<'a>> {
+        let total_size = read_u32(bytes, 0)? as usize;
+        let bytes = bytes.get(..total_size)?;
+        let end_tag = total_size.checked_sub(TAG_HEADER_SIZE)?;
+        if end_tag < TAG_HEADER_SIZE
+            || read_u32(bytes, end_tag)? != END
+            || read_u32(bytes, end_tag + 4)? != TAG_HEADER_SIZE as u32
+        {
+            return None;
+        }
+        Some(BootInformation { bytes })
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn start_address(&self) -> usize {
+        self.bytes.as_ptr() as usize
+    }
+
+    pub fn end_address(&self) -> usize {
+        self.start_address() + self.total_size()
+    }
+
+    pub fn tags(&self) -> TagIter<'a> {
+        TagIter {
+            bytes: self.bytes,
+            // after the total size and a reserved field
+            offset: 8,
+        }
+    }
+
+    fn get_tag(&self, typ: u32) -> Option<Tag<'a>> {
+        self.tags().find(|tag| tag.typ == typ)
+    }
+
+    /// The command line given to the kernel by the bootloader.
+    pub fn command_line(&self) -> Option<&'a str> {
+        self.get_tag(COMMAND_LINE).map(|tag| read_str(tag.data))
+    }
+
+    pub fn boot_loader_name(&self) -> Option<&'a str> {
+        self.get_tag(BOOT_LOADER_NAME).map(|tag| read_str(tag.data))
+    }
+
+    pub fn module_tags(&self) -> impl Iterator<Item = ModuleTag<'a>> {
+        self.tags()
+            .filter(|tag| tag.typ == MODULE)
+            .filter_map(|tag| {
+                Some(ModuleTag {
+                    start: read_u32(tag.data, 0)?,
+                    end: read_u32(tag.data, 4)?,
+                    command_line: read_str(tag.data.get(8..)?),
+                })
+            })
+    }
+
+    pub fn basic_memory_info(&self) -> Option<BasicMemoryInfo> {
+        let tag = self.get_tag(BASIC_MEMORY_INFO)?;
+        Some(BasicMemoryInfo {
+            lower: read_u32(tag.data, 0)?,
+            upper: read_u32(tag.data, 4)?,
+        })
+    }
+
+    pub fn boot_device(&self) -> Option<BootDevice> {
+        let tag = self.get_tag(BOOT_DEVICE)?;
+        Some(BootDevice {
+            bios_device: read_u32(tag.data, 0)?,
+            partition: read_u32(tag.data, 4)?,
+            sub_partition: read_u32(tag.data, 8)?,
+        })
+    }
+
+    pub fn memory_map_tag(&self) -> Option<MemoryMapTag<'a>> {
+        self.get_tag(MEMORY_MAP)
+            .and_then(|tag| MemoryMapTag::new(tag.data))
+    }
+
+    pub fn framebuffer_info(&self) -> Option<FramebufferInfo<'a>> {
+        self.get_tag(FRAMEBUFFER)
+            .and_then(|tag| FramebufferInfo::new(tag.data))
+    }
+
+    pub fn elf_sections_tag(&self) -> Option<ElfSectionsTag<'a>> {
+        self.get_tag(ELF_SECTIONS)
+            .and_then(|tag| ElfSectionsTag::new(tag.data))
+    }
+
+    pub fn apm_table(&self) -> Option<ApmTable> {
+        let tag = self.get_tag(APM)?;
+        Some(ApmTable {
+            version: read_u16(tag.data, 0)?,
+            code_segment: read_u16(tag.data, 2)?,
+            offset: read_u32(tag.data, 4)?,
+            code_segment_16: read_u16(tag.data, 8)?,
+            data_segment: read_u16(tag.data, 10)?,
+            flags: read_u16(tag.data, 12)?,
+            code_segment_length: read_u16(tag.data, 14)?,
+            code_segment_16_length: read_u16(tag.data, 16)?,
+            data_segment_length: read_u16(tag.data, 18)?,
+        })
+    }
+
+    /// Copy of the ACPI 1.0 RSDP.
+    pub fn acpi_old_rsdp(&self) -> Option<Rsdp<'a>> {
+        self.get_tag(ACPI_OLD_RSDP)
+            .and_then(|tag| Rsdp::new(tag.data))
+    }
+
+    /// Copy of the ACPI 2.0 or later RSDP, which has the address of the XSDT.
+    pub fn acpi_new_rsdp(&self) -> Option<Rsdp<'a>> {
+        self.get_tag(ACPI_NEW_RSDP)
+            .and_then(|tag| Rsdp::new(tag.data))
+    }
+
+    /// Physical address the kernel image was loaded at.
+    pub fn load_base_address(&self) -> Option<u32> {
+        self.get_tag(LOAD_BASE_ADDRESS)
+            .and_then(|tag| read_u32(tag.data, 0))
+    }
+}
+
+/// Reads the multiboot information structure at `address`.
+///
+/// Panics if the structure doesn't end with an end tag.
+///
+/// # Safety
+///
+/// `address` must point to the structure given by the bootloader, which must
+/// stay mapped and unmodified.
+pub unsafe fn load(address: usize) -> BootInformation<'static> {
+    let total_size = *(address as *const u32) as usize;
+    let bytes = slice::from_raw_parts(address as *const u8, total_size);
+    BootInformation::from_bytes(bytes).expect("invalid multiboot information structure")
+}
@@ -0,0 +1,128 @@
+use super::{read_u32, read_u64};
+
+/// Size of an entry as defined by the specification, later versions may add
+/// fields at the end.
+const MIN_ENTRY_SIZE: usize = 24;
+
+/// What an area of physical memory can be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAreaType {
+    /// RAM that is free to use.
+    Available,
+    /// Anything else, such as ROMs or memory mapped devices.
+    Reserved,
+    /// RAM holding the ACPI tables, which can be used once they are read.
+    AcpiReclaimable,
+    /// Memory that must be preserved across hibernation.
+    AcpiNvs,
+    /// Defective RAM.
+    BadMemory,
+}
+
+impl From<u32> for MemoryAreaType {
+    fn from(typ: u32) -> MemoryAreaType {
+        match typ {
+            1 => MemoryAreaType::Available,
+            3 => MemoryAreaType::AcpiReclaimable,
+            4 => MemoryAreaType::AcpiNvs,
+            5 => MemoryAreaType::BadMemory,
+            // every other value means reserved
+            _ => MemoryAreaType::Reserved,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryArea {
+    pub base_addr: u64,
+    pub length: u64,
+    typ: u32,
+}
+
+impl MemoryArea {
+    pub fn typ(&self) -> MemoryAreaType {
+        MemoryAreaType::from(self.typ)
+    }
+
+    /// Address right after the last byte of the area.
+    pub fn end_address(&self) -> u64 {
+        self.base_addr.saturating_add(self.length)
+    }
+}
+
+/// Iterates over every area of the memory map, whatever its type.
+#[derive(Debug, Clone)]
+pub struct AllMemoryAreaIter<'a> {
+    entries: &'a [u8],
+    entry_size: usize,
+    offset: usize,
+}
+
+impl Iterator for AllMemoryAreaIter<'_> {
+    type Item = MemoryArea;
+
+    fn next(&mut self) -> Option<MemoryArea> {
+        let entry = self
+            .entries
+            .get(self.offset..self.offset.checked_add(self.entry_size)?)?;
+        self.offset += self.entry_size;
+        Some(MemoryArea {
+            base_addr: read_u64(entry, 0)?,
+            length: read_u64(entry, 8)?,
+            typ: read_u32(entry, 16)?,
+        })
+    }
+}
+
+/// Iterates over the areas of available RAM.
+#[derive(Debug, Clone)]
+pub struct MemoryAreaIter<'a> {
+    areas: AllMemoryAreaIter<'a>,
+}
+
+impl Iterator for MemoryAreaIter<'_> {
+    type Item = MemoryArea;
+
+    fn next(&mut self) -> Option<MemoryArea> {
+        self.areas
+            .find(|area| area.typ() == MemoryAreaType::Available)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryMapTag<'a> {
+    entry_size: u32,
+    entries: &'a [u8],
+}
+
+impl<'a> MemoryMapTag<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Option<MemoryMapTag<'a>> {
+        Some(MemoryMapTag {
+            entry_size: read_u32(data, 0)?,
+            // followed by the version of the entries, always 0
+            entries: data.get(8..)?,
+        })
+    }
+
+    /// The areas of available RAM.
+    pub fn memory_areas(&self) -> MemoryAreaIter<'a> {
+        MemoryAreaIter {
+            areas: self.all_memory_areas(),
+        }
+    }
+
+    /// Every area, including the reserved ones.
+    pub fn all_memory_areas(&self) -> AllMemoryAreaIter<'a> {
+        let entry_size = self.entry_size as usize;
+        AllMemoryAreaIter {
+            // no area can be read from entries that are too small
+            entries: if entry_size < MIN_ENTRY_SIZE {
+                &[]
+            } else {
+                self.entries
+            },
+            entry_size,
+            offset: 0,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Copies of the ACPI Root System Description Pointer given by the bootloader.
+
+use super::{read_u32, read_u64, read_u8};
+use core::str;
+
+const SIGNATURE: &[u8] = b"RSD PTR ";
+
+/// Size of the RSDP of ACPI 1.0, covered by `checksum`.
+const V1_SIZE: usize = 20;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Rsdp<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Option<Rsdp<'a>> {
+        if data.len() < V1_SIZE {
+            return None;
+        }
+        Some(Rsdp { bytes: data })
+    }
+
+    pub fn oem_id(&self) -> &'a str {
+        str::from_utf8(&self.bytes[9..15]).unwrap_or("")
+    }
+
+    /// 0 for ACPI 1.0, 2 for ACPI 2.0 and later.
+    pub fn revision(&self) -> u8 {
+        read_u8(self.bytes, 15).unwrap()
+    }
+
+    /// Physical address of the RSDT.
+    pub fn rsdt_address(&self) -> u32 {
+        read_u32(self.bytes, 16).unwrap()
+    }
+
+    /// Physical address of the XSDT, only from ACPI 2.0.
+    pub fn xsdt_address(&self) -> Option<u64> {
+        if self.revision() < 2 {
+            return None;
+        }
+        read_u64(self.bytes, 24)
+    }
+
+    /// Whether the signature is right and the bytes covered by the checksums add up to 0.
+    pub fn checksum_is_valid(&self) -> bool {
+        let sum = |bytes: &[u8]| bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        let length = match read_u32(self.bytes, 20) {
+            Some(length) if self.revision() >= 2 => length as usize,
+            _ => V1_SIZE,
+        };
+        self.bytes.starts_with(SIGNATURE)
+            && sum(&self.bytes[..V1_SIZE]) == 0
+            && self
+                .bytes
+                .get(..length)
+                .is_some_and(|bytes| sum(bytes) == 0)
+    }
+}
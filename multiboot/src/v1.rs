@@ -0,0 +1,206 @@
+//! Conversion of the information structure of a multiboot1 bootloader, such
+//! as the `-kernel` loader of QEMU, to a multiboot2 structure.
+//!
+//! The multiboot1 structure is a fixed set of fields, each valid if its bit
+//! is set in `flags`, and most of them point to other places of physical
+//! memory. Everything is copied into the tags, so nothing of the original
+//! structure is needed after `convert`.
+
+use super::tag_type::*;
+use super::{read_str, read_u16, read_u32, read_u8, InfoWriter};
+
+/// Given in EAX by a multiboot1 bootloader.
+pub const MAGIC: u32 = 0x2bad_b002;
+
+// bits of `flags`
+const MEMORY: u32 = 1 << 0;
+const BOOT_DEVICE_INFO: u32 = 1 << 1;
+const CMDLINE: u32 = 1 << 2;
+const MODULES: u32 = 1 << 3;
+const ELF_SHDR: u32 = 1 << 5;
+const MEMORY_MAP_INFO: u32 = 1 << 6;
+const LOADER_NAME: u32 = 1 << 9;
+const APM_TABLE: u32 = 1 << 10;
+const FRAMEBUFFER_INFO: u32 = 1 << 12;
+
+/// Size of the structure up to the end of the framebuffer fields.
+const INFO_SIZE: usize = 116;
+/// `flags` and the fields that are always present in version 0.6.96.
+const MIN_INFO_SIZE: usize = 88;
+const MODULE_SIZE: usize = 16;
+const APM_TABLE_SIZE: usize = 20;
+/// Size of a memory map entry of multiboot2, which is always used.
+const MEMORY_AREA_SIZE: u32 = 24;
+/// Memory areas past this number are left out.
+const MAX_MEMORY_AREAS: usize = 64;
+/// Longest string copied out of the multiboot1 structure.
+const MAX_STRING: usize = 4096;
+
+/// Appends to `writer` a tag for every field of the multiboot1 structure at
+/// physical address `address`.
+///
+/// `memory(address, len)` gives the bytes of physical memory at `address`, or
+/// `None` if they can't be reached. The fields pointing to memory that can't
+/// be reached, or that don't fit in `writer`, are left out. Returns `None` if
+/// the structure itself can't be read.
+///
+/// Multiboot1 bootloaders put the path of the kernel and of the modules at
+/// the start of their command lines, which is removed to get the same command
+/// lines as with multiboot2.
+pub fn convert<'m, M>(address: u32, memory: M, writer: &mut InfoWriter) -> Option<()>
+where
+    M: Fn(u32, usize) -> Option<&'m [u8]>,
+{
+    let info = memory(address, INFO_SIZE).or_else(|| memory(address, MIN_INFO_SIZE))?;
+    let flags = read_u32(info, 0)?;
+    let field = |offset| read_u32(info, offset);
+    let string = |address: u32| {
+        let bytes = memory(address, MAX_STRING).unwrap_or_else(|| {
+            // the string may end right before memory that can't be reached
+            let len = (0..MAX_STRING as u32)
+                .map_while(|i| memory(address.checked_add(i)?, 1))
+                .take_while(|byte| byte[0] != 0)
+                .count();
+            memory(address, len).unwrap_or(&[])
+        });
+        read_str(bytes)
+    };
+
+    if flags & CMDLINE != 0 {
+        let command_line = without_path(string(field(16)?));
+        writer.tag(COMMAND_LINE, &[command_line.as_bytes(), &[0]]);
+    }
+    if flags & LOADER_NAME != 0 {
+        let name = string(field(64)?);
+        writer.tag(BOOT_LOADER_NAME, &[name.as_bytes(), &[0]]);
+    }
+    if flags & MODULES != 0 {
+        let (count, modules) = (field(20)? as usize, field(24)?);
+        let table = count
+            .checked_mul(MODULE_SIZE)
+            .and_then(|len| memory(modules, len))
+            .unwrap_or(&[]);
+        for module in table.chunks_exact(MODULE_SIZE) {
+            let command_line = without_path(string(read_u32(module, 8)?));
+            writer.tag(MODULE, &[&module[..8], command_line.as_bytes(), &[0]]);
+        }
+    }
+    if flags & MEMORY != 0 {
+        writer.tag(BASIC_MEMORY_INFO, &[&info[4..12]]);
+    }
+    if flags & BOOT_DEVICE_INFO != 0 {
+        // drive, then the partitions from the top level, 0xff if unused
+        let [_, sub_partition, partition, drive] = field(12)?.to_le_bytes();
+        let widen = |byte: u8| if byte == 0xff { u32::MAX } else { byte.into() };
+        writer.tag(
+            BOOT_DEVICE,
+            &[
+                &u32::from(drive).to_le_bytes(),
+                &widen(partition).to_le_bytes(),
+                &widen(sub_partition).to_le_bytes(),
+            ],
+        );
+    }
+    if flags & MEMORY_MAP_INFO != 0 {
+        convert_memory_map(&memory, field(44)? as usize, field(48)?, writer);
+    }
+    if flags & ELF_SHDR != 0 {
+        let (count, entry_size, sections) = (field(28)?, field(32)?, field(36)?);
+        let len = (count as usize).checked_mul(entry_size as usize);
+        if let Some(headers) = len.and_then(|len| memory(sections, len)) {
+            // number and size of the entries, then the index of the string table
+            writer.tag(ELF_SECTIONS, &[&info[28..36], &info[40..44], headers]);
+        }
+    }
+    if flags & APM_TABLE != 0 {
+        if let Some(table) = memory(field(68)?, APM_TABLE_SIZE) {
+            writer.tag(APM, &[table]);
+        }
+    }
+    if flags & FRAMEBUFFER_INFO != 0 && info.len() >= INFO_SIZE {
+        convert_framebuffer(&memory, info, writer);
+    }
+    Some(())
+}
+
+/// The arguments of a command line starting with a path.
+fn without_path(command_line: &str) -> &str {
+    command_line
+        .trim_start()
+        .split_once(' ')
+        .map_or("", |(_, arguments)| arguments.trim_start())
+}
+
+/// Entries of the multiboot1 memory map start with their size, which doesn't
+/// count the size field itself.
+fn convert_memory_map<'m, M>(memory: &M, length: usize, address: u32, writer: &mut InfoWriter)
+where
+    M: Fn(u32, usize) -> Option<&'m [u8]>,
+{
+    let Some(entries) = memory(address, length) else {
+        return;
+    };
+    let mut converted = [0u8; MEMORY_AREA_SIZE as usize * MAX_MEMORY_AREAS];
+    let mut count = 0;
+    let mut offset = Some(0);
+    while let Some(entry) = offset {
+        let (Some(size), Some(area)) = (
+            read_u32(entries, entry),
+            entry
+                .checked_add(24)
+                .and_then(|end| entries.get(entry + 4..end)),
+        ) else {
+            break;
+        };
+        if count == MAX_MEMORY_AREAS {
+            break;
+        }
+        // base, length and type are at the same offsets in both versions
+        let start = count * MEMORY_AREA_SIZE as usize;
+        converted[start..start + 20].copy_from_slice(area);
+        count += 1;
+        offset = entry
+            .checked_add(4)
+            .and_then(|next| next.checked_add(size as usize));
+    }
+    writer.tag(
+        MEMORY_MAP,
+        &[
+            &MEMORY_AREA_SIZE.to_le_bytes(),
+            &0u32.to_le_bytes(),
+            &converted[..count * MEMORY_AREA_SIZE as usize],
+        ],
+    );
+}
+
+fn convert_framebuffer<'m, M>(memory: &M, info: &[u8], writer: &mut InfoWriter)
+where
+    M: Fn(u32, usize) -> Option<&'m [u8]>,
+{
+    // address, pitch, width, height, bits per pixel and type, then the
+    // reserved field of multiboot2
+    let common = &info[88..110];
+    let reserved = [0u8; 2];
+    let color_info = &info[110..116];
+    match read_u8(info, 109) {
+        Some(0) => {
+            // the palette is pointed to instead of being in the structure
+            let (Some(palette), Some(count)) = (read_u32(color_info, 0), read_u16(color_info, 4))
+            else {
+                return;
+            };
+            if let Some(colors) = memory(palette, count as usize * 3) {
+                writer.tag(
+                    FRAMEBUFFER,
+                    &[common, &reserved, &count.to_le_bytes(), colors],
+                );
+            }
+        }
+        Some(1) => {
+            writer.tag(FRAMEBUFFER, &[common, &reserved, color_info]);
+        }
+        _ => {
+            writer.tag(FRAMEBUFFER, &[common, &reserved]);
+        }
+    }
+}
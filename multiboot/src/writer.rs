@@ -0,0 +1,72 @@
+use super::tag_type::END;
+use super::{BootInformation, TagIter, TAG_ALIGN, TAG_HEADER_SIZE};
+
+/// Builds a multiboot2 information structure in a buffer, one tag at a time.
+pub struct InfoWriter<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> InfoWriter<'b> {
+    pub fn new(buffer: &'b mut [u8]) -> InfoWriter<'b> {
+        InfoWriter {
+            buffer,
+            // the total size and the reserved field, written by `finish`
+            len: 8,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> bool {
+        match self.buffer.get_mut(self.len..self.len + bytes.len()) {
+            Some(destination) => {
+                destination.copy_from_slice(bytes);
+                self.len += bytes.len();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Appends a tag made of the concatenation of `parts`. Returns `false`
+    /// and leaves the structure unchanged if the tag doesn't fit, along with
+    /// the end tag.
+    pub fn tag(&mut self, typ: u32, parts: &[&[u8]]) -> bool {
+        let size = TAG_HEADER_SIZE + parts.iter().map(|part| part.len()).sum::<usize>();
+        let end = (self.len + size).next_multiple_of(TAG_ALIGN);
+        if end + TAG_HEADER_SIZE > self.buffer.len() || u32::try_from(size).is_err() {
+            return false;
+        }
+        self.write(&typ.to_le_bytes());
+        self.write(&(size as u32).to_le_bytes());
+        for part in parts {
+            self.write(part);
+        }
+        self.buffer[self.len..end].fill(0);
+        self.len = end;
+        true
+    }
+
+    /// Whether a tag of type `typ` was appended.
+    pub fn contains(&self, typ: u32) -> bool {
+        let mut tags = TagIter {
+            bytes: self.buffer.get(..self.len).unwrap_or(&[]),
+            offset: 8,
+        };
+        tags.any(|tag| tag.typ == typ)
+    }
+
+    /// Appends the end tag. Returns `None` if the buffer can't even hold the
+    /// header and the end tag.
+    pub fn finish(mut self) -> Option<BootInformation<'b>> {
+        if self.len + TAG_HEADER_SIZE > self.buffer.len() {
+            return None;
+        }
+        self.write(&END.to_le_bytes());
+        self.write(&(TAG_HEADER_SIZE as u32).to_le_bytes());
+        let total_size = self.len as u32;
+        self.buffer[..4].copy_from_slice(&total_size.to_le_bytes());
+        self.buffer[4..8].fill(0);
+        let InfoWriter { buffer, len } = self;
+        BootInformation::from_bytes(&buffer[..len])
+    }
+}
@@ -0,0 +1,258 @@
+//! Builds synthetic multiboot information structures, and reads every field
+//! of a structure the way the kernel would.
+
+#![allow(dead_code)] // every test file uses a part of it
+
+use multiboot::{BootInformation, FramebufferType};
+use std::hint::black_box;
+
+pub const COMMAND_LINE: u32 = 1;
+pub const BOOT_LOADER_NAME: u32 = 2;
+pub const MODULE: u32 = 3;
+pub const BASIC_MEMORY_INFO: u32 = 4;
+pub const BOOT_DEVICE: u32 = 5;
+pub const MEMORY_MAP: u32 = 6;
+pub const FRAMEBUFFER: u32 = 8;
+pub const ELF_SECTIONS: u32 = 9;
+pub const APM: u32 = 10;
+pub const ACPI_OLD_RSDP: u32 = 14;
+pub const ACPI_NEW_RSDP: u32 = 15;
+pub const LOAD_BASE_ADDRESS: u32 = 21;
+
+/// Little-endian bytes of a list of fields.
+#[derive(Default)]
+pub struct Fields(pub Vec<u8>);
+
+impl Fields {
+    pub fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+
+    pub fn u16(mut self, value: u16) -> Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    pub fn u32(mut self, value: u32) -> Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    pub fn u64(mut self, value: u64) -> Self {
+        self.0.extend(value.to_le_bytes());
+        self
+    }
+
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.0.extend(bytes);
+        self
+    }
+}
+
+pub struct Builder {
+    bytes: Vec<u8>,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        // total size and reserved field, the size is written by `build`
+        Builder { bytes: vec![0; 8] }
+    }
+
+    /// Appends a tag followed by the padding to the next tag.
+    pub fn tag(mut self, typ: u32, data: &[u8]) -> Builder {
+        self.bytes.extend(typ.to_le_bytes());
+        self.bytes.extend((8 + data.len() as u32).to_le_bytes());
+        self.bytes.extend(data);
+        self.bytes.resize(self.bytes.len().next_multiple_of(8), 0);
+        self
+    }
+
+    /// Appends raw bytes, to build malformed tags.
+    pub fn raw(mut self, bytes: &[u8]) -> Builder {
+        self.bytes.extend(bytes);
+        self
+    }
+
+    /// Appends the end tag and writes the total size.
+    pub fn build(self) -> Vec<u8> {
+        self.tag(0, &[]).build_without_end_tag()
+    }
+
+    pub fn build_without_end_tag(mut self) -> Vec<u8> {
+        let total_size = self.bytes.len() as u32;
+        self.bytes[..4].copy_from_slice(&total_size.to_le_bytes());
+        self.bytes
+    }
+}
+
+/// A 32-bit ELF section header.
+pub fn elf_section_32(typ: u32, flags: u32, address: u32, size: u32) -> Vec<u8> {
+    Fields::default()
+        .u32(0) // name
+        .u32(typ)
+        .u32(flags)
+        .u32(address)
+        .u32(0) // offset
+        .u32(size)
+        .u32(0) // link
+        .u32(0) // info
+        .u32(4) // address alignment
+        .u32(0) // entry size
+        .0
+}
+
+/// A 64-bit ELF section header.
+pub fn elf_section_64(typ: u32, flags: u64, address: u64, size: u64) -> Vec<u8> {
+    Fields::default()
+        .u32(0) // name
+        .u32(typ)
+        .u64(flags)
+        .u64(address)
+        .u64(0) // offset
+        .u64(size)
+        .u32(0) // link
+        .u32(0) // info
+        .u64(8) // address alignment
+        .u64(0) // entry size
+        .0
+}
+
+/// A memory map entry.
+pub fn memory_area(base: u64, length: u64, typ: u32) -> Vec<u8> {
+    Fields::default().u64(base).u64(length).u32(typ).u32(0).0
+}
+
+/// A structure with one tag of every kind the parser knows.
+pub fn every_tag() -> Vec<u8> {
+    let memory_map = Fields::default()
+        .u32(24)
+        .u32(0)
+        .bytes(&memory_area(0, 0x9fc00, 1))
+        .bytes(&memory_area(0x9fc00, 0x400, 2))
+        .bytes(&memory_area(0x100000, 0x7ee0000, 1));
+    let elf_sections = Fields::default()
+        .u32(3)
+        .u32(40)
+        .u32(2)
+        .bytes(&elf_section_32(0, 0, 0, 0))
+        .bytes(&elf_section_32(1, 0x6, 0x100000, 0x2000))
+        .bytes(&elf_section_32(3, 0, 0, 0x100));
+    let rsdp = Fields::default()
+        .bytes(b"RSD PTR ")
+        .u8(0) // checksum, fixed below
+        .bytes(b"BOCHS ")
+        .u8(0)
+        .u32(0x7fe14d2);
+    let mut rsdp = rsdp.0;
+    rsdp[8] = rsdp.iter().fold(0u8, |sum, &b| sum.wrapping_sub(b));
+
+    Builder::new()
+        .tag(COMMAND_LINE, b"pae verbose\0")
+        .tag(BOOT_LOADER_NAME, b"GRUB 2.06\0")
+        .tag(
+            MODULE,
+            &Fields::default()
+                .u32(0x200000)
+                .u32(0x201000)
+                .bytes(b"initrd\0")
+                .0,
+        )
+        .tag(BASIC_MEMORY_INFO, &Fields::default().u32(639).u32(129920).0)
+        .tag(
+            BOOT_DEVICE,
+            &Fields::default()
+                .u32(0xe0)
+                .u32(0xffff_ffff)
+                .u32(0xffff_ffff)
+                .0,
+        )
+        .tag(MEMORY_MAP, &memory_map.0)
+        .tag(
+            FRAMEBUFFER,
+            &Fields::default()
+                .u64(0xb8000)
+                .u32(160)
+                .u32(80)
+                .u32(25)
+                .u8(16)
+                .u8(2)
+                .u16(0)
+                .0,
+        )
+        .tag(ELF_SECTIONS, &elf_sections.0)
+        .tag(
+            APM,
+            &Fields::default()
+                .u16(0x102)
+                .u16(0xf000)
+                .u32(0x9a4)
+                .u16(0xf000)
+                .u16(0x40)
+                .u16(3)
+                .u16(0xffff)
+                .u16(0xffff)
+                .u16(0xffff)
+                .0,
+        )
+        .tag(ACPI_OLD_RSDP, &rsdp)
+        .tag(LOAD_BASE_ADDRESS, &Fields::default().u32(0x100000).0)
+        .build()
+}
+
+/// Reads everything the parser can give, which must never panic.
+pub fn exercise(boot_info: &BootInformation) {
+    black_box((boot_info.total_size(), boot_info.end_address()));
+    for tag in boot_info.tags() {
+        black_box(tag.typ);
+    }
+    black_box(boot_info.command_line());
+    black_box(boot_info.boot_loader_name());
+    for module in boot_info.module_tags() {
+        black_box(module);
+    }
+    black_box(boot_info.basic_memory_info());
+    black_box(boot_info.boot_device());
+    if let Some(memory_map) = boot_info.memory_map_tag() {
+        for area in memory_map.all_memory_areas() {
+            black_box((area, area.typ(), area.end_address()));
+        }
+        for area in memory_map.memory_areas() {
+            black_box(area);
+        }
+    }
+    if let Some(framebuffer) = boot_info.framebuffer_info() {
+        black_box(framebuffer);
+        if let FramebufferType::Indexed { palette } = framebuffer.typ {
+            for color in palette {
+                black_box(color);
+            }
+        }
+    }
+    if let Some(elf_sections) = boot_info.elf_sections_tag() {
+        for section in elf_sections.sections() {
+            black_box((
+                section.section_type(),
+                section.flags(),
+                section.is_allocated(),
+            ));
+            black_box((
+                section.start_address(),
+                section.end_address(),
+                section.size(),
+            ));
+            black_box((section.link(), section.entry_size()));
+            black_box(elf_sections.section(section.link()));
+        }
+    }
+    black_box(boot_info.apm_table());
+    for rsdp in [boot_info.acpi_old_rsdp(), boot_info.acpi_new_rsdp()]
+        .into_iter()
+        .flatten()
+    {
+        black_box((rsdp.oem_id(), rsdp.revision(), rsdp.checksum_is_valid()));
+        black_box((rsdp.rsdt_address(), rsdp.xsdt_address()));
+    }
+    black_box(boot_info.load_base_address());
+}
@@ -0,0 +1,192 @@
+//! Structures that are broken in every way the bootloader or a corrupted
+//! memory could break them. None of them may panic or be read out of bounds.
+
+mod common;
+
+use common::*;
+use multiboot::BootInformation;
+
+fn set_total_size(bytes: &mut [u8], total_size: u32) {
+    bytes[..4].copy_from_slice(&total_size.to_le_bytes());
+}
+
+#[test]
+fn too_short_for_a_header() {
+    for len in 0..16 {
+        let bytes = vec![0; len];
+        assert!(
+            BootInformation::from_bytes(&bytes).is_none(),
+            "{} bytes",
+            len
+        );
+    }
+}
+
+#[test]
+fn total_size_beyond_the_buffer() {
+    let mut bytes = every_tag();
+    let len = bytes.len() as u32;
+    set_total_size(&mut bytes, len + 8);
+    assert!(BootInformation::from_bytes(&bytes).is_none());
+    set_total_size(&mut bytes, u32::MAX);
+    assert!(BootInformation::from_bytes(&bytes).is_none());
+}
+
+#[test]
+fn total_size_not_at_the_end_tag() {
+    let mut bytes = every_tag();
+    let len = bytes.len() as u32;
+    for total_size in [0, 4, 8, 12, len - 4, len - 1] {
+        set_total_size(&mut bytes, total_size);
+        assert!(
+            BootInformation::from_bytes(&bytes).is_none(),
+            "total size {}",
+            total_size
+        );
+    }
+}
+
+#[test]
+fn trailing_bytes_are_ignored() {
+    let mut bytes = every_tag();
+    let len = bytes.len();
+    bytes.extend([0xff; 64]);
+    let boot_info = BootInformation::from_bytes(&bytes).unwrap();
+    assert_eq!(boot_info.total_size(), len);
+}
+
+#[test]
+fn missing_end_tag() {
+    let bytes = Builder::new()
+        .tag(COMMAND_LINE, b"no end\0")
+        .build_without_end_tag();
+    assert!(BootInformation::from_bytes(&bytes).is_none());
+
+    // the end tag with a wrong size
+    let bytes = Builder::new()
+        .tag(COMMAND_LINE, b"no end\0")
+        .raw(&Fields::default().u32(0).u32(16).u64(0).0)
+        .build_without_end_tag();
+    assert!(BootInformation::from_bytes(&bytes).is_none());
+}
+
+#[test]
+fn end_tag_before_the_end() {
+    // anything after the first end tag is not a tag
+    let bytes = Builder::new()
+        .tag(COMMAND_LINE, b"first\0")
+        .tag(0, &[])
+        .tag(BOOT_LOADER_NAME, b"hidden\0")
+        .build();
+    let boot_info = BootInformation::from_bytes(&bytes).unwrap();
+    assert_eq!(boot_info.tags().count(), 1);
+    assert!(boot_info.boot_loader_name().is_none());
+}
+
+#[test]
+fn tag_smaller_than_its_header() {
+    for size in 0..8 {
+        let bytes = Builder::new()
+            .tag(COMMAND_LINE, b"before\0")
+            .raw(&Fields::default().u32(BOOT_LOADER_NAME).u32(size).0)
+            .tag(BOOT_LOADER_NAME, b"after\0")
+            .build();
+        let boot_info = BootInformation::from_bytes(&bytes).unwrap();
+        exercise(&boot_info);
+        assert_eq!(boot_info.tags().count(), 1, "size {}", size);
+        assert_eq!(boot_info.command_line(), Some("before"));
+        assert!(boot_info.boot_loader_name().is_none());
+    }
+}
+
+#[test]
+fn tag_overrunning_the_structure() {
+    for size in [32, 33, 1000, u32::MAX - 7, u32::MAX] {
+        let bytes = Builder::new()
+            .raw(&Fields::default().u32(COMMAND_LINE).u32(size).0)
+            .build();
+        let boot_info = BootInformation::from_bytes(&bytes).unwrap();
+        exercise(&boot_info);
+        assert_eq!(boot_info.tags().count(), 0, "size {}", size);
+        assert!(boot_info.command_line().is_none());
+    }
+}
+
+#[test]
+fn unpadded_tag() {
+    // a 13 byte tag must be followed by 3 bytes of padding, without it the
+    // next tag is read 3 bytes too far
+    let bytes = Builder::new()
+        .raw(
+            &Fields::default()
+                .u32(COMMAND_LINE)
+                .u32(13)
+                .bytes(b"abcd\0")
+                .0,
+        )
+        .raw(
+            &Fields::default()
+                .u32(BOOT_LOADER_NAME)
+                .u32(16)
+                .bytes(b"GRUB\0\0\0\0")
+                .0,
+        )
+        .raw(&[0; 3])
+        .build();
+    let boot_info = BootInformation::from_bytes(&bytes).unwrap();
+    exercise(&boot_info);
+    assert_eq!(boot_info.command_line(), Some("abcd"));
+    assert_ne!(boot_info.boot_loader_name(), Some("GRUB"));
+}
+
+#[test]
+fn misaligned_buffer() {
+    let bytes = every_tag();
+    for shift in 1..8 {
+        let mut shifted = vec![0; shift];
+        shifted.extend(&bytes);
+        let boot_info = BootInformation::from_bytes(&shifted[shift..]).unwrap();
+        exercise(&boot_info);
+        assert_eq!(boot_info.command_line(), Some("pae verbose"));
+        assert_eq!(
+            boot_info.memory_map_tag().unwrap().memory_areas().count(),
+            2
+        );
+        assert_eq!(boot_info.elf_sections_tag().unwrap().sections().count(), 2);
+    }
+}
+
+#[test]
+fn every_truncation() {
+    let bytes = every_tag();
+    for len in 0..bytes.len() {
+        let mut truncated = bytes[..len].to_vec();
+        if len >= 4 {
+            set_total_size(&mut truncated, len as u32);
+        }
+        if let Some(boot_info) = BootInformation::from_bytes(&truncated) {
+            exercise(&boot_info);
+        }
+        // with a valid end tag right after the cut
+        if len >= 8 {
+            let mut ended = truncated.clone();
+            ended.extend(Fields::default().u32(0).u32(8).0);
+            set_total_size(&mut ended, len as u32 + 8);
+            exercise(&BootInformation::from_bytes(&ended).unwrap());
+        }
+    }
+}
+
+#[test]
+fn every_byte_flipped() {
+    let bytes = every_tag();
+    for index in 0..bytes.len() {
+        for value in [0x00, 0x01, 0x07, 0x08, 0x7f, 0x80, 0xff] {
+            let mut flipped = bytes.clone();
+            flipped[index] = value;
+            if let Some(boot_info) = BootInformation::from_bytes(&flipped) {
+                exercise(&boot_info);
+            }
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//! Every tag parsed out of well-formed structures.
+
+mod common;
+
+use common::*;
+use multiboot::{
+    BootInformation, ElfSectionFlags, ElfSectionType, FramebufferType, MemoryAreaType,
+};
+
+fn parse(bytes: &[u8]) -> BootInformation<'_> {
+    BootInformation::from_bytes(bytes).expect("valid structure")
+}
+
+#[test]
+fn all_tags() {
+    let bytes = every_tag();
+    let boot_info = parse(&bytes);
+    exercise(&boot_info);
+
+    assert_eq!(boot_info.total_size(), bytes.len());
+    assert_eq!(boot_info.tags().count(), 11);
+    assert_eq!(boot_info.command_line(), Some("pae verbose"));
+    assert_eq!(boot_info.boot_loader_name(), Some("GRUB 2.06"));
+    assert_eq!(boot_info.load_base_address(), Some(0x100000));
+
+    let info = boot_info.basic_memory_info().unwrap();
+    assert_eq!((info.lower, info.upper), (639, 129920));
+    let device = boot_info.boot_device().unwrap();
+    assert_eq!((device.bios_device, device.partition), (0xe0, 0xffff_ffff));
+
+    let apm = boot_info.apm_table().unwrap();
+    assert_eq!(apm.version, 0x102);
+    assert_eq!(apm.offset, 0x9a4);
+    assert_eq!(apm.data_segment_length, 0xffff);
+}
+
+#[test]
+fn empty_structure() {
+    let bytes = Builder::new().build();
+    let boot_info = parse(&bytes);
+    exercise(&boot_info);
+    assert_eq!(boot_info.total_size(), 16);
+    assert_eq!(boot_info.tags().count(), 0);
+    assert!(boot_info.command_line().is_none());
+    assert!(boot_info.memory_map_tag().is_none());
+}
+
+#[test]
+fn strings() {
+    let bytes = Builder::new()
+        .tag(COMMAND_LINE, b"")
+        .tag(BOOT_LOADER_NAME, b"no terminator")
+        .build();
+    let boot_info = parse(&bytes);
+    assert_eq!(boot_info.command_line(), Some(""));
+    assert_eq!(boot_info.boot_loader_name(), Some("no terminator"));
+
+    let bytes = Builder::new().tag(COMMAND_LINE, b"\xff\xfe\0").build();
+    assert_eq!(parse(&bytes).command_line(), Some(""));
+}
+
+#[test]
+fn first_tag_wins() {
+    let bytes = Builder::new()
+        .tag(COMMAND_LINE, b"first\0")
+        .tag(COMMAND_LINE, b"second\0")
+        .build();
+    assert_eq!(parse(&bytes).command_line(), Some("first"));
+}
+
+#[test]
+fn unknown_tags_are_skipped() {
+    let bytes = Builder::new()
+        .tag(0x1234, &[0xaa; 13])
+        .tag(COMMAND_LINE, b"after\0")
+        .build();
+    let boot_info = parse(&bytes);
+    assert_eq!(boot_info.tags().count(), 2);
+    assert_eq!(boot_info.command_line(), Some("after"));
+}
+
+#[test]
+fn modules() {
+    let bytes = Builder::new()
+        .tag(
+            MODULE,
+            &Fields::default()
+                .u32(0x200000)
+                .u32(0x201000)
+                .bytes(b"initrd\0")
+                .0,
+        )
+        .tag(MODULE, &Fields::default().u32(0x300000).u32(0x300800).0)
+        .tag(MODULE, &Fields::default().u32(0x400000).0)
+        .build();
+    let modules: Vec<_> = parse(&bytes).module_tags().collect();
+    assert_eq!(modules.len(), 2, "a module without an end is skipped");
+    assert_eq!((modules[0].start, modules[0].end), (0x200000, 0x201000));
+    assert_eq!(modules[0].command_line, "initrd");
+    assert_eq!((modules[1].start, modules[1].end), (0x300000, 0x300800));
+    assert_eq!(modules[1].command_line, "");
+}
+
+#[test]
+fn memory_map() {
+    let bytes = every_tag();
+    let areas: Vec<_> = parse(&bytes)
+        .memory_map_tag()
+        .unwrap()
+        .memory_areas()
+        .map(|area| (area.base_addr, area.length))
+        .collect();
+    // the reserved area is not available RAM
+    assert_eq!(areas, [(0, 0x9fc00), (0x100000, 0x7ee0000)]);
+}
+
+#[test]
+fn memory_area_types() {
+    let data = Fields::default()
+        .u32(24)
+        .u32(0)
+        .bytes(&memory_area(0, 0x9fc00, 1))
+        .bytes(&memory_area(0x9fc00, 0x400, 2))
+        .bytes(&memory_area(0x100000, 0x7ee0000, 1))
+        .bytes(&memory_area(0x7fe0000, 0x10000, 3))
+        .bytes(&memory_area(0x7ff0000, 0x10000, 4))
+        .bytes(&memory_area(0x8000000, 0x1000, 5))
+        .bytes(&memory_area(0xfffc0000, 0x40000, 0x1234))
+        .bytes(&memory_area(u64::MAX, 2, 0))
+        .0;
+    let bytes = Builder::new().tag(MEMORY_MAP, &data).build();
+    let memory_map = parse(&bytes).memory_map_tag().unwrap();
+    let types: Vec<_> = memory_map
+        .all_memory_areas()
+        .map(|area| area.typ())
+        .collect();
+    assert_eq!(
+        types,
+        [
+            MemoryAreaType::Available,
+            MemoryAreaType::Reserved,
+            MemoryAreaType::Available,
+            MemoryAreaType::AcpiReclaimable,
+            MemoryAreaType::AcpiNvs,
+            MemoryAreaType::BadMemory,
+            MemoryAreaType::Reserved,
+            MemoryAreaType::Reserved,
+        ]
+    );
+    let last = memory_map.all_memory_areas().last().unwrap();
+    assert_eq!(last.end_address(), u64::MAX);
+    assert_eq!(memory_map.memory_areas().count(), 2);
+}
+
+#[test]
+fn memory_map_with_larger_entries() {
+    let mut entry = memory_area(0x100000, 0x1000, 1);
+    entry.extend([0xff; 8]);
+    let data = Fields::default()
+        .u32(32)
+        .u32(0)
+        .bytes(&entry)
+        .bytes(&entry)
+        .0;
+    let bytes = Builder::new().tag(MEMORY_MAP, &data).build();
+    let memory_map = parse(&bytes).memory_map_tag().unwrap();
+    assert_eq!(memory_map.memory_areas().count(), 2);
+}
+
+#[test]
+fn memory_map_with_invalid_entry_size() {
+    for entry_size in [0, 1, 8, 23] {
+        let data = Fields::default()
+            .u32(entry_size)
+            .u32(0)
+            .bytes(&memory_area(0, 0x1000, 1))
+            .0;
+        let bytes = Builder::new().tag(MEMORY_MAP, &data).build();
+        let memory_map = parse(&bytes).memory_map_tag().unwrap();
+        assert_eq!(
+            memory_map.memory_areas().count(),
+            0,
+            "entry size {}",
+            entry_size
+        );
+    }
+}
+
+#[test]
+fn memory_map_with_partial_entry() {
+    let area = memory_area(0, 0x1000, 1);
+    let data = Fields::default()
+        .u32(24)
+        .u32(0)
+        .bytes(&area)
+        .bytes(&area[..20])
+        .0;
+    let bytes = Builder::new().tag(MEMORY_MAP, &data).build();
+    let memory_map = parse(&bytes).memory_map_tag().unwrap();
+    assert_eq!(memory_map.memory_areas().count(), 1);
+}
+
+fn framebuffer(typ: u8, color_info: &[u8]) -> Vec<u8> {
+    let data = Fields::default()
+        .u64(0xfd00_0000)
+        .u32(4096)
+        .u32(1024)
+        .u32(768)
+        .u8(32)
+        .u8(typ)
+        .u16(0)
+        .bytes(color_info)
+        .0;
+    Builder::new().tag(FRAMEBUFFER, &data).build()
+}
+
+#[test]
+fn framebuffer_rgb() {
+    let bytes = framebuffer(1, &[16, 8, 8, 8, 0, 8]);
+    let info = parse(&bytes).framebuffer_info().unwrap();
+    assert_eq!(info.address, 0xfd00_0000);
+    assert_eq!((info.pitch, info.width, info.height), (4096, 1024, 768));
+    assert_eq!(info.bits_per_pixel, 32);
+    match info.typ {
+        FramebufferType::Rgb { red, green, blue } => {
+            assert_eq!((red.position, red.size), (16, 8));
+            assert_eq!((green.position, green.size), (8, 8));
+            assert_eq!((blue.position, blue.size), (0, 8));
+        }
+        typ => panic!("unexpected type {:?}", typ),
+    }
+}
+
+#[test]
+fn framebuffer_indexed() {
+    let bytes = framebuffer(0, &[2, 0, 1, 2, 3, 4, 5, 6]);
+    let info = parse(&bytes).framebuffer_info().unwrap();
+    match info.typ {
+        FramebufferType::Indexed { palette } => {
+            assert_eq!(palette.len(), 2);
+            assert_eq!(
+                (palette[0].red, palette[0].green, palette[0].blue),
+                (1, 2, 3)
+            );
+            assert_eq!(
+                (palette[1].red, palette[1].green, palette[1].blue),
+                (4, 5, 6)
+            );
+        }
+        typ => panic!("unexpected type {:?}", typ),
+    }
+
+    // more colors than the tag holds
+    let bytes = framebuffer(0, &[3, 0, 1, 2, 3, 4, 5, 6]);
+    assert!(parse(&bytes).framebuffer_info().is_none());
+}
+
+#[test]
+fn framebuffer_text_and_unknown() {
+    let bytes = framebuffer(2, &[]);
+    let info = parse(&bytes).framebuffer_info().unwrap();
+    assert!(matches!(info.typ, FramebufferType::Text));
+
+    let bytes = framebuffer(3, &[]);
+    assert!(parse(&bytes).framebuffer_info().is_none());
+
+    // the RGB fields are missing
+    let bytes = framebuffer(1, &[16, 8]);
+    assert!(parse(&bytes).framebuffer_info().is_none());
+}
+
+fn elf_sections(entry_size: u32, sections: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = Fields::default()
+        .u32(sections.len() as u32)
+        .u32(entry_size)
+        .u32(0);
+    for section in sections {
+        data = data.bytes(section);
+    }
+    Builder::new().tag(ELF_SECTIONS, &data.0).build()
+}
+
+#[test]
+fn elf_sections_32() {
+    let bytes = elf_sections(
+        40,
+        &[
+            elf_section_32(0, 0, 0, 0),
+            elf_section_32(1, 0x6, 0xc010_0000, 0x3000),
+            elf_section_32(8, 0x3, 0xc010_3000, 0x1000),
+            elf_section_32(2, 0, 0, 0x500),
+        ],
+    );
+    let boot_info = parse(&bytes);
+    let sections: Vec<_> = boot_info.elf_sections_tag().unwrap().sections().collect();
+    assert_eq!(sections.len(), 3, "the null section is skipped");
+
+    assert_eq!(sections[0].section_type(), ElfSectionType::ProgramSection);
+    assert_eq!(sections[0].start_address(), 0xc010_0000);
+    assert_eq!(sections[0].end_address(), 0xc010_3000);
+    assert_eq!(
+        sections[0].flags(),
+        ElfSectionFlags::ALLOCATED | ElfSectionFlags::EXECUTABLE
+    );
+    assert_eq!(sections[1].section_type(), ElfSectionType::Uninitialized);
+    assert!(sections[1].flags().contains(ElfSectionFlags::WRITABLE));
+    assert_eq!(
+        sections[2].section_type(),
+        ElfSectionType::LinkerSymbolTable
+    );
+    assert!(!sections[2].is_allocated());
+}
+
+#[test]
+fn elf_sections_64() {
+    let bytes = elf_sections(
+        64,
+        &[
+            elf_section_64(1, 0x2, 0xffff_8000_0010_0000, 0x2000),
+            elf_section_64(0x6fff_fff6, 0, 0, 0),
+            elf_section_64(0x7000_0001, 0, 0, 0),
+            elf_section_64(0x1234_5678, 0, 0, 0),
+        ],
+    );
+    let boot_info = parse(&bytes);
+    let sections: Vec<_> = boot_info.elf_sections_tag().unwrap().sections().collect();
+    assert_eq!(sections.len(), 4);
+    assert_eq!(sections[0].start_address(), 0xffff_8000_0010_0000);
+    assert_eq!(sections[0].size(), 0x2000);
+    assert!(sections[0].is_allocated());
+    assert_eq!(
+        sections[1].section_type(),
+        ElfSectionType::EnvironmentSpecific
+    );
+    assert_eq!(
+        sections[2].section_type(),
+        ElfSectionType::ProcessorSpecific
+    );
+    assert_eq!(
+        sections[3].section_type(),
+        ElfSectionType::Unknown(0x1234_5678)
+    );
+}
+
+/// Sets the link and the entry size of a section header.
+fn link(mut section: Vec<u8>, link: u32, entry_size: u32) -> Vec<u8> {
+    let (link_offset, entry_size_offset) = if section.len() == 40 {
+        (24, 36)
+    } else {
+        (40, 56)
+    };
+    section[link_offset..link_offset + 4].copy_from_slice(&link.to_le_bytes());
+    section[entry_size_offset..entry_size_offset + 4].copy_from_slice(&entry_size.to_le_bytes());
+    section
+}
+
+#[test]
+fn elf_symbol_table() {
+    for entry_size in [40, 64] {
+        let section = |typ, size| match entry_size {
+            40 => elf_section_32(typ, 0, 0x200000, size),
+            _ => elf_section_64(typ, 0, 0x200000, size.into()),
+        };
+        let bytes = elf_sections(
+            entry_size,
+            &[
+                section(0, 0),
+                section(3, 0x100),
+                link(section(2, 0x300), 1, 16),
+            ],
+        );
+        let tag = parse(&bytes).elf_sections_tag().unwrap();
+
+        let symbols = tag
+            .sections()
+            .find(|s| s.section_type() == ElfSectionType::LinkerSymbolTable)
+            .unwrap();
+        assert_eq!((symbols.link(), symbols.entry_size()), (1, 16));
+        let strings = tag.section(symbols.link()).unwrap();
+        assert_eq!(strings.section_type(), ElfSectionType::StringTable);
+        assert_eq!(strings.size(), 0x100);
+        assert_eq!(
+            tag.section(0).unwrap().section_type(),
+            ElfSectionType::Unused
+        );
+        assert!(tag.section(3).is_none());
+    }
+}
+
+#[test]
+fn elf_section_out_of_the_tag() {
+    let mut data = Fields::default().u32(2).u32(40).u32(0);
+    data = data.bytes(&elf_section_32(1, 0x2, 0x100000, 0x1000));
+    let bytes = Builder::new().tag(ELF_SECTIONS, &data.0).build();
+    let tag = parse(&bytes).elf_sections_tag().unwrap();
+    assert!(tag.section(0).is_some());
+    assert!(tag.section(1).is_none());
+    assert!(tag.section(u32::MAX).is_none());
+
+    let bytes = elf_sections(41, &[elf_section_32(1, 0x2, 0x100000, 0x1000)]);
+    assert!(parse(&bytes)
+        .elf_sections_tag()
+        .unwrap()
+        .section(0)
+        .is_none());
+}
+
+#[test]
+fn elf_sections_with_invalid_entry_size() {
+    for entry_size in [0, 39, 41, 63, 128] {
+        let section = elf_section_32(1, 0x2, 0x100000, 0x1000);
+        let bytes = elf_sections(entry_size, &[section]);
+        let tag = parse(&bytes).elf_sections_tag().unwrap();
+        assert_eq!(tag.sections().count(), 0, "entry size {}", entry_size);
+    }
+}
+
+#[test]
+fn elf_sections_beyond_the_tag() {
+    let mut data = Fields::default().u32(1000).u32(40).u32(0);
+    data = data.bytes(&elf_section_32(1, 0x2, 0x100000, 0x1000));
+    let bytes = Builder::new().tag(ELF_SECTIONS, &data.0).build();
+    let tag = parse(&bytes).elf_sections_tag().unwrap();
+    assert_eq!(tag.sections().count(), 1);
+}
+
+#[test]
+fn elf_section_end_saturates() {
+    let bytes = elf_sections(64, &[elf_section_64(1, 0x2, u64::MAX - 1, 0x1000)]);
+    let tag = parse(&bytes).elf_sections_tag().unwrap();
+    assert_eq!(tag.sections().next().unwrap().end_address(), u64::MAX);
+}
+
+fn rsdp_bytes(revision: u8, checksum_fix: bool) -> Vec<u8> {
+    let mut bytes = Fields::default()
+        .bytes(b"RSD PTR ")
+        .u8(0)
+        .bytes(b"OEMID ")
+        .u8(revision)
+        .u32(0x7fe14d2)
+        .u32(36)
+        .u64(0x7fe15e0)
+        .u8(0) // extended checksum
+        .bytes(&[0; 3])
+        .0;
+    if checksum_fix {
+        let sum = |bytes: &[u8]| bytes.iter().fold(0u8, |sum, &b| sum.wrapping_sub(b));
+        bytes[8] = sum(&bytes[..20]);
+        bytes[32] = sum(&bytes);
+    }
+    bytes
+}
+
+#[test]
+fn acpi_old_rsdp() {
+    let bytes = every_tag();
+    let boot_info = parse(&bytes);
+    let rsdp = boot_info.acpi_old_rsdp().unwrap();
+    assert_eq!(rsdp.oem_id(), "BOCHS ");
+    assert_eq!(rsdp.revision(), 0);
+    assert_eq!(rsdp.rsdt_address(), 0x7fe14d2);
+    assert_eq!(rsdp.xsdt_address(), None);
+    assert!(rsdp.checksum_is_valid());
+    assert!(boot_info.acpi_new_rsdp().is_none());
+}
+
+#[test]
+fn acpi_new_rsdp() {
+    let bytes = Builder::new()
+        .tag(ACPI_NEW_RSDP, &rsdp_bytes(2, true))
+        .build();
+    let rsdp = parse(&bytes).acpi_new_rsdp().unwrap();
+    assert_eq!(rsdp.oem_id(), "OEMID ");
+    assert_eq!(rsdp.revision(), 2);
+    assert_eq!(rsdp.xsdt_address(), Some(0x7fe15e0));
+    assert!(rsdp.checksum_is_valid());
+
+    let bytes = Builder::new()
+        .tag(ACPI_NEW_RSDP, &rsdp_bytes(2, false))
+        .build();
+    assert!(!parse(&bytes).acpi_new_rsdp().unwrap().checksum_is_valid());
+}
+
+#[test]
+fn acpi_rsdp_with_bad_signature_or_size() {
+    let mut data = rsdp_bytes(0, true);
+    data[0] = b'X';
+    let bytes = Builder::new().tag(ACPI_OLD_RSDP, &data).build();
+    assert!(!parse(&bytes).acpi_old_rsdp().unwrap().checksum_is_valid());
+
+    let bytes = Builder::new()
+        .tag(ACPI_OLD_RSDP, &rsdp_bytes(0, true)[..19])
+        .build();
+    assert!(parse(&bytes).acpi_old_rsdp().is_none());
+
+    // an ACPI 2.0 RSDP cut after the ACPI 1.0 part
+    let bytes = Builder::new()
+        .tag(ACPI_NEW_RSDP, &rsdp_bytes(2, true)[..24])
+        .build();
+    let rsdp = parse(&bytes).acpi_new_rsdp().unwrap();
+    assert_eq!(rsdp.xsdt_address(), None);
+    assert!(!rsdp.checksum_is_valid());
+}
+
+#[test]
+fn truncated_fixed_size_tags() {
+    let bytes = Builder::new()
+        .tag(BASIC_MEMORY_INFO, &[0; 7])
+        .tag(BOOT_DEVICE, &[0; 11])
+        .tag(APM, &[0; 19])
+        .tag(LOAD_BASE_ADDRESS, &[0; 3])
+        .tag(MEMORY_MAP, &[0; 7])
+        .tag(ELF_SECTIONS, &[0; 11])
+        .tag(FRAMEBUFFER, &[0; 21])
+        .build();
+    let boot_info = parse(&bytes);
+    exercise(&boot_info);
+    assert!(boot_info.basic_memory_info().is_none());
+    assert!(boot_info.boot_device().is_none());
+    assert!(boot_info.apm_table().is_none());
+    assert!(boot_info.load_base_address().is_none());
+    assert!(boot_info.memory_map_tag().is_none());
+    assert!(boot_info.elf_sections_tag().is_none());
+    assert!(boot_info.framebuffer_info().is_none());
+}
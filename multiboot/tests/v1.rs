@@ -0,0 +1,338 @@
+//! Multiboot1 structures converted to multiboot2 ones.
+
+mod common;
+
+use common::*;
+use multiboot::{v1, BootInformation, FramebufferType, InfoWriter};
+
+const INFO: u32 = 0x1000;
+const STRINGS: u32 = 0x2000;
+const MODULES: u32 = 0x3000;
+const MEMORY_MAP: u32 = 0x4000;
+const SECTIONS: u32 = 0x5000;
+const PALETTE: u32 = 0x6000;
+const APM_TABLE: u32 = 0x7000;
+
+/// Physical memory with a multiboot1 structure in it.
+struct Memory(Vec<u8>);
+
+impl Memory {
+    fn new() -> Memory {
+        Memory(vec![0; 0x8000])
+    }
+
+    fn put(&mut self, address: u32, bytes: &[u8]) {
+        let address = address as usize;
+        self.0[address..address + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn field(&mut self, offset: u32, value: u32) {
+        self.put(INFO + offset, &value.to_le_bytes());
+    }
+
+    fn set_flags(&mut self, flags: u32) {
+        let old = u32::from_le_bytes(self.0[INFO as usize..][..4].try_into().unwrap());
+        self.field(0, old | flags);
+    }
+
+    fn read(&self, address: u32, len: usize) -> Option<&[u8]> {
+        self.0
+            .get(address as usize..(address as usize).checked_add(len)?)
+    }
+
+    fn convert<'b>(&self, buffer: &'b mut [u8]) -> Option<BootInformation<'b>> {
+        let mut writer = InfoWriter::new(buffer);
+        v1::convert(INFO, |address, len| self.read(address, len), &mut writer)?;
+        writer.finish()
+    }
+}
+
+/// The fields that GRUB and QEMU give.
+fn full() -> Memory {
+    let mut memory = Memory::new();
+    memory.put(STRINGS, b"/boot/kfs.bin pae  screens=2\0");
+    memory.put(STRINGS + 0x100, b"GRUB 0.97\0");
+    memory.put(STRINGS + 0x200, b"/boot/initrd.tar initrd\0");
+    memory.put(STRINGS + 0x300, b"/boot/other\0");
+    memory.set_flags(1 << 2 | 1 << 9);
+    memory.field(16, STRINGS);
+    memory.field(64, STRINGS + 0x100);
+
+    memory.set_flags(1 << 0 | 1 << 1);
+    memory.field(4, 639);
+    memory.field(8, 130048);
+    memory.field(12, 0x80_00_ff_ff);
+
+    let modules = Fields::default()
+        .u32(0x20_0000)
+        .u32(0x20_2000)
+        .u32(STRINGS + 0x200)
+        .u32(0)
+        .u32(0x30_0000)
+        .u32(0x30_0100)
+        .u32(STRINGS + 0x300)
+        .u32(0);
+    memory.put(MODULES, &modules.0);
+    memory.set_flags(1 << 3);
+    memory.field(20, 2);
+    memory.field(24, MODULES);
+
+    let entry = |base: u64, length: u64, typ: u32| {
+        Fields::default().u32(20).u64(base).u64(length).u32(typ).0
+    };
+    let mut map = entry(0, 0x9fc00, 1);
+    map.extend(entry(0xf0000, 0x10000, 2));
+    // larger entries are allowed, their size says where the next one starts
+    map.extend(
+        Fields::default()
+            .u32(28)
+            .u64(0x10_0000)
+            .u64(0x7ee_0000)
+            .u32(1)
+            .u64(0)
+            .0,
+    );
+    map.extend(entry(0xfffc_0000, 0x4_0000, 2));
+    memory.put(MEMORY_MAP, &map);
+    memory.set_flags(1 << 6);
+    memory.field(44, map.len() as u32);
+    memory.field(48, MEMORY_MAP);
+
+    let mut sections = elf_section_32(0, 0, 0, 0);
+    sections.extend(elf_section_32(1, 0x6, 0xc010_0000, 0x3000));
+    sections.extend(elf_section_32(3, 0, 0, 0x100));
+    memory.put(SECTIONS, &sections);
+    memory.set_flags(1 << 5);
+    memory.field(28, 3);
+    memory.field(32, 40);
+    memory.field(36, SECTIONS);
+    memory.field(40, 2);
+    memory
+}
+
+#[test]
+fn full_structure() {
+    let memory = full();
+    let mut buffer = [0; 4096];
+    let boot_info = memory.convert(&mut buffer).unwrap();
+    exercise(&boot_info);
+
+    assert_eq!(boot_info.command_line(), Some("pae  screens=2"));
+    assert_eq!(boot_info.boot_loader_name(), Some("GRUB 0.97"));
+
+    let info = boot_info.basic_memory_info().unwrap();
+    assert_eq!((info.lower, info.upper), (639, 130048));
+    let device = boot_info.boot_device().unwrap();
+    assert_eq!(device.bios_device, 0x80);
+    assert_eq!(device.partition, 0);
+    assert_eq!(device.sub_partition, u32::MAX);
+
+    let modules: Vec<_> = boot_info.module_tags().collect();
+    assert_eq!(modules.len(), 2);
+    assert_eq!((modules[0].start, modules[0].end), (0x20_0000, 0x20_2000));
+    assert_eq!(modules[0].command_line, "initrd");
+    assert_eq!((modules[1].start, modules[1].end), (0x30_0000, 0x30_0100));
+    assert_eq!(modules[1].command_line, "");
+
+    let areas: Vec<_> = boot_info
+        .memory_map_tag()
+        .unwrap()
+        .memory_areas()
+        .map(|area| (area.base_addr, area.length))
+        .collect();
+    assert_eq!(areas, [(0, 0x9fc00), (0x10_0000, 0x7ee_0000)]);
+
+    let sections: Vec<_> = boot_info.elf_sections_tag().unwrap().sections().collect();
+    assert_eq!(sections.len(), 2);
+    assert_eq!(sections[0].start_address(), 0xc010_0000);
+    assert!(sections[0].is_allocated());
+    assert!(!sections[1].is_allocated());
+
+    assert!(boot_info.framebuffer_info().is_none());
+    assert!(boot_info.apm_table().is_none());
+    assert!(boot_info.acpi_old_rsdp().is_none());
+}
+
+#[test]
+fn no_fields() {
+    let memory = Memory::new();
+    let mut buffer = [0; 64];
+    let boot_info = memory.convert(&mut buffer).unwrap();
+    assert_eq!(boot_info.tags().count(), 0);
+}
+
+#[test]
+fn apm_and_framebuffer() {
+    let mut memory = Memory::new();
+    memory.put(
+        APM_TABLE,
+        &Fields::default()
+            .u16(0x102)
+            .u16(0xf000)
+            .u32(0x9a4)
+            .u16(0xf000)
+            .u16(0x40)
+            .u16(3)
+            .u16(0xffff)
+            .u16(0xffff)
+            .u16(0x1000)
+            .0,
+    );
+    memory.set_flags(1 << 10 | 1 << 12);
+    memory.field(68, APM_TABLE);
+    let framebuffer = Fields::default()
+        .u64(0xfd00_0000)
+        .u32(4096)
+        .u32(1024)
+        .u32(768)
+        .u8(32)
+        .u8(1)
+        .bytes(&[16, 8, 8, 8, 0, 8]);
+    memory.put(INFO + 88, &framebuffer.0);
+
+    let mut buffer = [0; 256];
+    let boot_info = memory.convert(&mut buffer).unwrap();
+    let apm = boot_info.apm_table().unwrap();
+    assert_eq!(
+        (apm.version, apm.offset, apm.data_segment_length),
+        (0x102, 0x9a4, 0x1000)
+    );
+    let info = boot_info.framebuffer_info().unwrap();
+    assert_eq!(
+        (info.address, info.width, info.height),
+        (0xfd00_0000, 1024, 768)
+    );
+    match info.typ {
+        FramebufferType::Rgb { red, blue, .. } => {
+            assert_eq!((red.position, blue.position), (16, 0));
+        }
+        typ => panic!("unexpected type {:?}", typ),
+    }
+}
+
+#[test]
+fn indexed_framebuffer() {
+    let mut memory = Memory::new();
+    memory.put(PALETTE, &[1, 2, 3, 4, 5, 6]);
+    memory.set_flags(1 << 12);
+    let framebuffer = Fields::default()
+        .u64(0xa0000)
+        .u32(320)
+        .u32(320)
+        .u32(200)
+        .u8(8)
+        .u8(0)
+        .u32(PALETTE)
+        .u16(2);
+    memory.put(INFO + 88, &framebuffer.0);
+
+    let mut buffer = [0; 256];
+    let boot_info = memory.convert(&mut buffer).unwrap();
+    match boot_info.framebuffer_info().unwrap().typ {
+        FramebufferType::Indexed { palette } => {
+            assert_eq!(palette.len(), 2);
+            assert_eq!(
+                (palette[1].red, palette[1].green, palette[1].blue),
+                (4, 5, 6)
+            );
+        }
+        typ => panic!("unexpected type {:?}", typ),
+    }
+}
+
+#[test]
+fn unreachable_pointers() {
+    let mut memory = full();
+    memory.field(16, 0xffff_fff0); // command line
+    memory.field(24, 0x7ff8); // modules cut by the end of memory
+    memory.field(48, 0x10_0000); // memory map
+    memory.field(36, 0x7fff); // ELF sections
+    memory.set_flags(1 << 10);
+    memory.field(68, 0xffff_ffff); // APM table
+
+    let mut buffer = [0; 4096];
+    let boot_info = memory.convert(&mut buffer).unwrap();
+    exercise(&boot_info);
+    assert_eq!(boot_info.command_line(), Some(""));
+    assert_eq!(boot_info.module_tags().count(), 0);
+    assert!(boot_info.memory_map_tag().is_none());
+    assert!(boot_info.elf_sections_tag().is_none());
+    assert!(boot_info.apm_table().is_none());
+    assert_eq!(boot_info.boot_loader_name(), Some("GRUB 0.97"));
+}
+
+#[test]
+fn huge_memory_map_entry() {
+    let mut memory = full();
+    let entry = |size: u32, base: u64| Fields::default().u32(size).u64(base).u64(0x1000).u32(1).0;
+    let mut map = entry(20, 0);
+    // the next entry would start past the end of the map, and of the
+    // address space on 32 bits
+    map.extend(entry(u32::MAX, 0x10_0000));
+    map.extend(entry(20, 0x20_0000));
+    memory.put(MEMORY_MAP, &map);
+    memory.field(44, map.len() as u32);
+
+    let mut buffer = [0; 4096];
+    let boot_info = memory.convert(&mut buffer).unwrap();
+    let areas: Vec<_> = boot_info
+        .memory_map_tag()
+        .unwrap()
+        .memory_areas()
+        .map(|area| area.base_addr)
+        .collect();
+    assert_eq!(areas, [0, 0x10_0000]);
+}
+
+#[test]
+fn string_at_the_end_of_memory() {
+    let mut memory = full();
+    memory.put(0x7ff0, b"/kfs.bin verbose");
+    memory.field(16, 0x7ff0);
+    let mut buffer = [0; 4096];
+    let boot_info = memory.convert(&mut buffer).unwrap();
+    assert_eq!(boot_info.command_line(), Some("verbose"));
+}
+
+#[test]
+fn structure_out_of_memory() {
+    let memory = Memory::new();
+    let mut writer = InfoWriter::new(&mut []);
+    assert!(v1::convert(
+        0x7fc0,
+        |address, len| memory.read(address, len),
+        &mut writer
+    )
+    .is_none());
+}
+
+#[test]
+fn small_buffers() {
+    let memory = full();
+    for size in 0..1024 {
+        let mut buffer = vec![0; size];
+        if let Some(boot_info) = memory.convert(&mut buffer) {
+            exercise(&boot_info);
+            assert!(boot_info.total_size() <= size);
+        }
+    }
+}
+
+#[test]
+fn writer() {
+    let mut buffer = [0xff; 64];
+    let mut writer = InfoWriter::new(&mut buffer);
+    assert!(writer.tag(COMMAND_LINE, &[b"ab", b"c\0"]));
+    assert!(writer.contains(COMMAND_LINE));
+    assert!(!writer.contains(BOOT_LOADER_NAME));
+    // 8 bytes of header, 16 of the first tag and the end tag leave 32 bytes
+    assert!(!writer.tag(BOOT_LOADER_NAME, &[&[0; 25]]));
+    assert!(writer.tag(BOOT_LOADER_NAME, &[&[b'x'; 23], &[0]]));
+    let boot_info = writer.finish().unwrap();
+    assert_eq!(boot_info.total_size(), 64);
+    assert_eq!(boot_info.command_line(), Some("abc"));
+    assert_eq!(boot_info.boot_loader_name(), Some("x".repeat(23).as_str()));
+
+    assert!(InfoWriter::new(&mut [0; 15]).finish().is_none());
+    assert!(InfoWriter::new(&mut [0; 16]).finish().is_some());
+}
@@ -0,0 +1,150 @@
+//! The handoff from the bootloader. The kernel can be loaded by a multiboot2
+//! bootloader such as GRUB, or by a multiboot1 one such as `qemu -kernel`,
+//! whose information structure is converted to a multiboot2 one.
+
+use crate::memory::KERNEL_OFFSET;
+use core::ptr::{addr_of, addr_of_mut};
+use multiboot::{tag_type, v1, BootInformation, ElfSectionFlags, InfoWriter};
+
+/// Room for the converted structure. The tags that don't fit are left out.
+const INFO_BUFFER_SIZE: usize = 8192;
+
+#[repr(C, align(8))]
+struct InfoBuffer([u8; INFO_BUFFER_SIZE]);
+
+static mut INFO_BUFFER: InfoBuffer = InfoBuffer([0; INFO_BUFFER_SIZE]);
+
+/// The multiboot information structure at physical address `address`,
+/// given along with `magic` by the bootloader.
+///
+/// Must be called once, before the kernel is remapped.
+pub unsafe fn load(magic: u32, address: usize) -> BootInformation<'static> {
+    match magic {
+        // reached through the higher half
+        multiboot::MAGIC => multiboot::load(KERNEL_OFFSET + address),
+        v1::MAGIC => convert(address as u32),
+        _ => panic!("unknown bootloader magic {:#x}", magic),
+    }
+}
+
+unsafe fn convert(address: u32) -> BootInformation<'static> {
+    let mut writer = InfoWriter::new(&mut (*addr_of_mut!(INFO_BUFFER)).0);
+    v1::convert(address, physical_memory, &mut writer)
+        .expect("invalid multiboot1 information structure");
+    if !writer.contains(tag_type::ELF_SECTIONS) {
+        write_elf_sections(&mut writer);
+    }
+    writer
+        .finish()
+        .expect("multiboot information buffer is too small")
+}
+
+/// The lower 3 GiB are identity mapped by `asm/boot.asm` until the kernel is remapped.
+/// A null address is the one of a field that the loader didn't fill.
+fn physical_memory(address: u32, len: usize) -> Option<&'static [u8]> {
+    let end = (address as usize).checked_add(len)?;
+    if address == 0 || end > KERNEL_OFFSET {
+        return None;
+    }
+    Some(unsafe { core::slice::from_raw_parts(address as *const u8, len) })
+}
+
+const SECTION_HEADER_SIZE: usize = 40;
+const PROGRAM_SECTION: u32 = 1;
+const UNINITIALIZED: u32 = 8;
+
+extern "C" {
+    static __boot_start: u8;
+    static __boot_end: u8;
+    static __rodata_start: u8;
+    static __rodata_end: u8;
+    static __text_start: u8;
+    static __text_end: u8;
+    static __data_start: u8;
+    static __data_end: u8;
+    static __bss_start: u8;
+    static __bss_end: u8;
+    static __got_start: u8;
+    static __got_end: u8;
+    static __data_rel_ro_start: u8;
+    static __data_rel_ro_end: u8;
+}
+
+/// Appends an ELF sections tag describing the sections of `linker.ld`, as
+/// multiboot1 bootloaders don't have to give the section headers, and QEMU
+/// doesn't.
+fn write_elf_sections(writer: &mut InfoWriter) {
+    const ALLOCATED: ElfSectionFlags = ElfSectionFlags::ALLOCATED;
+    const WRITABLE: ElfSectionFlags = ElfSectionFlags::WRITABLE;
+    const EXECUTABLE: ElfSectionFlags = ElfSectionFlags::EXECUTABLE;
+
+    // taking the address of an extern static is safe, only reading it isn't
+    let sections = [
+        (
+            PROGRAM_SECTION,
+            ALLOCATED | EXECUTABLE,
+            addr_of!(__boot_start),
+            addr_of!(__boot_end),
+        ),
+        (
+            PROGRAM_SECTION,
+            ALLOCATED,
+            addr_of!(__rodata_start),
+            addr_of!(__rodata_end),
+        ),
+        (
+            PROGRAM_SECTION,
+            ALLOCATED | EXECUTABLE,
+            addr_of!(__text_start),
+            addr_of!(__text_end),
+        ),
+        (
+            PROGRAM_SECTION,
+            ALLOCATED | WRITABLE,
+            addr_of!(__data_start),
+            addr_of!(__data_end),
+        ),
+        (
+            UNINITIALIZED,
+            ALLOCATED | WRITABLE,
+            addr_of!(__bss_start),
+            addr_of!(__bss_end),
+        ),
+        (
+            PROGRAM_SECTION,
+            ALLOCATED | WRITABLE,
+            addr_of!(__got_start),
+            addr_of!(__got_end),
+        ),
+        (
+            PROGRAM_SECTION,
+            ALLOCATED | WRITABLE,
+            addr_of!(__data_rel_ro_start),
+            addr_of!(__data_rel_ro_end),
+        ),
+    ];
+
+    let mut headers = [0; 7 * SECTION_HEADER_SIZE];
+    for (header, (typ, flags, start, end)) in
+        headers.chunks_exact_mut(SECTION_HEADER_SIZE).zip(sections)
+    {
+        let (start, end) = (start as u32, end as u32);
+        header[4..8].copy_from_slice(&typ.to_le_bytes());
+        header[8..12].copy_from_slice(&(flags.bits() as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&start.to_le_bytes());
+        header[20..24].copy_from_slice(&(end - start).to_le_bytes());
+        header[32..36].copy_from_slice(&4096u32.to_le_bytes()); // alignment
+    }
+    let count = sections.len() as u32;
+    let entry_size = SECTION_HEADER_SIZE as u32;
+    // no section names, so no string table
+    writer.tag(
+        tag_type::ELF_SECTIONS,
+        &[
+            &count.to_le_bytes(),
+            &entry_size.to_le_bytes(),
+            &0u32.to_le_bytes(),
+            &headers,
+        ],
+    );
+}
@@ -0,0 +1,180 @@
+//! Options given to the kernel on the multiboot command line, as
+//! space-separated `key=value` pairs and bare flags, like
+//! `multiboot2 /boot/kfs.bin pae keymap=azerty screens=2`.
+
+use crate::interrupts::PIT_FREQUENCIES;
+use crate::vga_buffer::VGA_SCREENS;
+use core::fmt;
+use spin::Once;
+
+/// Warnings past this number are dropped.
+const MAX_WARNINGS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keymap {
+    Us104,
+    Azerty,
+}
+
+/// Which of the boot messages are printed, see `info!` and `debug!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Info,
+    Debug,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Warning {
+    UnknownOption(&'static str),
+    MissingValue(&'static str),
+    UnexpectedValue(&'static str),
+    InvalidValue {
+        option: &'static str,
+        value: &'static str,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::UnknownOption(option) => write!(f, "unknown option \"{}\"", option),
+            Warning::MissingValue(option) => write!(f, "option \"{}\" needs a value", option),
+            Warning::UnexpectedValue(option) => {
+                write!(f, "option \"{}\" doesn't take a value", option)
+            }
+            Warning::InvalidValue { option, value } => {
+                write!(f, "invalid value \"{}\" for option \"{}\"", value, option)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KernelParams {
+    /// The command line as given by the bootloader.
+    pub raw: &'static str,
+    /// `pae`: use PAE paging if the CPU supports it.
+    pub pae: bool,
+    /// `keymap=us104|azerty`
+    pub keymap: Keymap,
+    /// `screens=N`: number of virtual screens reachable with F1 to F4.
+    pub screens: usize,
+    /// `loglevel=quiet|info|debug`, or the `quiet` and `verbose` flags.
+    pub verbosity: Verbosity,
+    /// `serial`: also run the shell on the first serial port.
+    pub serial: bool,
+    /// `pit_hz=N`: frequency of the timer interrupt.
+    pub pit_hz: u32,
+    warnings: [Option<Warning>; MAX_WARNINGS],
+}
+
+impl KernelParams {
+    pub const DEFAULT: KernelParams = KernelParams {
+        raw: "",
+        pae: false,
+        keymap: Keymap::Us104,
+        screens: VGA_SCREENS,
+        verbosity: Verbosity::Info,
+        serial: false,
+        pit_hz: 100,
+        warnings: [None; MAX_WARNINGS],
+    };
+
+    /// Parses `raw`, the options that are unknown or have an invalid value
+    /// are ignored with a warning.
+    pub fn parse(raw: &'static str) -> KernelParams {
+        let mut params = KernelParams {
+            raw,
+            ..KernelParams::DEFAULT
+        };
+        for arg in raw.split_whitespace() {
+            let (option, value) = match arg.split_once('=') {
+                Some((option, value)) => (option, Some(value)),
+                None => (arg, None),
+            };
+            if let Err(warning) = params.set(option, value) {
+                params.warn(warning);
+            }
+        }
+        params
+    }
+
+    fn set(&mut self, option: &'static str, value: Option<&'static str>) -> Result<(), Warning> {
+        let invalid = |value| Warning::InvalidValue { option, value };
+        match option {
+            "pae" => self.pae = flag(option, value)?,
+            "serial" => self.serial = flag(option, value)?,
+            "quiet" => {
+                flag(option, value)?;
+                self.verbosity = Verbosity::Quiet;
+            }
+            "verbose" => {
+                flag(option, value)?;
+                self.verbosity = Verbosity::Debug;
+            }
+            "keymap" => {
+                self.keymap = match value.ok_or(Warning::MissingValue(option))? {
+                    "us104" | "us" | "qwerty" => Keymap::Us104,
+                    "azerty" | "fr" => Keymap::Azerty,
+                    value => return Err(invalid(value)),
+                }
+            }
+            "loglevel" => {
+                self.verbosity = match value.ok_or(Warning::MissingValue(option))? {
+                    "quiet" | "0" => Verbosity::Quiet,
+                    "info" | "1" => Verbosity::Info,
+                    "debug" | "2" => Verbosity::Debug,
+                    value => return Err(invalid(value)),
+                }
+            }
+            "screens" => {
+                let value = value.ok_or(Warning::MissingValue(option))?;
+                self.screens = value
+                    .parse()
+                    .ok()
+                    .filter(|screens| (1..=VGA_SCREENS).contains(screens))
+                    .ok_or_else(|| invalid(value))?;
+            }
+            "pit_hz" => {
+                let value = value.ok_or(Warning::MissingValue(option))?;
+                self.pit_hz = value
+                    .parse()
+                    .ok()
+                    .filter(|hz| PIT_FREQUENCIES.contains(hz))
+                    .ok_or_else(|| invalid(value))?;
+            }
+            _ => return Err(Warning::UnknownOption(option)),
+        }
+        Ok(())
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        if let Some(slot) = self.warnings.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(warning);
+        }
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Warning> {
+        self.warnings.iter().flatten()
+    }
+}
+
+fn flag(option: &'static str, value: Option<&'static str>) -> Result<bool, Warning> {
+    match value {
+        None => Ok(true),
+        Some(_) => Err(Warning::UnexpectedValue(option)),
+    }
+}
+
+static PARAMS: Once<KernelParams> = Once::new();
+
+/// Parses the command line, must be called once before anything reads the options.
+pub fn init(raw: &'static str) {
+    PARAMS.call_once(|| KernelParams::parse(raw));
+}
+
+/// The options of the command line, or the defaults before `init`.
+pub fn params() -> &'static KernelParams {
+    PARAMS.r#try().unwrap_or(&KernelParams::DEFAULT)
+}
@@ -0,0 +1,483 @@
+//! Global descriptor table, with flat segments for the kernel and user
+//! space, the task state segment used to find the kernel stack when an
+//! interrupt comes from ring 3, and the one of the double fault task.
+//!
+//! `asm/boot.asm` loads a minimal GDT to reach `kernel_main`, which is
+//! replaced by this one as soon as the kernel starts.
+
+use crate::interrupts;
+use crate::memory::paging::{read_cr3, VirtualAddress};
+use core::arch::asm;
+use core::fmt;
+use core::mem::size_of;
+use core::ops::Range;
+use core::ptr::addr_of;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+const GDT_SIZE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum PrivilegeLevel {
+    Ring0 = 0,
+    Ring1 = 1,
+    Ring2 = 2,
+    Ring3 = 3,
+}
+
+impl PrivilegeLevel {
+    pub const fn from_u16(value: u16) -> PrivilegeLevel {
+        match value & 0b11 {
+            0 => PrivilegeLevel::Ring0,
+            1 => PrivilegeLevel::Ring1,
+            2 => PrivilegeLevel::Ring2,
+            _ => PrivilegeLevel::Ring3,
+        }
+    }
+}
+
+/// Index of a descriptor in the GDT, along with the requested privilege level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct SegmentSelector(pub u16);
+
+impl SegmentSelector {
+    pub const fn new(index: u16, rpl: PrivilegeLevel) -> SegmentSelector {
+        SegmentSelector(index << 3 | rpl as u16)
+    }
+}
+
+pub const KERNEL_CODE_SELECTOR: SegmentSelector = SegmentSelector::new(1, PrivilegeLevel::Ring0);
+pub const KERNEL_DATA_SELECTOR: SegmentSelector = SegmentSelector::new(2, PrivilegeLevel::Ring0);
+pub const USER_CODE_SELECTOR: SegmentSelector = SegmentSelector::new(3, PrivilegeLevel::Ring3);
+pub const USER_DATA_SELECTOR: SegmentSelector = SegmentSelector::new(4, PrivilegeLevel::Ring3);
+pub const TSS_SELECTOR: SegmentSelector = SegmentSelector::new(5, PrivilegeLevel::Ring0);
+pub const DOUBLE_FAULT_TSS_SELECTOR: SegmentSelector =
+    SegmentSelector::new(6, PrivilegeLevel::Ring0);
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 4 * 4096;
+
+bitflags! {
+    /// Bits of a segment descriptor besides its base and limit.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DescriptorFlags: u64 {
+        /// Set by the CPU when the segment is loaded.
+        const ACCESSED =       1 << 40;
+        /// Readable for a code segment, writable for a data segment, busy for a TSS.
+        const READ_WRITE =     1 << 41;
+        /// Conforming code segment, or expand-down data segment.
+        const CONFORMING =     1 << 42;
+        const EXECUTABLE =     1 << 43;
+        /// A code or data segment, otherwise a system segment such as a TSS.
+        const USER_SEGMENT =   1 << 44;
+        const DPL_RING_3 =     3 << 45;
+        const PRESENT =        1 << 47;
+        /// Free for the operating system.
+        const AVAILABLE =      1 << 52;
+        /// 32-bit segment.
+        const DEFAULT_SIZE =   1 << 54;
+        /// The limit is in 4 KiB units.
+        const GRANULARITY =    1 << 55;
+    }
+}
+
+/// Type of a system descriptor, in the bits of `READ_WRITE` to `EXECUTABLE`.
+const AVAILABLE_TSS: u64 = 0x9 << 40;
+const SYSTEM_TYPE_MASK: u64 = 0xf << 40;
+const BUSY_TSS: u64 = 0xb << 40;
+
+/// An 8-byte GDT entry.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Descriptor(u64);
+
+impl Descriptor {
+    pub const NULL: Descriptor = Descriptor(0);
+
+    const fn new(base: u32, limit: u32, flags: u64) -> Descriptor {
+        let base = base as u64;
+        let limit = limit as u64;
+        Descriptor(
+            (limit & 0xffff)
+                | (base & 0xff_ffff) << 16
+                | flags
+                | (limit >> 16 & 0xf) << 48
+                | (base >> 24) << 56,
+        )
+    }
+
+    /// A segment spanning the whole 4 GiB address space.
+    const fn flat_segment(dpl: PrivilegeLevel, flags: DescriptorFlags) -> Descriptor {
+        let flags = flags.bits()
+            | DescriptorFlags::USER_SEGMENT.bits()
+            | DescriptorFlags::PRESENT.bits()
+            | DescriptorFlags::DEFAULT_SIZE.bits()
+            | DescriptorFlags::GRANULARITY.bits();
+        Descriptor::new(0, 0xf_ffff, flags | (dpl as u64) << 45)
+    }
+
+    pub const fn code_segment(dpl: PrivilegeLevel) -> Descriptor {
+        Descriptor::flat_segment(
+            dpl,
+            DescriptorFlags::EXECUTABLE.union(DescriptorFlags::READ_WRITE),
+        )
+    }
+
+    pub const fn data_segment(dpl: PrivilegeLevel) -> Descriptor {
+        Descriptor::flat_segment(dpl, DescriptorFlags::READ_WRITE)
+    }
+
+    /// `tss` must stay at the same address as long as the descriptor is used.
+    pub fn tss_segment(tss: *const TaskStateSegment) -> Descriptor {
+        let base = tss as u32;
+        let limit = (size_of::<TaskStateSegment>() - 1) as u32;
+        Descriptor::new(base, limit, AVAILABLE_TSS | DescriptorFlags::PRESENT.bits())
+    }
+
+    pub fn flags(&self) -> DescriptorFlags {
+        DescriptorFlags::from_bits_truncate(self.0)
+    }
+
+    pub fn base(&self) -> u32 {
+        ((self.0 >> 16 & 0xff_ffff) | (self.0 >> 56) << 24) as u32
+    }
+
+    /// Offset of the last byte of the segment.
+    pub fn limit(&self) -> u32 {
+        let limit = (self.0 & 0xffff | (self.0 >> 48 & 0xf) << 16) as u32;
+        if self.flags().contains(DescriptorFlags::GRANULARITY) {
+            limit << 12 | 0xfff
+        } else {
+            limit
+        }
+    }
+
+    pub fn dpl(&self) -> PrivilegeLevel {
+        PrivilegeLevel::from_u16((self.0 >> 45) as u16)
+    }
+
+    pub fn kind(&self) -> &'static str {
+        let flags = self.flags();
+        if self.0 == 0 {
+            "null"
+        } else if flags.contains(DescriptorFlags::USER_SEGMENT) {
+            if flags.contains(DescriptorFlags::EXECUTABLE) {
+                "code"
+            } else {
+                "data"
+            }
+        } else {
+            match self.0 & SYSTEM_TYPE_MASK {
+                AVAILABLE_TSS => "TSS",
+                BUSY_TSS => "TSS (busy)",
+                _ => "system",
+            }
+        }
+    }
+}
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "null");
+        }
+        write!(
+            f,
+            "{:10} base {:#010x} limit {:#010x} ring {}",
+            self.kind(),
+            self.base(),
+            self.limit(),
+            self.dpl() as u8
+        )?;
+        let flags = self.flags();
+        if flags.contains(DescriptorFlags::USER_SEGMENT) {
+            let access = match (
+                flags.contains(DescriptorFlags::EXECUTABLE),
+                flags.contains(DescriptorFlags::READ_WRITE),
+            ) {
+                (true, true) => "rx",
+                (true, false) => "x",
+                (false, true) => "rw",
+                (false, false) => "r",
+            };
+            write!(f, " {}", access)?;
+            if flags.contains(DescriptorFlags::DEFAULT_SIZE) {
+                write!(f, " 32-bit")?;
+            }
+        }
+        if !flags.contains(DescriptorFlags::PRESENT) {
+            write!(f, " not present")?;
+        }
+        Ok(())
+    }
+}
+
+/// 32-bit task state segment. The CPU saves the state of the running task
+/// in it on a task switch, and only reads `esp0` and `ss0` otherwise, to
+/// switch to the kernel stack on an interrupt from ring 3.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct TaskStateSegment {
+    pub link: u16,
+    reserved_0: u16,
+    pub esp0: u32,
+    pub ss0: u16,
+    reserved_1: u16,
+    pub esp1: u32,
+    pub ss1: u16,
+    reserved_2: u16,
+    pub esp2: u32,
+    pub ss2: u16,
+    reserved_3: u16,
+    pub cr3: u32,
+    pub eip: u32,
+    pub eflags: u32,
+    pub eax: u32,
+    pub ecx: u32,
+    pub edx: u32,
+    pub ebx: u32,
+    pub esp: u32,
+    pub ebp: u32,
+    pub esi: u32,
+    pub edi: u32,
+    pub es: u16,
+    reserved_4: u16,
+    pub cs: u16,
+    reserved_5: u16,
+    pub ss: u16,
+    reserved_6: u16,
+    pub ds: u16,
+    reserved_7: u16,
+    pub fs: u16,
+    reserved_8: u16,
+    pub gs: u16,
+    reserved_9: u16,
+    pub ldt: u16,
+    reserved_10: u16,
+    pub trap: u16,
+    /// Offset of the I/O permission bitmap. Pointing past the end of the
+    /// segment means there is none, so ring 3 can't use any port.
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    pub const fn new() -> TaskStateSegment {
+        TaskStateSegment {
+            link: 0,
+            reserved_0: 0,
+            esp0: 0,
+            ss0: 0,
+            reserved_1: 0,
+            esp1: 0,
+            ss1: 0,
+            reserved_2: 0,
+            esp2: 0,
+            ss2: 0,
+            reserved_3: 0,
+            cr3: 0,
+            eip: 0,
+            eflags: 0,
+            eax: 0,
+            ecx: 0,
+            edx: 0,
+            ebx: 0,
+            esp: 0,
+            ebp: 0,
+            esi: 0,
+            edi: 0,
+            es: 0,
+            reserved_4: 0,
+            cs: 0,
+            reserved_5: 0,
+            ss: 0,
+            reserved_6: 0,
+            ds: 0,
+            reserved_7: 0,
+            fs: 0,
+            reserved_8: 0,
+            gs: 0,
+            reserved_9: 0,
+            ldt: 0,
+            reserved_10: 0,
+            trap: 0,
+            iomap_base: size_of::<TaskStateSegment>() as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed(2))]
+pub struct DescriptorTablePointer {
+    pub limit: u16,
+    pub base: u32,
+}
+
+pub struct GlobalDescriptorTable {
+    table: [Descriptor; GDT_SIZE],
+    len: usize,
+}
+
+impl GlobalDescriptorTable {
+    /// A table with only the null descriptor.
+    pub const fn new() -> GlobalDescriptorTable {
+        GlobalDescriptorTable {
+            table: [Descriptor::NULL; GDT_SIZE],
+            len: 1,
+        }
+    }
+
+    /// Appends `descriptor` and returns its selector, with the privilege
+    /// level of the descriptor as requested privilege level.
+    pub fn add_entry(&mut self, descriptor: Descriptor) -> SegmentSelector {
+        assert!(self.len < GDT_SIZE, "the GDT is full");
+        self.table[self.len] = descriptor;
+        self.len += 1;
+        SegmentSelector::new((self.len - 1) as u16, descriptor.dpl())
+    }
+
+    /// Loads the table in GDTR. The segment registers keep their old
+    /// descriptors until they are reloaded.
+    pub fn load(&'static self) {
+        let pointer = DescriptorTablePointer {
+            base: self.table.as_ptr() as u32,
+            limit: (self.len * size_of::<Descriptor>() - 1) as u16,
+        };
+        unsafe {
+            asm!("lgdt [{}]", in(reg) &pointer, options(readonly, nostack, preserves_flags));
+        }
+    }
+}
+
+static TSS: Mutex<TaskStateSegment> = Mutex::new(TaskStateSegment::new());
+/// Task switched to on a double fault, so that it has a stack of its own
+/// even if the kernel stack overflowed.
+static DOUBLE_FAULT_TSS: Mutex<TaskStateSegment> = Mutex::new(TaskStateSegment::new());
+
+#[repr(C, align(16))]
+struct Stack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+static mut DOUBLE_FAULT_STACK: Stack = Stack([0; DOUBLE_FAULT_STACK_SIZE]);
+
+lazy_static! {
+    static ref GDT: GlobalDescriptorTable = {
+        let mut gdt = GlobalDescriptorTable::new();
+        let selectors = [
+            gdt.add_entry(Descriptor::code_segment(PrivilegeLevel::Ring0)),
+            gdt.add_entry(Descriptor::data_segment(PrivilegeLevel::Ring0)),
+            gdt.add_entry(Descriptor::code_segment(PrivilegeLevel::Ring3)),
+            gdt.add_entry(Descriptor::data_segment(PrivilegeLevel::Ring3)),
+            // the CPU reads the TSS behind the back of the lock, which never moves it
+            gdt.add_entry(Descriptor::tss_segment(&*TSS.lock())),
+            gdt.add_entry(Descriptor::tss_segment(&*DOUBLE_FAULT_TSS.lock())),
+        ];
+        assert_eq!(
+            selectors,
+            [
+                KERNEL_CODE_SELECTOR,
+                KERNEL_DATA_SELECTOR,
+                USER_CODE_SELECTOR,
+                USER_DATA_SELECTOR,
+                TSS_SELECTOR,
+                DOUBLE_FAULT_TSS_SELECTOR
+            ]
+        );
+        gdt
+    };
+}
+
+extern "C" {
+    static stack_top: u8;
+}
+
+/// Loads the GDT, reloads every segment register and loads the TSS, whose
+/// kernel stack is the boot stack until `set_kernel_stack` is called.
+pub fn init() {
+    set_kernel_stack(KERNEL_DATA_SELECTOR, addr_of!(stack_top) as VirtualAddress);
+    GDT.load();
+    unsafe {
+        reload_segments(KERNEL_CODE_SELECTOR, KERNEL_DATA_SELECTOR);
+        asm!("ltr {0:x}", in(reg) TSS_SELECTOR.0, options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Sets the stack that the CPU switches to on an interrupt from ring 3.
+pub fn set_kernel_stack(ss0: SegmentSelector, esp0: VirtualAddress) {
+    interrupts::without_interrupts(|| {
+        let mut tss = TSS.lock();
+        tss.ss0 = ss0.0;
+        tss.esp0 = esp0 as u32;
+    });
+}
+
+/// Top of the stack of the double fault task.
+pub fn double_fault_stack_top() -> VirtualAddress {
+    addr_of!(DOUBLE_FAULT_STACK) as VirtualAddress + DOUBLE_FAULT_STACK_SIZE
+}
+
+/// The stack of the double fault task.
+pub fn double_fault_stack() -> Range<VirtualAddress> {
+    let top = double_fault_stack_top();
+    top - DOUBLE_FAULT_STACK_SIZE..top
+}
+
+/// Makes the double fault task start at `entry`, on its own stack, in the
+/// current address space whose kernel space is shared by every other one.
+/// The error code pushed by the CPU, always 0, is where `entry` expects
+/// its return address.
+pub fn set_double_fault_task(entry: extern "C" fn() -> !) {
+    interrupts::without_interrupts(|| {
+        let mut tss = DOUBLE_FAULT_TSS.lock();
+        tss.eip = entry as u32;
+        tss.esp = double_fault_stack_top() as u32;
+        tss.eflags = 1 << 1; // reserved bit, interrupts disabled
+        tss.cr3 = read_cr3() as u32;
+        tss.cs = KERNEL_CODE_SELECTOR.0;
+        tss.ss = KERNEL_DATA_SELECTOR.0;
+        tss.ds = KERNEL_DATA_SELECTOR.0;
+        tss.es = KERNEL_DATA_SELECTOR.0;
+        tss.fs = KERNEL_DATA_SELECTOR.0;
+        tss.gs = KERNEL_DATA_SELECTOR.0;
+    });
+}
+
+/// Runs `f` with the state of the task interrupted by a double fault, saved
+/// by the CPU in its TSS. Returns `None` if the TSS was being updated.
+pub fn with_interrupted_task<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut TaskStateSegment) -> R,
+{
+    TSS.try_lock().map(|mut tss| f(&mut tss))
+}
+
+/// Reloads CS with a far return, and the data segment registers.
+unsafe fn reload_segments(code: SegmentSelector, data: SegmentSelector) {
+    asm!(
+        "push {code}",
+        "lea {tmp}, [2f]",
+        "push {tmp}",
+        "retf",
+        "2:",
+        "mov ds, {data:x}",
+        "mov es, {data:x}",
+        "mov fs, {data:x}",
+        "mov gs, {data:x}",
+        "mov ss, {data:x}",
+        code = in(reg) code.0 as usize,
+        data = in(reg) data.0 as u32,
+        tmp = out(reg) _,
+        options(preserves_flags),
+    );
+}
+
+/// The table in GDTR.
+pub fn loaded_table() -> &'static [Descriptor] {
+    let mut pointer = DescriptorTablePointer { limit: 0, base: 0 };
+    unsafe {
+        asm!("sgdt [{}]", in(reg) &mut pointer, options(nostack, preserves_flags));
+        core::slice::from_raw_parts(
+            pointer.base as *const Descriptor,
+            (pointer.limit as usize + 1) / size_of::<Descriptor>(),
+        )
+    }
+}
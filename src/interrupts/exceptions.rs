@@ -0,0 +1,418 @@
+use super::idt::InterruptStackFrame;
+use crate::gdt;
+use crate::memory::paging::{read_cr3, VirtualAddress};
+use crate::memory::{self, lazy, PAGE_SIZE};
+use crate::symbols::Location;
+use core::arch::asm;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PageFaultErrorCode: u32 {
+        const PROTECTION_VIOLATION = 1 << 0;
+        const CAUSED_BY_WRITE =      1 << 1;
+        const USER_MODE =            1 << 2;
+        const MALFORMED_TABLE =      1 << 3;
+        const INSTRUCTION_FETCH =    1 << 4;
+    }
+}
+
+impl fmt::Display for PageFaultErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let present = if self.contains(Self::PROTECTION_VIOLATION) {
+            "protection violation"
+        } else {
+            "page not present"
+        };
+        let access = if self.contains(Self::INSTRUCTION_FETCH) {
+            "instruction fetch"
+        } else if self.contains(Self::CAUSED_BY_WRITE) {
+            "write"
+        } else {
+            "read"
+        };
+        let mode = if self.contains(Self::USER_MODE) {
+            "user"
+        } else {
+            "kernel"
+        };
+        write!(f, "{}, {} in {} mode", present, access, mode)?;
+        if self.contains(Self::MALFORMED_TABLE) {
+            write!(f, ", reserved bit set in a page table")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error code of the exceptions caused by a segment selector, such as a
+/// general protection fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorErrorCode(u32);
+
+impl SelectorErrorCode {
+    const EXTERNAL: u32 = 1 << 0;
+
+    /// Whether the exception happened while delivering an external interrupt.
+    pub fn external(&self) -> bool {
+        self.0 & Self::EXTERNAL != 0
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self.0 >> 1 & 0b11 {
+            0 => "GDT",
+            2 => "LDT",
+            _ => "IDT",
+        }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.0 >> 3 & 0x1fff
+    }
+}
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "not caused by a selector");
+        }
+        write!(f, "{} entry {}", self.table(), self.index())?;
+        if self.table() != "IDT" {
+            write!(f, " (selector {:#x})", self.index() << 3)?;
+        }
+        if self.external() {
+            write!(f, ", during an external interrupt")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error code of a control protection exception.
+struct ControlProtectionErrorCode(u32);
+
+impl fmt::Display for ControlProtectionErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cause = match self.0 & 0x7fff {
+            1 => "near RET",
+            2 => "far RET or IRET",
+            3 => "missing ENDBRANCH",
+            4 => "RSTORSSP",
+            5 => "SETSSBSY",
+            _ => "unknown cause",
+        };
+        write!(f, "{}", cause)?;
+        if self.0 & 1 << 15 != 0 {
+            write!(f, ", in an enclave")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reports an exception that the kernel can't recover from, with an optional
+/// line of details about what the handler saw.
+fn exception_panic(
+    name: &str,
+    stack_frame: &InterruptStackFrame,
+    error_code: Option<(u32, &dyn fmt::Display)>,
+    details: Option<fmt::Arguments>,
+) -> ! {
+    let InterruptStackFrame {
+        instruction_pointer,
+        code_segment,
+        cpu_flags,
+    } = *stack_frame;
+    let details = Details(details);
+    match error_code {
+        Some((code, decoded)) => panic!(
+            "{}\n    eip: {}\n    cs: {:#x}, eflags: {:#010x}, error code: {:#x} ({}){}",
+            name,
+            Location(instruction_pointer as VirtualAddress),
+            code_segment,
+            cpu_flags,
+            code,
+            decoded,
+            details
+        ),
+        None => panic!(
+            "{}\n    eip: {}\n    cs: {:#x}, eflags: {:#010x}{}",
+            name,
+            Location(instruction_pointer as VirtualAddress),
+            code_segment,
+            cpu_flags,
+            details
+        ),
+    }
+}
+
+/// The details of an exception, on their own line when there are some.
+struct Details<'a>(Option<fmt::Arguments<'a>>);
+
+impl fmt::Display for Details<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(details) => write!(f, "\n    {}", details),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A handler for an exception without an error code, or with an error code
+/// decoded by the given type.
+macro_rules! exception_handler {
+    ($handler:ident, $name:expr) => {
+        pub extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame) {
+            exception_panic($name, &stack_frame, None, None)
+        }
+    };
+    ($handler:ident, $name:expr, $error_code:expr) => {
+        pub extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame, error_code: u32) {
+            exception_panic(
+                $name,
+                &stack_frame,
+                Some((error_code, &$error_code(error_code))),
+                None,
+            )
+        }
+    };
+}
+
+/// For the error codes that don't carry any information.
+struct RawErrorCode(u32);
+
+impl fmt::Display for RawErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", if self.0 == 0 { "none" } else { "unexpected" })
+    }
+}
+
+/// Exit code of a VMM communication exception, given by the hypervisor.
+struct ExitCode(u32);
+
+impl fmt::Display for ExitCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "exit code {:#x}", self.0)
+    }
+}
+
+exception_handler!(divide_error_handler, "#DE divide error");
+exception_handler!(debug_handler, "#DB debug");
+exception_handler!(non_maskable_interrupt_handler, "NMI non-maskable interrupt");
+exception_handler!(breakpoint_handler, "#BP breakpoint");
+exception_handler!(overflow_handler, "#OF overflow");
+exception_handler!(bound_range_exceeded_handler, "#BR bound range exceeded");
+exception_handler!(invalid_opcode_handler, "#UD invalid opcode");
+exception_handler!(device_not_available_handler, "#NM device not available");
+exception_handler!(
+    coprocessor_segment_overrun_handler,
+    "coprocessor segment overrun"
+);
+exception_handler!(invalid_tss_handler, "#TS invalid TSS", SelectorErrorCode);
+exception_handler!(
+    segment_not_present_handler,
+    "#NP segment not present",
+    SelectorErrorCode
+);
+exception_handler!(
+    stack_segment_fault_handler,
+    "#SS stack-segment fault",
+    SelectorErrorCode
+);
+exception_handler!(
+    general_protection_fault_handler,
+    "#GP general protection fault",
+    SelectorErrorCode
+);
+exception_handler!(
+    x87_floating_point_handler,
+    "#MF x87 floating-point exception"
+);
+exception_handler!(alignment_check_handler, "#AC alignment check", RawErrorCode);
+exception_handler!(
+    simd_floating_point_handler,
+    "#XM SIMD floating-point exception"
+);
+exception_handler!(virtualization_handler, "#VE virtualization exception");
+exception_handler!(
+    control_protection_handler,
+    "#CP control protection exception",
+    ControlProtectionErrorCode
+);
+exception_handler!(hypervisor_injection_handler, "#HV hypervisor injection");
+exception_handler!(
+    vmm_communication_handler,
+    "#VC VMM communication exception",
+    ExitCode
+);
+exception_handler!(
+    security_exception_handler,
+    "#SX security exception",
+    RawErrorCode
+);
+
+/// State of the task interrupted by a double fault.
+#[derive(Debug, Clone, Copy)]
+pub struct DoubleFault {
+    pub eip: u32,
+    pub esp: u32,
+    /// Whether the stack pointer was in the guard page under the kernel
+    /// stack, or right at its bottom when the push that faulted was undone.
+    pub stack_overflow: bool,
+}
+
+/// Where `catch_double_fault` resumes, or 0 if a double fault is fatal.
+static RECOVERY_EIP: AtomicUsize = AtomicUsize::new(0);
+static RECOVERY_ESP: AtomicUsize = AtomicUsize::new(0);
+static RECOVERY_CR3: AtomicUsize = AtomicUsize::new(0);
+static CAUGHT: Mutex<Option<DoubleFault>> = Mutex::new(None);
+
+/// Entry point of the double fault task, reached through a task gate with
+/// the stack of `gdt::double_fault_stack_top`. The CPU saved the state of
+/// the interrupted task in its TSS.
+pub extern "C" fn double_fault_task() -> ! {
+    let guard = memory::stack_guard_page();
+    let task = gdt::with_interrupted_task(|task| *task);
+    let fault = task.map(|task| DoubleFault {
+        eip: task.eip,
+        esp: task.esp,
+        stack_overflow: (guard..=guard + PAGE_SIZE).contains(&(task.esp as usize)),
+    });
+
+    let recovery_eip = RECOVERY_EIP.swap(0, Ordering::Relaxed);
+    if recovery_eip != 0 {
+        if let (Some(fault), Some(())) = (
+            fault,
+            gdt::with_interrupted_task(|task| {
+                task.eip = recovery_eip as u32;
+                task.esp = RECOVERY_ESP.load(Ordering::Relaxed) as u32;
+                // CR3 is loaded but not saved on a task switch
+                task.cr3 = RECOVERY_CR3.load(Ordering::Relaxed) as u32;
+            }),
+        ) {
+            *CAUGHT.lock() = Some(fault);
+            resume_interrupted_task();
+        }
+    }
+
+    match task {
+        Some(task) => panic!(
+            "#DF double fault{}\n    \
+             eip: {}\n    \
+             esp: {:#010x}, ebp: {:#010x}, eflags: {:#010x}\n    \
+             eax: {:#010x}, ebx: {:#010x}, ecx: {:#010x}, edx: {:#010x}\n    \
+             esi: {:#010x}, edi: {:#010x}, cs: {:#x}, ss: {:#x}, ds: {:#x}",
+            if fault.is_some_and(|fault| fault.stack_overflow) {
+                ": kernel stack overflow"
+            } else {
+                ""
+            },
+            Location(task.eip as VirtualAddress),
+            task.esp,
+            task.ebp,
+            task.eflags,
+            task.eax,
+            task.ebx,
+            task.ecx,
+            task.edx,
+            task.esi,
+            task.edi,
+            task.cs,
+            task.ss,
+            task.ds
+        ),
+        None => panic!("#DF double fault while the TSS was locked"),
+    }
+}
+
+/// Switches back to the task interrupted by the double fault. The next
+/// double fault starts the task again from its entry point, on an empty stack.
+fn resume_interrupted_task() -> ! {
+    unsafe {
+        asm!(
+            "iretd",
+            // the CPU pushed the error code on the stack left by the last run
+            "mov esp, {stack_top}",
+            "push 0",
+            "jmp {entry}",
+            stack_top = in(reg) gdt::double_fault_stack_top(),
+            entry = sym double_fault_task,
+            options(noreturn),
+        )
+    }
+}
+
+/// Runs `f` and returns the double fault it caused, if any, instead of
+/// panicking. Everything `f` did on the stack is dropped on a double fault,
+/// so it must not hold any lock.
+pub fn catch_double_fault(f: extern "C" fn()) -> Option<DoubleFault> {
+    RECOVERY_CR3.store(read_cr3(), Ordering::Relaxed);
+    unsafe {
+        asm!(
+            // the registers are those of the double fault when resuming at 2
+            "push ebp",
+            "push ebx",
+            "push esi",
+            "push edi",
+            "mov dword ptr [{esp}], esp",
+            "lea ecx, [2f]",
+            "mov dword ptr [{eip}], ecx",
+            "call eax",
+            "mov dword ptr [{eip}], 0",
+            "2:",
+            "mov esp, dword ptr [{esp}]",
+            "pop edi",
+            "pop esi",
+            "pop ebx",
+            "pop ebp",
+            in("eax") f,
+            esp = sym RECOVERY_ESP,
+            eip = sym RECOVERY_EIP,
+            clobber_abi("C"),
+        );
+    }
+    CAUGHT.lock().take()
+}
+
+pub extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    exception_panic("#MC machine check", &stack_frame, None, None)
+}
+
+fn read_cr2() -> usize {
+    let value: usize;
+    unsafe {
+        asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+pub extern "x86-interrupt" fn page_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u32,
+) {
+    let address = read_cr2();
+    let error = PageFaultErrorCode::from_bits_truncate(error_code);
+
+    if !error.contains(PageFaultErrorCode::PROTECTION_VIOLATION) && lazy::map_on_fault(address) {
+        return;
+    }
+    if error
+        .contains(PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE)
+        && memory::resolve_copy_on_write(address)
+    {
+        return;
+    }
+
+    let region = match lazy::find(address) {
+        Some(region) => region.name,
+        None => "none",
+    };
+    exception_panic(
+        "#PF page fault",
+        &stack_frame,
+        Some((error_code, &error)),
+        Some(format_args!(
+            "address: {:#010x}, lazy region: {}",
+            address, region
+        )),
+    )
+}
@@ -1,9 +1,6 @@
+use crate::gdt::{SegmentSelector, KERNEL_CODE_SELECTOR};
 use core::marker::PhantomData;
 
-extern "C" {
-    static kernel_code: usize;
-}
-
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Entry<F> {
@@ -30,24 +27,62 @@ impl<F> Entry<F> {
     pub unsafe fn set_handler_addr(&mut self, addr: usize) -> &mut EntryOptions {
         self.pointer_low = addr as u16;
         self.pointer_middle = (addr >> 16) as u16;
-        self.gdt_selector = unsafe { &kernel_code as *const usize as usize as u16 };
+        self.gdt_selector = KERNEL_CODE_SELECTOR.0;
         self.options.set_present();
         &mut self.options
     }
+
+    /// Makes the entry switch to the task of the TSS at `tss` instead of
+    /// calling a handler.
+    pub fn set_task_gate(&mut self, tss: SegmentSelector) -> &mut EntryOptions {
+        self.pointer_low = 0;
+        self.pointer_middle = 0;
+        self.gdt_selector = tss.0;
+        self.options = EntryOptions::task_gate();
+        self.options.set_present();
+        &mut self.options
+    }
+}
+
+/// Pushed by the CPU before calling a handler. `esp` and `ss` are only
+/// pushed on a privilege change, which can't happen while everything runs in ring 0.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InterruptStackFrame {
+    pub instruction_pointer: u32,
+    pub code_segment: u32,
+    pub cpu_flags: u32,
 }
 
-pub type HandlerFunc = extern "x86-interrupt" fn();
+pub type HandlerFunc = extern "x86-interrupt" fn(InterruptStackFrame);
+pub type HandlerFuncWithErrCode = extern "x86-interrupt" fn(InterruptStackFrame, u32);
+/// For the exceptions that can't be returned from.
+pub type DivergingHandlerFunc = extern "x86-interrupt" fn(InterruptStackFrame) -> !;
+pub type DivergingHandlerFuncWithErrCode = extern "x86-interrupt" fn(InterruptStackFrame, u32) -> !;
 
 pub trait HandlerFuncType {
     fn to_virt_addr(self) -> usize;
 }
 
-impl HandlerFuncType for HandlerFunc {
-    fn to_virt_addr(self) -> usize {
-        self as usize
-    }
+macro_rules! impl_handler_func_type {
+    ($($f:ty),*) => {
+        $(
+            impl HandlerFuncType for $f {
+                fn to_virt_addr(self) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
 }
 
+impl_handler_func_type!(
+    HandlerFunc,
+    HandlerFuncWithErrCode,
+    DivergingHandlerFunc,
+    DivergingHandlerFuncWithErrCode
+);
+
 impl<F: HandlerFuncType> Entry<F> {
     #[inline]
     pub fn set_handler_fn(&mut self, handler: F) -> &mut EntryOptions {
@@ -65,6 +100,11 @@ impl EntryOptions {
         Self(0b1110_0000_0000)
     }
 
+    #[inline]
+    const fn task_gate() -> Self {
+        Self(0b0101_0000_0000)
+    }
+
     #[inline]
     pub fn set_present(&mut self) {
         self.0 |= 1 << 15;
@@ -1,6 +1,12 @@
 mod entry;
 
-use self::entry::{Entry, HandlerFunc};
+pub use self::entry::InterruptStackFrame;
+
+use self::entry::{
+    DivergingHandlerFunc, DivergingHandlerFuncWithErrCode, Entry, HandlerFunc,
+    HandlerFuncWithErrCode,
+};
+use crate::gdt::DescriptorTablePointer;
 use core::arch::asm;
 use core::ops::{Index, IndexMut};
 
@@ -8,24 +14,74 @@ const IDT_SIZE: usize = 256;
 const NB_BUILTINS: usize = 32;
 const NB_INTERRUPTS: usize = IDT_SIZE - NB_BUILTINS;
 
-#[derive(Debug, Clone, Copy)]
-#[repr(C, packed(2))]
-struct DescriptorTablePointer {
-    limit: u16,
-    base: u32,
-}
-
+/// The first 32 entries are the CPU exceptions, each with the handler type
+/// matching what the CPU pushes. The others are reached by indexing.
 #[repr(C)]
 #[repr(align(16))]
 pub struct InterruptDescriptorTable {
-    builtins: [Entry<HandlerFunc>; NB_BUILTINS],
+    pub divide_error: Entry<HandlerFunc>,
+    pub debug: Entry<HandlerFunc>,
+    pub non_maskable_interrupt: Entry<HandlerFunc>,
+    pub breakpoint: Entry<HandlerFunc>,
+    pub overflow: Entry<HandlerFunc>,
+    pub bound_range_exceeded: Entry<HandlerFunc>,
+    pub invalid_opcode: Entry<HandlerFunc>,
+    pub device_not_available: Entry<HandlerFunc>,
+    /// The error code is always 0.
+    pub double_fault: Entry<DivergingHandlerFuncWithErrCode>,
+    /// Only raised by CPUs older than the i486.
+    pub coprocessor_segment_overrun: Entry<HandlerFunc>,
+    pub invalid_tss: Entry<HandlerFuncWithErrCode>,
+    pub segment_not_present: Entry<HandlerFuncWithErrCode>,
+    pub stack_segment_fault: Entry<HandlerFuncWithErrCode>,
+    pub general_protection_fault: Entry<HandlerFuncWithErrCode>,
+    pub page_fault: Entry<HandlerFuncWithErrCode>,
+    reserved_1: Entry<HandlerFunc>,
+    pub x87_floating_point: Entry<HandlerFunc>,
+    /// The error code is always 0.
+    pub alignment_check: Entry<HandlerFuncWithErrCode>,
+    pub machine_check: Entry<DivergingHandlerFunc>,
+    pub simd_floating_point: Entry<HandlerFunc>,
+    pub virtualization: Entry<HandlerFunc>,
+    pub control_protection: Entry<HandlerFuncWithErrCode>,
+    reserved_2: [Entry<HandlerFunc>; 6],
+    pub hypervisor_injection: Entry<HandlerFunc>,
+    pub vmm_communication: Entry<HandlerFuncWithErrCode>,
+    pub security_exception: Entry<HandlerFuncWithErrCode>,
+    reserved_3: Entry<HandlerFunc>,
     interrupts: [Entry<HandlerFunc>; NB_INTERRUPTS],
 }
 
 impl InterruptDescriptorTable {
     pub fn new() -> Self {
         Self {
-            builtins: [Entry::missing(); NB_BUILTINS],
+            divide_error: Entry::missing(),
+            debug: Entry::missing(),
+            non_maskable_interrupt: Entry::missing(),
+            breakpoint: Entry::missing(),
+            overflow: Entry::missing(),
+            bound_range_exceeded: Entry::missing(),
+            invalid_opcode: Entry::missing(),
+            device_not_available: Entry::missing(),
+            double_fault: Entry::missing(),
+            coprocessor_segment_overrun: Entry::missing(),
+            invalid_tss: Entry::missing(),
+            segment_not_present: Entry::missing(),
+            stack_segment_fault: Entry::missing(),
+            general_protection_fault: Entry::missing(),
+            page_fault: Entry::missing(),
+            reserved_1: Entry::missing(),
+            x87_floating_point: Entry::missing(),
+            alignment_check: Entry::missing(),
+            machine_check: Entry::missing(),
+            simd_floating_point: Entry::missing(),
+            virtualization: Entry::missing(),
+            control_protection: Entry::missing(),
+            reserved_2: [Entry::missing(); 6],
+            hypervisor_injection: Entry::missing(),
+            vmm_communication: Entry::missing(),
+            security_exception: Entry::missing(),
+            reserved_3: Entry::missing(),
             interrupts: [Entry::missing(); NB_INTERRUPTS],
         }
     }
@@ -48,20 +104,18 @@ impl Index<usize> for InterruptDescriptorTable {
     type Output = Entry<HandlerFunc>;
 
     fn index(&self, i: usize) -> &Self::Output {
-        if i < NB_BUILTINS {
-            &self.builtins[i]
-        } else {
-            &self.interrupts[i - NB_BUILTINS]
+        match i {
+            0..NB_BUILTINS => panic!("entry {} is a CPU exception, use its field", i),
+            _ => &self.interrupts[i - NB_BUILTINS],
         }
     }
 }
 
 impl IndexMut<usize> for InterruptDescriptorTable {
     fn index_mut(&mut self, i: usize) -> &mut Self::Output {
-        if i < NB_BUILTINS {
-            &mut self.builtins[i]
-        } else {
-            &mut self.interrupts[i - NB_BUILTINS]
+        match i {
+            0..NB_BUILTINS => panic!("entry {} is a CPU exception, use its field", i),
+            _ => &mut self.interrupts[i - NB_BUILTINS],
         }
     }
 }
@@ -1,10 +1,19 @@
+mod exceptions;
 mod idt;
 mod pic;
+mod pit;
 
-use self::idt::InterruptDescriptorTable;
+pub use self::exceptions::catch_double_fault;
+pub use self::pit::{frequency as pit_frequency, ticks, FREQUENCIES as PIT_FREQUENCIES};
+
+use self::idt::{InterruptDescriptorTable, InterruptStackFrame};
 use self::pic::ChainedPics;
-use crate::keyboard::{layouts, scancodes, Keyboard};
+use crate::cmdline::params;
+use crate::gdt;
+use crate::keyboard::layouts::AnyLayout;
+use crate::keyboard::{scancodes, Keyboard};
 use crate::port::Port;
+use crate::serial::{self, SerialDecoder};
 use crate::shell::SHELL;
 use core::arch::asm;
 use lazy_static::lazy_static;
@@ -21,20 +30,76 @@ static PICS: Mutex<ChainedPics> =
 enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    Serial = PIC_1_OFFSET + serial::COM1_IRQ,
 }
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
+        idt.divide_error
+            .set_handler_fn(exceptions::divide_error_handler);
+        idt.debug.set_handler_fn(exceptions::debug_handler);
+        idt.non_maskable_interrupt
+            .set_handler_fn(exceptions::non_maskable_interrupt_handler);
+        idt.breakpoint
+            .set_handler_fn(exceptions::breakpoint_handler);
+        idt.overflow.set_handler_fn(exceptions::overflow_handler);
+        idt.bound_range_exceeded
+            .set_handler_fn(exceptions::bound_range_exceeded_handler);
+        idt.invalid_opcode
+            .set_handler_fn(exceptions::invalid_opcode_handler);
+        idt.device_not_available
+            .set_handler_fn(exceptions::device_not_available_handler);
+        idt.double_fault
+            .set_task_gate(gdt::DOUBLE_FAULT_TSS_SELECTOR);
+        idt.coprocessor_segment_overrun
+            .set_handler_fn(exceptions::coprocessor_segment_overrun_handler);
+        idt.invalid_tss
+            .set_handler_fn(exceptions::invalid_tss_handler);
+        idt.segment_not_present
+            .set_handler_fn(exceptions::segment_not_present_handler);
+        idt.stack_segment_fault
+            .set_handler_fn(exceptions::stack_segment_fault_handler);
+        idt.general_protection_fault
+            .set_handler_fn(exceptions::general_protection_fault_handler);
+        idt.page_fault
+            .set_handler_fn(exceptions::page_fault_handler);
+        idt.x87_floating_point
+            .set_handler_fn(exceptions::x87_floating_point_handler);
+        idt.alignment_check
+            .set_handler_fn(exceptions::alignment_check_handler);
+        idt.machine_check
+            .set_handler_fn(exceptions::machine_check_handler);
+        idt.simd_floating_point
+            .set_handler_fn(exceptions::simd_floating_point_handler);
+        idt.virtualization
+            .set_handler_fn(exceptions::virtualization_handler);
+        idt.control_protection
+            .set_handler_fn(exceptions::control_protection_handler);
+        idt.hypervisor_injection
+            .set_handler_fn(exceptions::hypervisor_injection_handler);
+        idt.vmm_communication
+            .set_handler_fn(exceptions::vmm_communication_handler);
+        idt.security_exception
+            .set_handler_fn(exceptions::security_exception_handler);
         idt[InterruptIndex::Timer as usize].set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard as usize].set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::Serial as usize].set_handler_fn(serial_interrupt_handler);
         idt
     };
 }
 
 pub fn init() {
+    gdt::set_double_fault_task(exceptions::double_fault_task);
     IDT.load();
-    unsafe { PICS.lock().init() };
+    pit::set_frequency(params().pit_hz);
+    unsafe {
+        let mut pics = PICS.lock();
+        pics.init();
+        if serial::enabled() {
+            pics.unmask(serial::COM1_IRQ);
+        }
+    }
     enable();
 }
 
@@ -65,6 +130,22 @@ fn disable() {
     }
 }
 
+/// Halts until the next interrupt, unless `ready` returns true. `ready` runs
+/// with interrupts disabled, and `sti` only takes effect after the `hlt` that
+/// follows it, so an interrupt that would make `ready` true can't come in
+/// between and leave the CPU waiting for another one.
+pub fn halt_unless<F>(ready: F)
+where
+    F: FnOnce() -> bool,
+{
+    disable();
+    if ready() {
+        enable();
+    } else {
+        unsafe { asm!("sti; hlt", options(nomem, nostack)) };
+    }
+}
+
 #[inline]
 pub fn without_interrupts<F, R>(f: F) -> R
 where
@@ -81,18 +162,37 @@ where
     ret
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler() {
+/// Runs `f` with interrupts enabled, and restores the interrupt flag after.
+/// In an interrupt handler, only the interrupts of a higher priority than
+/// the one being handled come in, such as the timer interrupt.
+pub fn with_interrupts<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let saved_intpt_flag = are_enabled();
+    if !saved_intpt_flag {
+        enable();
+    }
+    let ret = f();
+    if !saved_intpt_flag {
+        disable();
+    }
+    ret
+}
+
+extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    pit::tick();
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer as u8);
     }
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler() {
+extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
     lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, scancodes::ScancodeSet1>> =
+        static ref KEYBOARD: Mutex<Keyboard<AnyLayout, scancodes::ScancodeSet1>> =
             Mutex::new(Keyboard::new(
-                layouts::Us104Key,
+                AnyLayout::new(params().keymap),
                 scancodes::ScancodeSet1::new(),
             ));
     }
@@ -109,3 +209,18 @@ extern "x86-interrupt" fn keyboard_interrupt_handler() {
             .notify_end_of_interrupt(InterruptIndex::Keyboard as u8);
     }
 }
+
+extern "x86-interrupt" fn serial_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    static DECODER: Mutex<SerialDecoder> = Mutex::new(SerialDecoder::new());
+
+    while let Some(byte) = serial::receive() {
+        if let Some(key) = DECODER.lock().add_byte(byte) {
+            SHELL.lock().send_key(key);
+        }
+    }
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Serial as u8);
+    }
+}
@@ -96,6 +96,14 @@ impl ChainedPics {
         }
     }
 
+    /// Lets the PIC raise the interrupt of line `irq`, which is masked after
+    /// boot for most lines.
+    pub unsafe fn unmask(&mut self, irq: u8) {
+        let pic = &mut self.pics[irq as usize / 8];
+        let mask = pic.read_mask() & !(1 << (irq % 8));
+        pic.write_mask(mask);
+    }
+
     pub unsafe fn notify_end_of_interrupt(&mut self, interrupt_id: u8) {
         if self.pics[1].handles_interrupt(interrupt_id) {
             self.pics[1].end_of_interrupt();
@@ -0,0 +1,45 @@
+//! Channel 0 of the Programmable Interval Timer, which raises the timer interrupt.
+
+use crate::port::Port;
+use core::ops::RangeInclusive;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+const BASE_FREQUENCY: u32 = 1_193_182;
+
+/// Frequencies whose divisor fits in 16 bits, up to an interrupt every
+/// 100 µs: a faster timer would leave the CPU little time for anything else.
+pub const FREQUENCIES: RangeInclusive<u32> = 19..=10_000;
+
+/// Channel 0, low then high byte of the divisor, square wave generator.
+const CHANNEL_0_SQUARE_WAVE: u8 = 0x36;
+
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+static FREQUENCY: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_frequency(hz: u32) {
+    assert!(FREQUENCIES.contains(&hz), "invalid PIT frequency {} Hz", hz);
+    let divisor = (BASE_FREQUENCY / hz) as u16;
+    let mut command: Port<u8> = Port::new(0x43);
+    let mut channel_0: Port<u8> = Port::new(0x40);
+    unsafe {
+        command.write(CHANNEL_0_SQUARE_WAVE);
+        channel_0.write(divisor as u8);
+        channel_0.write((divisor >> 8) as u8);
+    }
+    FREQUENCY.store(BASE_FREQUENCY / divisor as u32, Ordering::Relaxed);
+}
+
+/// The frequency the timer actually runs at, which is `BASE_FREQUENCY`
+/// divided by a whole divisor, so it can be a bit off the requested one.
+pub fn frequency() -> u32 {
+    FREQUENCY.load(Ordering::Relaxed)
+}
+
+pub(super) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of timer interrupts since `interrupts::init`.
+pub fn ticks() -> usize {
+    TICKS.load(Ordering::Relaxed)
+}
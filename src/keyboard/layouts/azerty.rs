@@ -0,0 +1,40 @@
+use super::super::{DecodedKey, KeyCode, Modifiers};
+use super::{KeyboardLayout, Us104Key};
+
+/// French AZERTY layout. Characters outside of ASCII are decoded, but the
+/// shell ignores them.
+pub struct AzertyKey;
+
+const KEY_CHARS: [char; 10] = ['à', '&', 'é', '"', '\'', '(', '-', 'è', '_', 'ç'];
+
+impl KeyboardLayout for AzertyKey {
+    fn map_keycode(&self, keycode: KeyCode, modifiers: &Modifiers) -> DecodedKey {
+        let shifted = modifiers.is_shifted();
+        let pick =
+            |normal: char, shift: char| DecodedKey::Unicode(if shifted { shift } else { normal });
+        match keycode {
+            // the keys are named after their place on a US keyboard
+            KeyCode::Q => Us104Key.map_keycode(KeyCode::A, modifiers),
+            KeyCode::A => Us104Key.map_keycode(KeyCode::Q, modifiers),
+            KeyCode::W => Us104Key.map_keycode(KeyCode::Z, modifiers),
+            KeyCode::Z => Us104Key.map_keycode(KeyCode::W, modifiers),
+            KeyCode::OemColon => Us104Key.map_keycode(KeyCode::M, modifiers),
+            k if (KeyCode::Key0..=KeyCode::Key9).contains(&k) => {
+                let num = k as u8 - KeyCode::Key0 as u8;
+                pick(KEY_CHARS[num as usize], (num | 48).into())
+            }
+            KeyCode::M => pick(',', '?'),
+            KeyCode::OemComma => pick(';', '.'),
+            KeyCode::OemPeriod => pick(':', '/'),
+            KeyCode::OemQuestion => pick('!', '§'),
+            KeyCode::OemMinus => pick(')', '°'),
+            KeyCode::OemPlus => pick('=', '+'),
+            KeyCode::OemOpen => pick('^', '¨'),
+            KeyCode::OemClose => pick('$', '£'),
+            KeyCode::OemQuote => pick('ù', '%'),
+            KeyCode::OemPipe => pick('*', 'µ'),
+            KeyCode::OemTilde => DecodedKey::Unicode('²'),
+            k => Us104Key.map_keycode(k, modifiers),
+        }
+    }
+}
@@ -1,9 +1,36 @@
+mod azerty;
 mod us104;
 
+pub use self::azerty::AzertyKey;
 pub use self::us104::Us104Key;
 
 use super::{DecodedKey, KeyCode, Modifiers};
+use crate::cmdline::Keymap;
 
 pub trait KeyboardLayout {
     fn map_keycode(&self, keycode: KeyCode, modifiers: &Modifiers) -> DecodedKey;
 }
+
+/// The layout chosen on the kernel command line.
+pub enum AnyLayout {
+    Us104(Us104Key),
+    Azerty(AzertyKey),
+}
+
+impl AnyLayout {
+    pub const fn new(keymap: Keymap) -> AnyLayout {
+        match keymap {
+            Keymap::Us104 => AnyLayout::Us104(Us104Key),
+            Keymap::Azerty => AnyLayout::Azerty(AzertyKey),
+        }
+    }
+}
+
+impl KeyboardLayout for AnyLayout {
+    fn map_keycode(&self, keycode: KeyCode, modifiers: &Modifiers) -> DecodedKey {
+        match self {
+            AnyLayout::Us104(layout) => layout.map_keycode(keycode, modifiers),
+            AnyLayout::Azerty(layout) => layout.map_keycode(keycode, modifiers),
+        }
+    }
+}
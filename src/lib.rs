@@ -1,52 +1,63 @@
 #![no_std]
-#![feature(abi_x86_interrupt, exclusive_range_pattern)]
+#![feature(abi_x86_interrupt, alloc_error_handler, exclusive_range_pattern)]
 
+mod boot;
+mod cmdline;
+mod gdt;
 mod interrupts;
 mod keyboard;
 mod memory;
+mod panic;
 mod port;
+mod serial;
 mod shell;
+mod signal;
+mod symbols;
+mod tests;
 mod vga_buffer;
 
 #[macro_use]
 extern crate bitflags;
-extern crate multiboot2;
+extern crate alloc;
 
-use crate::memory::frame::FrameAllocator;
-use core::arch::asm;
-use core::panic::PanicInfo;
-
-use multiboot2::{BootInformationHeader, ElfSectionFlags};
+use multiboot::ElfSectionFlags;
 
 #[no_mangle]
-pub extern "C" fn kernel_main(multiboot_header_address: usize) {
-    let boot_info = unsafe {
-        multiboot2::BootInformation::load(multiboot_header_address as *const BootInformationHeader)
-            .unwrap()
-    };
+pub extern "C" fn kernel_main(multiboot_magic: u32, multiboot_header_address: usize) {
+    gdt::init();
+    let boot_info = unsafe { boot::load(multiboot_magic, multiboot_header_address) };
 
-    let our_boot_info = unsafe { memory::multiboot::load(multiboot_header_address) };
+    cmdline::init(boot_info.command_line().unwrap_or(""));
+    if cmdline::params().serial {
+        serial::init();
+    }
 
-    let memory_map_tag = our_boot_info
-        .memory_map_tag()
-        .expect("Memory map tag required");
-    let elf_sections_tag = boot_info.elf_sections().expect("Elf-sections tag required");
+    let memory_map_tag = boot_info.memory_map_tag().expect("Memory map tag required");
+    let elf_sections_tag = boot_info
+        .elf_sections_tag()
+        .expect("Elf-sections tag required");
 
     vga_buffer::WRITER.lock().clear_vga_buffer();
     shell::SHELL.lock().init();
 
-    println!("Memory areas:");
-    for area in memory_map_tag.memory_areas() {
-        println!(
-            "     start: 0x{:x}, length: 0x{:x}",
-            area.base_addr, area.length
+    for warning in cmdline::params().warnings() {
+        println!("cmdline: {}", warning);
+    }
+
+    debug!("Memory areas:");
+    for area in memory_map_tag.all_memory_areas() {
+        debug!(
+            "     start: 0x{:x}, length: 0x{:x}, type: {:?}",
+            area.base_addr,
+            area.length,
+            area.typ()
         );
     }
 
-    println!("kernel sections:");
-    for section in elf_sections_tag {
+    debug!("kernel sections:");
+    for section in elf_sections_tag.sections() {
         if section.flags() != ElfSectionFlags::empty() {
-            println!(
+            debug!(
                 "    addr: 0x{:x}, size: 0x{:x}, flags: 0x{:x}",
                 section.start_address(),
                 section.size(),
@@ -55,63 +66,69 @@ pub extern "C" fn kernel_main(multiboot_header_address: usize) {
         }
     }
 
-    let kernel_start = boot_info
-        .elf_sections()
-        .unwrap()
+    let kernel_start = elf_sections_tag
+        .sections()
         .map(|s| s.start_address())
         .min()
         .unwrap();
 
-    let kernel_end = boot_info
-        .elf_sections()
-        .unwrap()
-        .map(|s| s.start_address() + s.size())
+    let kernel_end = elf_sections_tag
+        .sections()
+        .map(|s| s.end_address())
         .max()
         .unwrap();
 
-    let multiboot_end = multiboot_header_address + boot_info.total_size();
-
-    println!(
+    debug!(
         "kernel_start: {:#x}, kernel_end: {:#x}",
         kernel_start, kernel_end
     );
-    println!(
+    debug!(
         "multiboot_start: {:#x}, multiboot_end: {:#x}",
-        multiboot_header_address, multiboot_end
-    );
-
-    let mut frame_allocator = memory::frame::AreaFrameAllocator::new(
-        kernel_start as usize,
-        kernel_end as usize,
-        multiboot_header_address,
-        multiboot_end,
-        memory_map_tag.memory_areas(),
+        boot_info.start_address(),
+        boot_info.end_address()
     );
 
-    for i in 0..10 {
-        match frame_allocator.allocate_frame() {
-            None => {
-                println!("allocated {} frames", i);
-                break;
+    memory::init(&boot_info);
+    info!("kernel remapped");
+    symbols::init();
+    // the ACPI reclaimable memory is kept until the ACPI tables are read:
+    // nothing parses them yet, so it is only reclaimed by `mmap reclaim`
+    memory::with_memory(|active_table, frame_allocator| {
+        info!(
+            "paging: {}, no-execute {}",
+            active_table.mode_name(),
+            if memory::paging::no_execute_enabled() {
+                "enabled"
+            } else {
+                "disabled"
             }
-            Some(frame) => println!("{:?}", frame),
-        }
-    }
+        );
+        info!(
+            "frames: {} total, {} used, {} free",
+            frame_allocator.total_frames(),
+            frame_allocator.used_frames(),
+            frame_allocator.free_frames()
+        )
+    });
+
     interrupts::init();
-    hlt_loop()
+    idle_loop()
 }
 
-#[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
-    // TODO: Yellow on Black
-    println!("{}", info);
-    hlt_loop()
+#[alloc_error_handler]
+fn alloc_error(layout: core::alloc::Layout) -> ! {
+    panic!(
+        "out of memory: cannot allocate {} bytes aligned on {} bytes",
+        layout.size(),
+        layout.align()
+    )
 }
 
-fn hlt_loop() -> ! {
+/// Delivers the deferred signals once the interrupt handlers that raised
+/// them have returned.
+fn idle_loop() -> ! {
     loop {
-        unsafe {
-            asm!("hlt", options(nomem, nostack, preserves_flags));
-        }
+        signal::dispatch();
+        interrupts::halt_unless(signal::pending);
     }
 }
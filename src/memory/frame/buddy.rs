@@ -0,0 +1,343 @@
+use super::{Frame, FrameAllocator};
+use crate::memory::{Reservation, PAGE_SIZE};
+use core::ops::Range;
+use multiboot::{MemoryArea, MemoryAreaIter};
+use spin::Mutex;
+
+/// Blocks of order `MAX_ORDER` span 4 MiB, the size of a huge page.
+pub const MAX_ORDER: usize = 10;
+
+// Enough frames to cover the whole 32-bit physical address space.
+const MAX_FRAMES: usize = 1 << 20;
+const BITS_PER_WORD: usize = u32::BITS as usize;
+const NB_ORDERS: usize = MAX_ORDER + 1;
+/// Ranges whose frames can be reserved: the kernel, the multiboot
+/// information structure, the symbol tables and the boot modules.
+const MAX_RESERVATIONS: usize = 20;
+
+const fn blocks(order: usize) -> usize {
+    MAX_FRAMES >> order
+}
+
+// index of the first bitmap word of each order
+const WORD_OFFSETS: [usize; NB_ORDERS + 1] = {
+    let mut offsets = [0; NB_ORDERS + 1];
+    let mut order = 0;
+    while order < NB_ORDERS {
+        offsets[order + 1] = offsets[order] + blocks(order) / BITS_PER_WORD;
+        order += 1;
+    }
+    offsets
+};
+const BITMAP_WORDS: usize = WORD_OFFSETS[NB_ORDERS];
+
+// The free blocks of the allocator, and the number of owners of each frame
+// besides the one that allocated it, for the frames shared by copy-on-write
+// mappings. Only used while the allocator is borrowed, but too big to be
+// moved around on the stack with it, so there is only one buddy allocator.
+static BITMAP: Mutex<[u32; BITMAP_WORDS]> = Mutex::new([0; BITMAP_WORDS]);
+static SHARERS: Mutex<[u8; MAX_FRAMES]> = Mutex::new([0; MAX_FRAMES]);
+
+/// Numbers of the frames that are entirely inside `area`, the only usable ones.
+fn area_frames(area: &MemoryArea) -> Range<usize> {
+    let start = area.base_addr.div_ceil(PAGE_SIZE as u64);
+    let end = area.end_address() / PAGE_SIZE as u64;
+    start.min(MAX_FRAMES as u64) as usize..end.min(MAX_FRAMES as u64) as usize
+}
+
+/// Buddy system allocator handing out physically contiguous runs of
+/// `2^order` frames, aligned on their own size.
+///
+/// Every order has its own part of `BITMAP`, with a bit set for each free
+/// block of that order. A block is either free at exactly one order, split into two
+/// buddies of the order below, or allocated.
+pub struct BuddyFrameAllocator {
+    free_blocks: [usize; NB_ORDERS],
+    next_free_word: [usize; NB_ORDERS],
+    total_frames: usize,
+    used_frames: usize,
+    /// The reservations along with their first and last frames.
+    reservations: [Option<(Reservation, Frame, Frame)>; MAX_RESERVATIONS],
+}
+
+impl FrameAllocator for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<Frame> {
+        self.allocate_frames(0)
+    }
+
+    fn deallocate_frame(&mut self, frame: Frame) {
+        self.deallocate_frames(frame, 0)
+    }
+}
+
+impl BuddyFrameAllocator {
+    pub fn new(
+        reservations: impl Iterator<Item = Reservation>,
+        memory_areas: MemoryAreaIter,
+    ) -> BuddyFrameAllocator {
+        let mut allocator = BuddyFrameAllocator {
+            free_blocks: [0; NB_ORDERS],
+            next_free_word: [0; NB_ORDERS],
+            total_frames: 0,
+            used_frames: 0,
+            reservations: [const { None }; MAX_RESERVATIONS],
+        };
+
+        for (i, reservation) in reservations.filter(|r| r.end > r.start).enumerate() {
+            assert!(
+                i < MAX_RESERVATIONS,
+                "more than {} reserved ranges",
+                MAX_RESERVATIONS
+            );
+            allocator.reservations[i] = Some((
+                reservation,
+                Frame::containing_address(reservation.start),
+                Frame::containing_address(reservation.end - 1),
+            ));
+        }
+
+        for area in memory_areas {
+            allocator.add_area(&area);
+        }
+
+        allocator
+    }
+
+    /// Hands the frames of `area` to the allocator, such as an ACPI
+    /// reclaimable area once the ACPI tables are read. The area must not
+    /// overlap with the areas already added.
+    pub fn add_area(&mut self, area: &MemoryArea) {
+        for number in area_frames(area) {
+            let frame = Frame { number };
+            if self.is_reserved(&frame) {
+                self.used_frames += 1;
+            } else {
+                self.free_block(number, 0);
+            }
+            self.total_frames += 1;
+        }
+    }
+
+    /// Removes the reservation named `name`, if any, and frees its frames
+    /// that are in `memory_areas`, the areas given to the allocator.
+    pub fn release(&mut self, name: &str, memory_areas: impl Iterator<Item = MemoryArea>) {
+        let Some((_, first, last)) = self
+            .reservations
+            .iter_mut()
+            .find(|entry| {
+                entry
+                    .as_ref()
+                    .is_some_and(|(reservation, _, _)| reservation.name == name)
+            })
+            .and_then(Option::take)
+        else {
+            return;
+        };
+        for area in memory_areas {
+            let frames = area_frames(&area);
+            for number in frames.start.max(first.number)..frames.end.min(last.number + 1) {
+                // the frames at the ends may be shared with other reservations
+                if !self.is_reserved(&Frame { number }) {
+                    self.free_block(number, 0);
+                    self.used_frames -= 1;
+                }
+            }
+        }
+    }
+
+    /// The ranges of physical memory that are never handed out.
+    pub fn reservations(&self) -> [Option<Reservation>; MAX_RESERVATIONS] {
+        self.reservations
+            .each_ref()
+            .map(|entry| entry.as_ref().map(|(reservation, _, _)| *reservation))
+    }
+
+    /// Allocates `2^order` contiguous frames and returns the first one.
+    pub fn allocate_frames(&mut self, order: usize) -> Option<Frame> {
+        assert!(order <= MAX_ORDER, "order {} is too big", order);
+
+        let mut current_order = (order..NB_ORDERS).find(|&o| self.free_blocks[o] != 0)?;
+        let mut block = self.find_free_block(current_order);
+        self.clear_free(current_order, block);
+
+        // split the block until it has the requested size, freeing the upper halves
+        while current_order > order {
+            current_order -= 1;
+            block <<= 1;
+            self.set_free(current_order, block | 1);
+        }
+
+        self.used_frames += 1 << order;
+        Some(Frame {
+            number: block << order,
+        })
+    }
+
+    /// Frees `2^order` frames previously returned by `allocate_frames(order)`.
+    /// A shared frame is only freed once all its owners have deallocated it.
+    pub fn deallocate_frames(&mut self, frame: Frame, order: usize) {
+        assert!(order <= MAX_ORDER, "order {} is too big", order);
+        assert!(
+            frame.number + (1 << order) <= MAX_FRAMES,
+            "{:?} is outside of physical memory",
+            frame
+        );
+        if order == 0 {
+            let mut sharers = SHARERS.lock();
+            if sharers[frame.number] > 0 {
+                sharers[frame.number] -= 1;
+                return;
+            }
+        }
+        assert!(
+            frame.number % (1 << order) == 0,
+            "{:?} is not aligned on order {}",
+            frame,
+            order
+        );
+        let last = Frame {
+            number: frame.number + (1 << order) - 1,
+        };
+        if let Some((reservation, _, _)) = self
+            .reservations
+            .iter()
+            .flatten()
+            .find(|(_, start, end)| &frame <= end && &last >= start)
+        {
+            panic!("{:?} is reserved for {}", frame, reservation.name);
+        }
+        assert!(
+            !self.is_any_free(&frame, order),
+            "double free of {:?}",
+            frame
+        );
+
+        self.free_block(frame.number >> order, order);
+        self.used_frames -= 1 << order;
+    }
+
+    /// Adds an owner to an allocated frame, which then needs one more
+    /// `deallocate_frame` to be freed.
+    pub fn share_frame(&mut self, frame: &Frame) {
+        assert!(
+            frame.number < MAX_FRAMES
+                && (0..NB_ORDERS).all(|o| !self.is_free(o, frame.number >> o)),
+            "{:?} is not allocated",
+            frame
+        );
+        let mut sharers = SHARERS.lock();
+        sharers[frame.number] = sharers[frame.number]
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("{:?} is shared too many times", frame));
+    }
+
+    /// Number of owners of an allocated frame.
+    pub fn references(&self, frame: &Frame) -> usize {
+        SHARERS.lock()[frame.number] as usize + 1
+    }
+
+    /// Number of usable frames reported by the memory map, or added later.
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    /// Number of usable frames that are allocated or reserved.
+    pub fn used_frames(&self) -> usize {
+        self.used_frames
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.total_frames - self.used_frames
+    }
+
+    /// Number of free blocks of each order.
+    pub fn free_blocks(&self) -> &[usize; NB_ORDERS] {
+        &self.free_blocks
+    }
+
+    /// Whether any of the `2^order` frames from `frame` is in a free block,
+    /// of the same order, of a bigger one that contains them, or of a
+    /// smaller one among them.
+    pub fn is_any_free(&self, frame: &Frame, order: usize) -> bool {
+        let end = frame.number + (1 << order);
+        (order..NB_ORDERS).any(|o| self.is_free(o, frame.number >> o))
+            || (0..order).any(|o| self.is_any_block_free(o, frame.number >> o..end >> o))
+    }
+
+    fn is_reserved(&self, frame: &Frame) -> bool {
+        self.reservations
+            .iter()
+            .flatten()
+            .any(|(_, start, end)| frame >= start && frame <= end)
+    }
+
+    /// Marks the block as free, merging it with its buddy as long as possible.
+    fn free_block(&mut self, mut block: usize, mut order: usize) {
+        while order < MAX_ORDER && self.is_free(order, block ^ 1) {
+            self.clear_free(order, block ^ 1);
+            block >>= 1;
+            order += 1;
+        }
+        self.set_free(order, block);
+    }
+
+    fn find_free_block(&mut self, order: usize) -> usize {
+        let first_word = WORD_OFFSETS[order];
+        let nb_words = WORD_OFFSETS[order + 1] - first_word;
+        let bitmap = BITMAP.lock();
+        for i in 0..nb_words {
+            let word_idx = (self.next_free_word[order] + i) % nb_words;
+            let word = bitmap[first_word + word_idx];
+            if word != 0 {
+                self.next_free_word[order] = word_idx;
+                return word_idx * BITS_PER_WORD + word.trailing_zeros() as usize;
+            }
+        }
+        unreachable!("free block count of order {} is out of sync", order)
+    }
+
+    fn bit(order: usize, block: usize) -> (usize, u32) {
+        (
+            WORD_OFFSETS[order] + block / BITS_PER_WORD,
+            1 << (block % BITS_PER_WORD),
+        )
+    }
+
+    fn is_free(&self, order: usize, block: usize) -> bool {
+        let (word, mask) = Self::bit(order, block);
+        BITMAP.lock()[word] & mask != 0
+    }
+
+    /// Whether any block of `order` in `blocks` is free, a bitmap word at a time.
+    fn is_any_block_free(&self, order: usize, blocks: Range<usize>) -> bool {
+        let bitmap = BITMAP.lock();
+        let mut block = blocks.start;
+        while block < blocks.end {
+            let (word, _) = Self::bit(order, block);
+            let shift = block % BITS_PER_WORD;
+            let count = (blocks.end - block).min(BITS_PER_WORD - shift);
+            let mask = (u32::MAX >> (BITS_PER_WORD - count)) << shift;
+            if bitmap[word] & mask != 0 {
+                return true;
+            }
+            block += count;
+        }
+        false
+    }
+
+    fn set_free(&mut self, order: usize, block: usize) {
+        let (word, mask) = Self::bit(order, block);
+        BITMAP.lock()[word] |= mask;
+        self.free_blocks[order] += 1;
+        let word_idx = block / BITS_PER_WORD;
+        if word_idx < self.next_free_word[order] {
+            self.next_free_word[order] = word_idx;
+        }
+    }
+
+    fn clear_free(&mut self, order: usize, block: usize) {
+        let (word, mask) = Self::bit(order, block);
+        BITMAP.lock()[word] &= !mask;
+        self.free_blocks[order] -= 1;
+    }
+}
@@ -0,0 +1,35 @@
+mod buddy;
+
+pub use self::buddy::BuddyFrameAllocator;
+
+use super::{paging::PhysicalAddress, PAGE_SIZE};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Frame {
+    number: usize,
+}
+
+impl Frame {
+    pub fn containing_address(address: usize) -> Frame {
+        Frame {
+            number: address / PAGE_SIZE,
+        }
+    }
+
+    pub fn start_address(&self) -> PhysicalAddress {
+        self.number * PAGE_SIZE
+    }
+
+    // Frame deliberately doesn't implement Clone, so that a frame can't
+    // accidentally be mapped or freed twice.
+    pub(super) fn clone(&self) -> Frame {
+        Frame {
+            number: self.number,
+        }
+    }
+}
+
+pub trait FrameAllocator {
+    fn allocate_frame(&mut self) -> Option<Frame>;
+    fn deallocate_frame(&mut self, frame: Frame);
+}
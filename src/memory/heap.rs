@@ -0,0 +1,313 @@
+//! Kernel heap: a first-fit allocator over a contiguous virtual region that
+//! grows page by page from `HEAP_START`, lazily backed by frames from the frame allocator.
+//! Small allocations are forwarded to the slab allocator.
+
+use super::paging::VirtualAddress;
+use super::{align_up, free_frames, lazy, release_range, slab, PAGE_SIZE};
+use crate::interrupts;
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::ptr::null_mut;
+use spin::Mutex;
+
+pub const HEAP_START: VirtualAddress = 0xd000_0000;
+pub const HEAP_MAX_SIZE: usize = 0x1000_0000; // 256 MiB
+
+const ALIGN: usize = 8;
+const HEADER_SIZE: usize = size_of::<Header>();
+const MIN_BLOCK_SIZE: usize = align_up(size_of::<FreeBlock>(), ALIGN);
+
+const USED_MAGIC: usize = 0x600d_b10c;
+const FREE_MAGIC: usize = 0xf4ee_b10c;
+
+/// Placed right before every block returned by the allocator.
+#[repr(C)]
+struct Header {
+    size: usize, // including the header
+    magic: usize,
+}
+
+/// Free blocks are kept in a singly linked list sorted by address,
+/// so that neighbours can be merged when a block is freed.
+#[repr(C)]
+struct FreeBlock {
+    header: Header,
+    next: *mut FreeBlock,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub size: usize,
+    pub free: usize,
+    pub free_blocks: usize,
+    pub largest_free_block: usize,
+}
+
+struct Heap {
+    brk: VirtualAddress,
+    free_list: *mut FreeBlock,
+}
+
+// The heap only hands out pointers into its own region, which is mapped in
+// every context the kernel runs in.
+unsafe impl Send for Heap {}
+
+static HEAP: Mutex<Heap> = Mutex::new(Heap {
+    brk: HEAP_START,
+    free_list: null_mut(),
+});
+
+impl Heap {
+    unsafe fn allocate(&mut self, size: usize, align: usize) -> *mut u8 {
+        let align = align.max(ALIGN);
+        let size = align_up(size.max(1), ALIGN);
+
+        loop {
+            if let Some(ptr) = self.find_fit(size, align) {
+                return ptr;
+            }
+            // enough for the block and the worst case alignment padding
+            let needed = HEADER_SIZE + size + 2 * align;
+            if self.brk(needed as isize).is_none() {
+                return null_mut();
+            }
+        }
+    }
+
+    unsafe fn find_fit(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+        let mut prev: *mut *mut FreeBlock = &mut self.free_list;
+        while !(*prev).is_null() {
+            let block = *prev;
+            let start = block as usize;
+            let end = start + (*block).header.size;
+
+            let mut payload = align_up(start + HEADER_SIZE, align);
+            // the padding before the block must be big enough to stay in the free list
+            while payload - HEADER_SIZE != start && payload - HEADER_SIZE - start < MIN_BLOCK_SIZE {
+                payload += align;
+            }
+            let block_start = payload - HEADER_SIZE;
+            let mut block_end = payload + size;
+
+            if block_end <= end {
+                *prev = (*block).next;
+                if end - block_end >= MIN_BLOCK_SIZE {
+                    Self::link_free_block(prev, block_end, end - block_end);
+                } else {
+                    block_end = end;
+                }
+                if block_start > start {
+                    Self::link_free_block(prev, start, block_start - start);
+                }
+
+                let header = block_start as *mut Header;
+                (*header).size = block_end - block_start;
+                (*header).magic = USED_MAGIC;
+                return Some(payload as *mut u8);
+            }
+
+            prev = &mut (*block).next;
+        }
+        None
+    }
+
+    unsafe fn free(&mut self, ptr: *mut u8) {
+        let header = Self::header(ptr);
+        (*header).magic = FREE_MAGIC;
+        self.insert_free_block(header as usize, (*header).size);
+    }
+
+    unsafe fn header(ptr: *const u8) -> *mut Header {
+        let address = ptr as usize;
+        assert!(
+            address >= HEAP_START + HEADER_SIZE && address < HEAP_START + HEAP_MAX_SIZE,
+            "{:p} is not a heap pointer",
+            ptr
+        );
+        let header = (address - HEADER_SIZE) as *mut Header;
+        match (*header).magic {
+            USED_MAGIC => header,
+            FREE_MAGIC => panic!("double free of {:p}", ptr),
+            _ => panic!("heap corruption at {:p}", ptr),
+        }
+    }
+
+    /// Adds `[start, start + size)` to the free list, merging it with its neighbours.
+    unsafe fn insert_free_block(&mut self, start: usize, size: usize) {
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut next = self.free_list;
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let mut block = start as *mut FreeBlock;
+        (*block).header = Header {
+            size,
+            magic: FREE_MAGIC,
+        };
+        (*block).next = next;
+
+        if !prev.is_null() && prev as usize + (*prev).header.size == start {
+            (*prev).header.size += size;
+            block = prev;
+        } else if prev.is_null() {
+            self.free_list = block;
+        } else {
+            (*prev).next = block;
+        }
+
+        if !next.is_null() && block as usize + (*block).header.size == next as usize {
+            (*block).header.size += (*next).header.size;
+            (*block).next = (*next).next;
+        } else {
+            (*block).next = next;
+        }
+    }
+
+    unsafe fn link_free_block(prev: *mut *mut FreeBlock, start: usize, size: usize) {
+        let block = start as *mut FreeBlock;
+        (*block).header = Header {
+            size,
+            magic: FREE_MAGIC,
+        };
+        (*block).next = *prev;
+        *prev = block;
+    }
+
+    /// Moves the end of the heap by `increment` bytes, rounded up to whole pages.
+    /// The heap can only shrink by giving back free memory at its end.
+    unsafe fn brk(&mut self, increment: isize) -> Option<VirtualAddress> {
+        let old_brk = self.brk;
+        let size = align_up(increment.unsigned_abs(), PAGE_SIZE);
+
+        if increment > 0 {
+            if size > HEAP_START + HEAP_MAX_SIZE - old_brk {
+                return None;
+            }
+            if size / PAGE_SIZE > free_frames() {
+                return None;
+            }
+            let new_brk = old_brk + size;
+            // the new pages are mapped when the free block header is written
+            lazy::resize(HEAP_START, new_brk);
+            self.brk = new_brk;
+            self.insert_free_block(old_brk, size);
+        } else if increment < 0 {
+            let new_brk = old_brk.checked_sub(size)?;
+            if new_brk < HEAP_START {
+                return None;
+            }
+            self.release_tail(new_brk)?;
+            lazy::resize(HEAP_START, new_brk);
+            release_range(new_brk, old_brk);
+            self.brk = new_brk;
+        }
+
+        Some(old_brk)
+    }
+
+    unsafe fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            size: self.brk - HEAP_START,
+            free: 0,
+            free_blocks: 0,
+            largest_free_block: 0,
+        };
+        let mut block = self.free_list;
+        while !block.is_null() {
+            let size = (*block).header.size;
+            stats.free += size;
+            stats.free_blocks += 1;
+            stats.largest_free_block = stats.largest_free_block.max(size);
+            block = (*block).next;
+        }
+        stats
+    }
+
+    /// Removes `[new_brk, brk)` from the last free block, if it is entirely free.
+    unsafe fn release_tail(&mut self, new_brk: VirtualAddress) -> Option<()> {
+        let mut prev: *mut *mut FreeBlock = &mut self.free_list;
+        while !(*prev).is_null() && !(**prev).next.is_null() {
+            prev = &mut (**prev).next;
+        }
+        let last = *prev;
+        if last.is_null() || last as usize + (*last).header.size != self.brk {
+            return None;
+        }
+
+        let start = last as usize;
+        if start == new_brk {
+            *prev = null_mut();
+        } else if start < new_brk && new_brk - start >= MIN_BLOCK_SIZE {
+            (*last).header.size = new_brk - start;
+        } else {
+            return None;
+        }
+        Some(())
+    }
+}
+
+pub(super) fn init() {
+    assert!(lazy::register("heap", HEAP_START, HEAP_START));
+}
+
+/// Small sizes are served by the `kmalloc-*` slab caches, the rest by the heap.
+fn allocate(size: usize, align: usize) -> *mut u8 {
+    match slab::kmalloc_cache(size, align) {
+        Some(cache) => cache.allocate(),
+        None => interrupts::without_interrupts(|| unsafe { HEAP.lock().allocate(size, align) }),
+    }
+}
+
+/// Allocates `size` bytes on the kernel heap.
+/// Returns a null pointer if the kernel is out of memory.
+pub fn kmalloc(size: usize) -> *mut u8 {
+    allocate(size, ALIGN)
+}
+
+/// Frees a block returned by `kmalloc`. Like `free`, does nothing for a
+/// null pointer, which `kmalloc` returns when it fails.
+pub unsafe fn kfree(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+    if slab::contains(ptr) {
+        return slab::free(ptr);
+    }
+    interrupts::without_interrupts(|| HEAP.lock().free(ptr))
+}
+
+/// Returns the usable size of a block returned by `kmalloc`,
+/// which can be bigger than the requested size.
+pub unsafe fn ksize(ptr: *const u8) -> usize {
+    if slab::contains(ptr) {
+        return slab::object_size(ptr);
+    }
+    interrupts::without_interrupts(|| (*Heap::header(ptr)).size - HEADER_SIZE)
+}
+
+/// Grows or shrinks the kernel heap by `increment` bytes, rounded up to
+/// whole pages, and returns the previous end of the heap.
+pub fn kbrk(increment: isize) -> Option<VirtualAddress> {
+    interrupts::without_interrupts(|| unsafe { HEAP.lock().brk(increment) })
+}
+
+pub fn stats() -> HeapStats {
+    interrupts::without_interrupts(|| unsafe { HEAP.lock().stats() })
+}
+
+pub struct KernelAllocator;
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        allocate(layout.size(), layout.align())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        kfree(ptr)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator;
@@ -0,0 +1,99 @@
+//! Lazily allocated regions: their pages are only backed by a zeroed frame the
+//! first time they are touched, from the page fault handler.
+//!
+//! The page fault handler locks the regions and then the page table and frame
+//! allocator, so none of them may be held while touching a lazy region: the
+//! kernel panics if the page table or the frame allocator are.
+
+use super::paging::{EntryFlags, Page, VirtualAddress};
+use super::{with_memory_on_fault, FRAMES_TO_MAP_PAGE, PAGE_SIZE};
+use crate::interrupts;
+use core::ptr::write_bytes;
+use spin::Mutex;
+
+const MAX_REGIONS: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LazyRegion {
+    pub name: &'static str,
+    pub start: VirtualAddress,
+    pub end: VirtualAddress,
+}
+
+// Not a `Vec`, since the heap itself is lazily allocated.
+static REGIONS: Mutex<[Option<LazyRegion>; MAX_REGIONS]> = Mutex::new([None; MAX_REGIONS]);
+
+/// Registers `[start, end)` as lazily allocated.
+/// Returns `false` if there is no room for another region.
+pub fn register(name: &'static str, start: VirtualAddress, end: VirtualAddress) -> bool {
+    assert!(start % PAGE_SIZE == 0 && end % PAGE_SIZE == 0);
+    interrupts::without_interrupts(|| {
+        let mut regions = REGIONS.lock();
+        match regions.iter_mut().find(|region| region.is_none()) {
+            Some(slot) => {
+                *slot = Some(LazyRegion { name, start, end });
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Forgets the region starting at `start`. Its pages that were touched are
+/// still mapped and must be released with `release_range`.
+pub fn unregister(start: VirtualAddress) {
+    interrupts::without_interrupts(|| {
+        *find_slot(&mut REGIONS.lock(), start) = None;
+    })
+}
+
+/// Moves the end of the region starting at `start`.
+pub fn resize(start: VirtualAddress, end: VirtualAddress) {
+    assert!(end % PAGE_SIZE == 0);
+    interrupts::without_interrupts(|| {
+        find_slot(&mut REGIONS.lock(), start).as_mut().unwrap().end = end;
+    })
+}
+
+fn find_slot(
+    regions: &mut [Option<LazyRegion>; MAX_REGIONS],
+    start: VirtualAddress,
+) -> &mut Option<LazyRegion> {
+    regions
+        .iter_mut()
+        .find(|region| region.is_some_and(|region| region.start == start))
+        .unwrap_or_else(|| panic!("no lazy region starts at {:#x}", start))
+}
+
+/// The region containing `address`, if any.
+pub fn find(address: VirtualAddress) -> Option<LazyRegion> {
+    interrupts::without_interrupts(|| {
+        REGIONS
+            .lock()
+            .iter()
+            .flatten()
+            .find(|region| (region.start..region.end).contains(&address))
+            .copied()
+    })
+}
+
+/// Maps a zeroed frame at the page containing `address` if it belongs to a
+/// lazy region, which must be the reason why it is not mapped.
+pub fn map_on_fault(address: VirtualAddress) -> bool {
+    if find(address).is_none() {
+        return false;
+    }
+    let page = Page::containing_address(address);
+    with_memory_on_fault(address, |active_table, frame_allocator| {
+        if frame_allocator.free_frames() < FRAMES_TO_MAP_PAGE {
+            panic!("out of memory while touching {:#x}", address);
+        }
+        active_table.map(
+            page,
+            EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE,
+            frame_allocator,
+        );
+    });
+    unsafe { write_bytes(page.start_address() as *mut u8, 0, PAGE_SIZE) };
+    true
+}
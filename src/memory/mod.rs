@@ -1,5 +1,383 @@
 pub mod frame;
-pub mod multiboot;
+pub mod heap;
+pub mod lazy;
 pub mod paging;
+pub mod slab;
+pub mod vmalloc;
+
+use self::frame::BuddyFrameAllocator;
+use self::paging::{
+    ioremap, ActivePageTable, CacheMode, EntryFlags, InactivePageTable, Page, PhysicalAddress,
+    TemporaryPage, VirtualAddress,
+};
+use crate::cmdline::params;
+use crate::{interrupts, symbols, vga_buffer};
+use core::ops::Range;
+use core::ptr::addr_of;
+use core::sync::atomic::{AtomicBool, Ordering};
+use multiboot::{BootInformation, MemoryAreaType};
+use spin::Mutex;
 
 pub const PAGE_SIZE: usize = 4096;
+
+/// Start of kernel space: the kernel is linked at `KERNEL_OFFSET` plus its physical
+/// address, and everything below is left to user space.
+pub const KERNEL_OFFSET: VirtualAddress = 0xc000_0000;
+
+/// Physical address of an address of the kernel image or of the multiboot
+/// information structure. Only the boot trampoline is linked below `KERNEL_OFFSET`.
+pub const fn kernel_physical_address(address: VirtualAddress) -> PhysicalAddress {
+    if address >= KERNEL_OFFSET {
+        address - KERNEL_OFFSET
+    } else {
+        address
+    }
+}
+
+/// Page used by the address space functions to reach the page directories
+/// of inactive tables, under the lock of the active table.
+const TEMPORARY_PAGE: VirtualAddress = 0xfe00_0000;
+
+/// Unmapped space left after every area of the vmalloc region and of the
+/// ioremap window, so that overflowing an area faults instead of silently
+/// reaching the next one.
+pub const GUARD_SIZE: usize = PAGE_SIZE;
+
+/// Number of free frames needed to map one page: one for the page, and one
+/// for a new page table if the page is the first of its table.
+pub const FRAMES_TO_MAP_PAGE: usize = 2;
+
+/// Rounds `address` up to a multiple of `align`, which must be a power of two.
+pub const fn align_up(address: usize, align: usize) -> usize {
+    (address + align - 1) & !(align - 1)
+}
+
+static BOOT_INFO: Mutex<Option<BootInformation<'static>>> = Mutex::new(None);
+static FRAME_ALLOCATOR: Mutex<Option<BuddyFrameAllocator>> = Mutex::new(None);
+static ACTIVE_TABLE: Mutex<Option<ActivePageTable>> = Mutex::new(None);
+
+static ACPI_RECLAIMED: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    static stack_guard: u8;
+    static stack_bottom: u8;
+    static stack_top: u8;
+}
+
+/// The page under the kernel stack, which is left unmapped so that an
+/// overflow of the stack faults. The stack probes of `kfs.json` make the
+/// functions with frames bigger than a page touch it instead of skipping it.
+pub fn stack_guard_page() -> VirtualAddress {
+    addr_of!(stack_guard) as VirtualAddress
+}
+
+/// The boot stack, which the kernel runs on.
+pub fn kernel_stack() -> Range<VirtualAddress> {
+    addr_of!(stack_bottom) as VirtualAddress..addr_of!(stack_top) as VirtualAddress
+}
+
+/// Physical addresses of the first byte and of the byte after the last one
+/// of the loaded sections of the kernel.
+fn kernel_range(boot_info: &BootInformation) -> (PhysicalAddress, PhysicalAddress) {
+    let elf_sections_tag = boot_info
+        .elf_sections_tag()
+        .expect("Elf-sections tag required");
+    let sections = || elf_sections_tag.sections().filter(|s| s.is_allocated());
+
+    let start = sections()
+        .map(|s| kernel_physical_address(s.start_address() as usize))
+        .min()
+        .unwrap();
+    let end = sections()
+        .map(|s| kernel_physical_address(s.end_address() as usize))
+        .max()
+        .unwrap();
+    (start, end)
+}
+
+pub fn init(boot_info: &BootInformation<'static>) {
+    let memory_map_tag = boot_info.memory_map_tag().expect("Memory map tag required");
+
+    let mut frame_allocator =
+        BuddyFrameAllocator::new(boot_reservations(*boot_info), memory_map_tag.memory_areas());
+    let mut active_table = paging::remap_the_kernel(&mut frame_allocator, boot_info, params().pae);
+    // its frame stays reserved with the rest of the kernel
+    active_table.unmap_frame(Page::containing_address(stack_guard_page()));
+
+    *BOOT_INFO.lock() = Some(*boot_info);
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+    *ACTIVE_TABLE.lock() = Some(active_table);
+    vga_buffer::map_buffer();
+
+    heap::init();
+}
+
+/// The multiboot information structure, which stays mapped after `init`.
+pub fn boot_info() -> BootInformation<'static> {
+    BOOT_INFO.lock().expect("memory is not initialized")
+}
+
+/// A file loaded by the bootloader next to the kernel, such as an initrd.
+/// Its frames are reserved, but it is not mapped.
+#[derive(Debug, Clone, Copy)]
+pub struct Module {
+    /// First word of the command line, given after the path in `grub.cfg`.
+    pub name: &'static str,
+    pub command_line: &'static str,
+    pub start: PhysicalAddress,
+    /// Physical address right after the last byte of the module.
+    pub end: PhysicalAddress,
+}
+
+impl Module {
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maps the first `len` bytes of the module, or all of it if it is
+    /// shorter, in the ioremap window. The mapping is never released unless
+    /// the start of the slice is given to `iounmap`.
+    pub fn map(&self, len: usize) -> &'static [u8] {
+        let len = len.min(self.len());
+        if len == 0 {
+            return &[];
+        }
+        let address = ioremap(self.start, len, CacheMode::WriteBack);
+        unsafe { core::slice::from_raw_parts(address as *const u8, len) }
+    }
+}
+
+/// The boot modules, in the order of `grub.cfg`.
+pub fn modules() -> impl Iterator<Item = Module> {
+    boot_modules(boot_info())
+}
+
+fn boot_modules(boot_info: BootInformation<'static>) -> impl Iterator<Item = Module> {
+    boot_info.module_tags().map(|tag| Module {
+        name: tag.command_line.split_whitespace().next().unwrap_or(""),
+        command_line: tag.command_line,
+        start: tag.start as PhysicalAddress,
+        end: tag.end as PhysicalAddress,
+    })
+}
+
+/// A range of physical memory that the frame allocator never hands out.
+#[derive(Debug, Clone, Copy)]
+pub struct Reservation {
+    pub name: &'static str,
+    pub start: PhysicalAddress,
+    /// Physical address right after the last byte of the reservation.
+    pub end: PhysicalAddress,
+}
+
+/// The reserved ranges: the kernel, the multiboot information structure,
+/// the boot modules and, until `symbols::init` has read them, the symbol
+/// tables of the kernel.
+pub fn reservations() -> impl Iterator<Item = Reservation> {
+    with_memory(|_, frame_allocator| frame_allocator.reservations())
+        .into_iter()
+        .flatten()
+}
+
+/// Gives the frames of the reservation named `name` to the frame allocator,
+/// once its content is not needed anymore.
+pub fn release_reservation(name: &str) {
+    let memory_map_tag = boot_info()
+        .memory_map_tag()
+        .expect("Memory map tag required");
+    // the areas that the frame allocator was given
+    let areas = memory_map_tag
+        .all_memory_areas()
+        .filter(|area| match area.typ() {
+            MemoryAreaType::Available => true,
+            MemoryAreaType::AcpiReclaimable => acpi_memory_reclaimed(),
+            _ => false,
+        });
+    with_memory(|_, frame_allocator| frame_allocator.release(name, areas))
+}
+
+fn boot_reservations(boot_info: BootInformation<'static>) -> impl Iterator<Item = Reservation> {
+    let (kernel_start, kernel_end) = kernel_range(&boot_info);
+    let kernel = Reservation {
+        name: "kernel",
+        start: kernel_start,
+        end: kernel_end,
+    };
+    let multiboot = Reservation {
+        name: "multiboot",
+        start: kernel_physical_address(boot_info.start_address()),
+        end: kernel_physical_address(boot_info.end_address()),
+    };
+    let symbols = symbols::sections(&boot_info)
+        .into_iter()
+        .flat_map(|(symbols, strings)| {
+            [("symtab", symbols), ("strtab", strings)].map(|(name, section)| Reservation {
+                name,
+                start: section.start_address() as PhysicalAddress,
+                end: section.end_address() as PhysicalAddress,
+            })
+        });
+    let modules = boot_modules(boot_info).map(|module| Reservation {
+        name: module.name,
+        start: module.start,
+        end: module.end,
+    });
+    [kernel, multiboot]
+        .into_iter()
+        .chain(symbols)
+        .chain(modules)
+}
+
+/// Gives the ACPI reclaimable areas of the memory map to the frame
+/// allocator, once the ACPI tables they hold have been read. The tables,
+/// reached through the RSDT or XSDT address of the RSDP, must not be read
+/// afterwards. Does nothing if they were already reclaimed.
+pub fn reclaim_acpi_memory() {
+    if ACPI_RECLAIMED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let memory_map_tag = boot_info()
+        .memory_map_tag()
+        .expect("Memory map tag required");
+    with_memory(|_, frame_allocator| {
+        for area in memory_map_tag.all_memory_areas() {
+            if area.typ() == MemoryAreaType::AcpiReclaimable {
+                frame_allocator.add_area(&area);
+            }
+        }
+    })
+}
+
+/// Whether the ACPI reclaimable areas are now usable RAM.
+pub fn acpi_memory_reclaimed() -> bool {
+    ACPI_RECLAIMED.load(Ordering::Relaxed)
+}
+
+/// Runs `f` with the active page table and the frame allocator.
+/// Must not be called again from inside `f`.
+pub fn with_memory<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut ActivePageTable, &mut BuddyFrameAllocator) -> R,
+{
+    interrupts::without_interrupts(|| {
+        let mut active_table = ACTIVE_TABLE.lock();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        f(
+            active_table.as_mut().expect("memory is not initialized"),
+            frame_allocator.as_mut().expect("memory is not initialized"),
+        )
+    })
+}
+
+/// Like `with_memory`, for the page fault handler resolving a fault at
+/// `address`. The fault may come from code holding the memory lock, such as
+/// a heap allocation inside `with_memory`, which would otherwise spin forever.
+fn with_memory_on_fault<F, R>(address: VirtualAddress, f: F) -> R
+where
+    F: FnOnce(&mut ActivePageTable, &mut BuddyFrameAllocator) -> R,
+{
+    interrupts::without_interrupts(|| {
+        let (Some(mut active_table), Some(mut frame_allocator)) =
+            (ACTIVE_TABLE.try_lock(), FRAME_ALLOCATOR.try_lock())
+        else {
+            panic!("page fault at {:#x} while the memory lock is held", address);
+        };
+        f(
+            active_table.as_mut().expect("memory is not initialized"),
+            frame_allocator.as_mut().expect("memory is not initialized"),
+        )
+    })
+}
+
+pub fn free_frames() -> usize {
+    with_memory(|_, frame_allocator| frame_allocator.free_frames())
+}
+
+/// Maps every page of `[start, end)` to a free frame.
+/// Nothing is mapped and `false` is returned if there aren't enough free frames.
+pub fn map_range(start: VirtualAddress, end: VirtualAddress, flags: EntryFlags) -> bool {
+    with_memory(|active_table, frame_allocator| {
+        for address in (start..end).step_by(PAGE_SIZE) {
+            if frame_allocator.free_frames() < FRAMES_TO_MAP_PAGE {
+                for address in (start..address).step_by(PAGE_SIZE) {
+                    active_table.unmap(Page::containing_address(address), frame_allocator);
+                }
+                return false;
+            }
+            active_table.map(Page::containing_address(address), flags, frame_allocator);
+        }
+        true
+    })
+}
+
+/// Unmaps every page of `[start, end)` and frees the frames they were mapped to.
+pub fn unmap_range(start: VirtualAddress, end: VirtualAddress) {
+    with_memory(|active_table, frame_allocator| {
+        for address in (start..end).step_by(PAGE_SIZE) {
+            active_table.unmap(Page::containing_address(address), frame_allocator);
+        }
+    })
+}
+
+/// Unmaps the pages of `[start, end)` that are mapped, such as the touched
+/// pages of a lazy region, and frees the frames they were mapped to.
+pub fn release_range(start: VirtualAddress, end: VirtualAddress) {
+    with_memory(|active_table, frame_allocator| {
+        for address in (start..end).step_by(PAGE_SIZE) {
+            let page = Page::containing_address(address);
+            if active_table.translate_page(page).is_some() {
+                active_table.unmap(page, frame_allocator);
+            }
+        }
+    })
+}
+
+/// Resolves a write fault at `address`, returns `false` if the page is not copy-on-write.
+pub fn resolve_copy_on_write(address: VirtualAddress) -> bool {
+    with_memory_on_fault(address, |active_table, frame_allocator| {
+        active_table.resolve_copy_on_write(
+            Page::containing_address(address),
+            &mut temporary_page(),
+            frame_allocator,
+        )
+    })
+}
+
+fn temporary_page() -> TemporaryPage {
+    TemporaryPage::new(Page::containing_address(TEMPORARY_PAGE))
+}
+
+/// Creates an address space with an empty user space, sharing the kernel space
+/// with every other address space.
+pub fn new_address_space() -> InactivePageTable {
+    with_memory(|active_table, frame_allocator| {
+        InactivePageTable::new(active_table, &mut temporary_page(), frame_allocator)
+    })
+}
+
+/// Like `with_memory`, but `f` edits the page tables of `table` instead of the
+/// active ones.
+pub fn with_address_space<F, R>(table: &InactivePageTable, f: F) -> R
+where
+    F: FnOnce(&mut ActivePageTable, &mut BuddyFrameAllocator) -> R,
+{
+    with_memory(|active_table, frame_allocator| {
+        active_table.with(table, &mut temporary_page(), frame_allocator, f)
+    })
+}
+
+/// Makes `table` the active address space and returns the previous one.
+pub fn switch_address_space(table: InactivePageTable) -> InactivePageTable {
+    with_memory(|active_table, _| active_table.switch(table))
+}
+
+/// Frees an inactive address space along with its user space.
+pub fn free_address_space(table: InactivePageTable) {
+    with_memory(|active_table, frame_allocator| {
+        active_table.free_table(table, &mut temporary_page(), frame_allocator)
+    })
+}
@@ -1,41 +1,80 @@
+use super::mode::{self, PagingMode};
 use crate::memory::frame::Frame;
+use multiboot::{ElfSection, ElfSectionFlags};
 
-pub struct Entry(usize);
+#[repr(transparent)]
+pub struct Entry<M: PagingMode>(M::Raw);
 
 bitflags! {
-    pub struct EntryFlags: usize {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct EntryFlags: u64 {
         const PRESENT =         1 << 0;
         const WRITABLE =        1 << 1;
         const USER_ACCESSIBLE = 1 << 2;
+        const WRITE_THROUGH =   1 << 3;
+        const NO_CACHE =        1 << 4;
+        const ACCESSED =        1 << 5; // set by the CPU
+        const DIRTY =           1 << 6; // set by the CPU on the first write to the page
         const HUGE_PAGE =       1 << 7;
+        const GLOBAL =          1 << 8; // kept in the TLB across CR3 reloads if CR4.PGE is set
+        const COPY_ON_WRITE =   1 << 9; // available to the OS, ignored by the CPU
+        const NO_EXECUTE =      1 << 63; // PAE only
     }
 }
 
-const ADDRESS_MASK: usize = 0xffff_f000;
+impl EntryFlags {
+    pub fn from_elf_section_flags(section: &ElfSection) -> EntryFlags {
+        let mut flags = EntryFlags::empty();
+
+        if section.flags().contains(ElfSectionFlags::ALLOCATED) {
+            // section is loaded to memory
+            flags |= EntryFlags::PRESENT;
+        }
+        if section.flags().contains(ElfSectionFlags::WRITABLE) {
+            flags |= EntryFlags::WRITABLE;
+        }
+        if !section.flags().contains(ElfSectionFlags::EXECUTABLE) {
+            flags |= EntryFlags::NO_EXECUTE;
+        }
+
+        flags
+    }
+}
+
+impl<M: PagingMode> Entry<M> {
+    fn bits(&self) -> u64 {
+        M::to_bits(self.0)
+    }
 
-impl Entry {
     pub fn is_unused(&self) -> bool {
-        self.0 == 0
+        self.bits() == 0
     }
 
     pub fn set_unused(&mut self) {
-        self.0 = 0;
+        self.0 = M::from_bits(0);
     }
 
     pub fn flags(&self) -> EntryFlags {
-        EntryFlags::from_bits_truncate(self.0)
+        EntryFlags::from_bits_truncate(self.bits())
     }
 
     pub fn pointed_frame(&self) -> Option<Frame> {
         if self.flags().contains(EntryFlags::PRESENT) {
-            Some(Frame::containing_address(self.0 & ADDRESS_MASK))
+            Some(Frame::containing_address(
+                (self.bits() & M::ADDRESS_MASK) as usize,
+            ))
         } else {
             None
         }
     }
 
-    pub fn set(&mut self, frame: Frame, flags: EntryFlags) {
-        assert!(frame.start_address() & !ADDRESS_MASK == 0);
-        self.0 = frame.start_address() | flags.bits();
+    pub fn set(&mut self, frame: Frame, mut flags: EntryFlags) {
+        let address = frame.start_address() as u64;
+        assert!(address & !M::ADDRESS_MASK == 0);
+        if !mode::no_execute_enabled() {
+            // the bit is reserved without EFER.NXE
+            flags.remove(EntryFlags::NO_EXECUTE);
+        }
+        self.0 = M::from_bits(address | flags.bits());
     }
 }
@@ -0,0 +1,119 @@
+//! Mappings of memory-mapped I/O, such as the VGA text buffer, in a window of
+//! kernel space reserved for them.
+
+use super::{EntryFlags, Page, PhysicalAddress, VirtualAddress};
+use crate::interrupts;
+use crate::memory::frame::Frame;
+use crate::memory::{align_up, with_memory, GUARD_SIZE, PAGE_SIZE};
+use spin::Mutex;
+
+pub const IOREMAP_START: VirtualAddress = 0xf800_0000;
+pub const IOREMAP_SIZE: usize = 0x0400_0000; // 64 MiB
+
+const MAX_AREAS: usize = 64;
+
+/// How the CPU caches accesses to a mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Regular memory, such as tables left by the firmware.
+    WriteBack,
+    /// Reads are cached but writes reach the device right away, such as a framebuffer.
+    WriteThrough,
+    /// Every access reaches the device, such as device registers.
+    Uncached,
+}
+
+impl CacheMode {
+    fn flags(self) -> EntryFlags {
+        match self {
+            CacheMode::WriteBack => EntryFlags::empty(),
+            CacheMode::WriteThrough => EntryFlags::WRITE_THROUGH,
+            CacheMode::Uncached => EntryFlags::NO_CACHE | EntryFlags::WRITE_THROUGH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IoArea {
+    start: VirtualAddress,
+    size: usize,
+}
+
+// a fixed array, as the VGA buffer is mapped here before the heap is initialized
+static AREAS: Mutex<[Option<IoArea>; MAX_AREAS]> = Mutex::new([None; MAX_AREAS]);
+
+/// First address of the window where `size` bytes are free.
+fn find_hole(areas: &[Option<IoArea>; MAX_AREAS], size: usize) -> Option<VirtualAddress> {
+    let overlaps = |start: VirtualAddress| {
+        areas
+            .iter()
+            .flatten()
+            .any(|area| start < area.start + area.size + GUARD_SIZE && area.start < start + size)
+    };
+    let candidates = areas
+        .iter()
+        .flatten()
+        .map(|area| area.start + area.size + GUARD_SIZE);
+    core::iter::once(IOREMAP_START)
+        .chain(candidates)
+        .filter(|&start| start + size <= IOREMAP_START + IOREMAP_SIZE)
+        .filter(|&start| !overlaps(start))
+        .min()
+}
+
+/// Maps the `len` bytes of device memory at `physical_address` in the ioremap
+/// window and returns the virtual address of `physical_address`.
+/// The frames don't belong to the frame allocator and are never freed.
+pub fn ioremap(
+    physical_address: PhysicalAddress,
+    len: usize,
+    cache_mode: CacheMode,
+) -> VirtualAddress {
+    assert!(len > 0, "ioremap of an empty range");
+    let offset = physical_address % PAGE_SIZE;
+    let size = align_up(offset + len, PAGE_SIZE);
+
+    let start = interrupts::without_interrupts(|| {
+        let mut areas = AREAS.lock();
+        let start = find_hole(&areas, size).expect("ioremap window is full");
+        let slot = areas
+            .iter_mut()
+            .find(|area| area.is_none())
+            .expect("too many ioremap areas");
+        *slot = Some(IoArea { start, size });
+        start
+    });
+
+    let flags = EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE | cache_mode.flags();
+    with_memory(|active_table, frame_allocator| {
+        for i in (0..size).step_by(PAGE_SIZE) {
+            active_table.map_to(
+                Page::containing_address(start + i),
+                Frame::containing_address(physical_address - offset + i),
+                flags,
+                frame_allocator,
+            );
+        }
+    });
+    start + offset
+}
+
+/// Unmaps an area returned by `ioremap`.
+pub fn iounmap(address: VirtualAddress) {
+    let area = interrupts::without_interrupts(|| {
+        let mut areas = AREAS.lock();
+        let slot = areas
+            .iter_mut()
+            .find(|area| {
+                area.is_some_and(|area| (area.start..area.start + area.size).contains(&address))
+            })
+            .unwrap_or_else(|| panic!("{:#x} is not an ioremap address", address));
+        slot.take().unwrap()
+    });
+
+    with_memory(|active_table, _| {
+        for i in (0..area.size).step_by(PAGE_SIZE) {
+            active_table.unmap_frame(Page::containing_address(area.start + i));
+        }
+    });
+}
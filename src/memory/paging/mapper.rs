@@ -0,0 +1,337 @@
+use core::ptr::{self, NonNull};
+
+use super::{
+    entry::{Entry, EntryFlags},
+    mode::PagingMode,
+    read_cr3,
+    table::{Level2, Table},
+    tlb, write_pdpt, InactivePageTable, Page, PhysicalAddress, TemporaryPage, VirtualAddress,
+};
+use crate::memory::{
+    frame::{BuddyFrameAllocator, Frame, FrameAllocator},
+    KERNEL_OFFSET, PAGE_SIZE,
+};
+
+pub struct Mapper<M: PagingMode> {
+    p2: NonNull<Table<M, Level2>>, // first of the `M::DIRECTORY_COUNT` page directories
+}
+
+impl<M: PagingMode> Mapper<M> {
+    /// `p2` must point to `M::DIRECTORY_COUNT` consecutive page directories.
+    pub unsafe fn new(p2: *mut Table<M, Level2>) -> Mapper<M> {
+        Mapper {
+            p2: NonNull::new_unchecked(p2),
+        }
+    }
+
+    /// The page directory in charge of `page`.
+    pub fn p2(&self, page: Page) -> &Table<M, Level2> {
+        unsafe { &*self.p2.as_ptr().add(page.directory_index::<M>()) }
+    }
+
+    pub fn p2_mut(&mut self, page: Page) -> &mut Table<M, Level2> {
+        unsafe { &mut *self.p2.as_ptr().add(page.directory_index::<M>()) }
+    }
+
+    /// The first of the page directories the recursive mapping points to,
+    /// which are always allocated together.
+    pub fn directories(&self) -> Frame {
+        self.translate_page(Page::containing_address(M::P2))
+            .unwrap()
+    }
+
+    /// Translates a virtual to the corresponding physical address.
+    /// Returns `None` if the address is not mapped.
+    pub fn translate(&self, virtual_address: VirtualAddress) -> Option<PhysicalAddress> {
+        let offset = virtual_address % PAGE_SIZE;
+        self.translate_page(Page::containing_address(virtual_address))
+            .map(|frame| frame.start_address() + offset)
+    }
+
+    pub fn translate_page(&self, page: Page) -> Option<Frame> {
+        let p2 = self.p2(page);
+
+        let huge_page = || {
+            let p2_entry = &p2[page.p2_index::<M>()];
+            let start_frame = p2_entry.pointed_frame()?;
+            if p2_entry.flags().contains(EntryFlags::HUGE_PAGE) {
+                // address must be 4MiB (2MiB with PAE) aligned
+                assert!(start_frame.start_address() % (M::ENTRY_COUNT * PAGE_SIZE) == 0);
+                return Some(Frame::containing_address(
+                    start_frame.start_address() + page.p1_index::<M>() * PAGE_SIZE,
+                ));
+            }
+            None
+        };
+
+        p2.next_table(page.p2_index::<M>())
+            .and_then(|p1| p1[page.p1_index::<M>()].pointed_frame())
+            .or_else(huge_page)
+    }
+
+    /// Maps the page to the frame with the provided flags.
+    /// The `PRESENT` flag is added by default. Needs a
+    /// `FrameAllocator` as it might need to create a new page table.
+    pub fn map_to<A>(&mut self, page: Page, frame: Frame, flags: EntryFlags, allocator: &mut A)
+    where
+        A: FrameAllocator,
+    {
+        let p2 = self.p2_mut(page);
+        p2.next_table_create(page.p2_index::<M>(), allocator);
+        if flags.contains(EntryFlags::USER_ACCESSIBLE) {
+            assert!(
+                page.start_address() < KERNEL_OFFSET,
+                "page {:#x} is in kernel space",
+                page.start_address()
+            );
+            // the page table must be user accessible too
+            let p2_entry = &mut p2[page.p2_index::<M>()];
+            let p2_flags = p2_entry.flags() | EntryFlags::USER_ACCESSIBLE;
+            p2_entry.set(p2_entry.pointed_frame().unwrap(), p2_flags);
+        }
+        let p1 = p2.next_table_mut(page.p2_index::<M>()).unwrap();
+        assert!(
+            p1[page.p1_index::<M>()].is_unused(),
+            "page {:#x} is already mapped",
+            page.start_address()
+        );
+        p1[page.p1_index::<M>()].set(frame, flags | EntryFlags::PRESENT);
+    }
+
+    /// Maps the page to some free frame with the provided flags.
+    /// The free frame is allocated from the given `FrameAllocator`.
+    pub fn map<A>(&mut self, page: Page, flags: EntryFlags, allocator: &mut A)
+    where
+        A: FrameAllocator,
+    {
+        let frame = allocator.allocate_frame().expect("out of memory");
+        self.map_to(page, frame, flags, allocator)
+    }
+
+    /// Identity map the given frame with the provided flags.
+    /// The `FrameAllocator` is used to create new page tables if needed.
+    pub fn identity_map<A>(&mut self, frame: Frame, flags: EntryFlags, allocator: &mut A)
+    where
+        A: FrameAllocator,
+    {
+        let page = Page::containing_address(frame.start_address());
+        self.map_to(page, frame, flags, allocator)
+    }
+
+    /// Unmaps the given page and adds all freed frames to the given
+    /// `FrameAllocator`.
+    pub fn unmap<A>(&mut self, page: Page, allocator: &mut A)
+    where
+        A: FrameAllocator,
+    {
+        let frame = self.unmap_frame(page);
+        // TODO: free p1 table if empty
+        allocator.deallocate_frame(frame);
+    }
+
+    /// Unmaps the given page and returns its frame, which is not freed.
+    pub fn unmap_frame(&mut self, page: Page) -> Frame {
+        assert!(
+            self.translate(page.start_address()).is_some(),
+            "page {:#x} is not mapped",
+            page.start_address()
+        );
+
+        let p1 = self
+            .p2_mut(page)
+            .next_table_mut(page.p2_index::<M>())
+            .expect("mapping code does not support huge pages");
+        let frame = p1[page.p1_index::<M>()].pointed_frame().unwrap();
+        p1[page.p1_index::<M>()].set_unused();
+        tlb::flush(page.start_address());
+        frame
+    }
+
+    fn p1_entry_mut(&mut self, page: Page) -> Option<&mut Entry<M>> {
+        let p1 = self.p2_mut(page).next_table_mut(page.p2_index::<M>())?;
+        Some(&mut p1[page.p1_index::<M>()])
+    }
+
+    /// Maps `page` to the frame of `source`. Both mappings become read-only
+    /// and copy-on-write, so the first write to either gets its own copy.
+    pub fn share_copy_on_write(
+        &mut self,
+        source: Page,
+        page: Page,
+        allocator: &mut BuddyFrameAllocator,
+    ) {
+        let (frame, flags) = self.mark_copy_on_write(source);
+        allocator.share_frame(&frame);
+        self.map_to(page, frame, flags, allocator);
+    }
+
+    /// Makes `page` read-only and copy-on-write if it is writable. Returns its
+    /// frame and flags, to map it in another address space after `share_frame`.
+    pub fn mark_copy_on_write(&mut self, page: Page) -> (Frame, EntryFlags) {
+        let entry = self
+            .p1_entry_mut(page)
+            .filter(|entry| entry.flags().contains(EntryFlags::PRESENT))
+            .unwrap_or_else(|| panic!("page {:#x} is not mapped", page.start_address()));
+        let frame = entry.pointed_frame().unwrap();
+        let mut flags = entry.flags();
+        if flags.contains(EntryFlags::WRITABLE) {
+            flags.remove(EntryFlags::WRITABLE);
+            flags.insert(EntryFlags::COPY_ON_WRITE);
+            entry.set(frame.clone(), flags);
+            tlb::flush(page.start_address());
+        }
+        (frame, flags)
+    }
+
+    /// Gives `page` a writable frame of its own if it is copy-on-write,
+    /// copying the shared frame through `temporary_page` unless this is its
+    /// last owner. Returns `false` if `page` is not copy-on-write.
+    pub fn resolve_copy_on_write(
+        &mut self,
+        page: Page,
+        temporary_page: &mut TemporaryPage,
+        allocator: &mut BuddyFrameAllocator,
+    ) -> bool {
+        let (frame, mut flags) = match self.p1_entry_mut(page) {
+            Some(entry) if entry.flags().contains(EntryFlags::COPY_ON_WRITE) => {
+                (entry.pointed_frame().unwrap(), entry.flags())
+            }
+            _ => return false,
+        };
+        flags.remove(EntryFlags::COPY_ON_WRITE);
+        flags.insert(EntryFlags::WRITABLE);
+
+        if allocator.references(&frame) == 1 {
+            self.p1_entry_mut(page).unwrap().set(frame, flags);
+            tlb::flush(page.start_address());
+            return true;
+        }
+
+        let new_frame = allocator.allocate_frame().expect("out of memory");
+        let copy = temporary_page.map(new_frame.clone(), self, allocator);
+        unsafe {
+            ptr::copy_nonoverlapping(
+                page.start_address() as *const u8,
+                copy as *mut u8,
+                PAGE_SIZE,
+            );
+        }
+        temporary_page.unmap(self);
+
+        self.p1_entry_mut(page).unwrap().set(new_frame, flags);
+        tlb::flush(page.start_address());
+        allocator.deallocate_frame(frame);
+        true
+    }
+
+    /// Creates the page tables of a new address space. Its kernel space is
+    /// shared with this one, so all the kernel page tables are created first.
+    pub fn new_table(
+        &mut self,
+        temporary_page: &mut TemporaryPage,
+        allocator: &mut BuddyFrameAllocator,
+    ) -> InactivePageTable {
+        let kernel = Page::containing_address(KERNEL_OFFSET);
+        assert!(kernel.directory_index::<M>() == M::DIRECTORY_COUNT - 1);
+        let kernel_entries = kernel.p2_index::<M>()..M::ENTRY_COUNT - M::DIRECTORY_COUNT;
+        for index in kernel_entries.clone() {
+            self.p2_mut(kernel).next_table_create(index, allocator);
+        }
+
+        let directories = allocator
+            .allocate_frames(M::DIRECTORY_ORDER)
+            .expect("no more frames");
+        for i in 0..M::DIRECTORY_COUNT {
+            let frame = Frame::containing_address(directories.start_address() + i * PAGE_SIZE);
+            let table = temporary_page.map_table_frame(frame, self, allocator);
+            table.zero();
+            if i == M::DIRECTORY_COUNT - 1 {
+                for index in kernel_entries.clone() {
+                    let entry = &self.p2(kernel)[index];
+                    table[index].set(entry.pointed_frame().unwrap(), entry.flags());
+                }
+                table.set_recursive_entries(&directories);
+            }
+            temporary_page.unmap(self);
+        }
+
+        let cr3 = if M::DIRECTORY_COUNT == 1 {
+            directories.start_address()
+        } else {
+            let pdpt = allocator.allocate_frame().expect("no more frames");
+            let address = temporary_page.map(pdpt.clone(), self, allocator);
+            unsafe { write_pdpt(address as *mut u64, &directories) };
+            temporary_page.unmap(self);
+            pdpt.start_address()
+        };
+
+        InactivePageTable { directories, cr3 }
+    }
+
+    /// Makes the recursive mapping point to the directories of `table`, so
+    /// that this mapper edits it instead. The last active directory stays
+    /// mapped at `temporary_page` until `restore`.
+    /// Returns the first active directory.
+    pub(super) fn redirect(
+        &mut self,
+        table: &InactivePageTable,
+        temporary_page: &mut TemporaryPage,
+        allocator: &mut BuddyFrameAllocator,
+    ) -> Frame {
+        let active = self.directories();
+        let last = Frame::containing_address(
+            active.start_address() + (M::DIRECTORY_COUNT - 1) * PAGE_SIZE,
+        );
+        temporary_page
+            .map_table_frame(last, self, allocator)
+            .set_recursive_entries(&table.directories);
+        tlb::flush_all();
+        active
+    }
+
+    /// Undoes `redirect`.
+    pub(super) fn restore(&mut self, active: Frame, temporary_page: &mut TemporaryPage) {
+        let last = temporary_page.start_address() as *mut Table<M, Level2>;
+        unsafe { (*last).set_recursive_entries(&active) };
+        tlb::flush_all();
+        temporary_page.unmap(self);
+    }
+
+    /// Unmaps every page of user space and frees their frames and page tables.
+    pub fn release_user_space(&mut self, allocator: &mut BuddyFrameAllocator) {
+        for address in (0..KERNEL_OFFSET).step_by(M::ENTRY_COUNT * PAGE_SIZE) {
+            let page = Page::containing_address(address);
+            let p2 = self.p2_mut(page);
+            if let Some(p1) = p2.next_table_mut(page.p2_index::<M>()) {
+                for index in 0..M::ENTRY_COUNT {
+                    if let Some(frame) = p1[index].pointed_frame() {
+                        allocator.deallocate_frame(frame);
+                    }
+                }
+                let p1_frame = p2[page.p2_index::<M>()].pointed_frame().unwrap();
+                p2[page.p2_index::<M>()].set_unused();
+                allocator.deallocate_frame(p1_frame);
+            }
+        }
+        tlb::flush_all();
+    }
+
+    /// Frees the user space, the page tables and the directories of `table`.
+    pub fn free_table(
+        &mut self,
+        table: InactivePageTable,
+        temporary_page: &mut TemporaryPage,
+        allocator: &mut BuddyFrameAllocator,
+    ) {
+        assert!(table.cr3 != read_cr3(), "the active table can't be freed");
+        let active = self.redirect(&table, temporary_page, allocator);
+        self.release_user_space(allocator);
+        self.restore(active, temporary_page);
+
+        if table.cr3 != table.directories.start_address() {
+            // the page directory pointer table of PAE
+            allocator.deallocate_frame(Frame::containing_address(table.cr3));
+        }
+        allocator.deallocate_frames(table.directories, M::DIRECTORY_ORDER);
+    }
+}
@@ -1,13 +1,360 @@
 mod entry;
+mod ioremap;
+mod mapper;
+mod mode;
 mod table;
+mod temporary_page;
+mod tlb;
 
-use super::PAGE_SIZE;
+pub use self::entry::EntryFlags;
+pub use self::ioremap::{ioremap, iounmap, CacheMode, IOREMAP_START};
+pub use self::mapper::Mapper;
+pub use self::mode::{no_execute_enabled, Pae, PagingMode, TwoLevel};
+pub use self::temporary_page::TemporaryPage;
 
-const ENTRY_COUNT: usize = 512;
+use self::table::{Level2, Table};
+use super::frame::{BuddyFrameAllocator, Frame, FrameAllocator};
+use super::{kernel_physical_address, KERNEL_OFFSET, PAGE_SIZE};
+use crate::println;
+use crate::vga_buffer::VGA_ADDRESS;
+use core::arch::asm;
+use multiboot::BootInformation;
 
 pub type PhysicalAddress = usize;
 pub type VirtualAddress = usize;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Page {
     number: usize,
 }
+
+impl Page {
+    pub fn containing_address(address: VirtualAddress) -> Page {
+        Page {
+            number: address / PAGE_SIZE,
+        }
+    }
+
+    pub fn start_address(&self) -> VirtualAddress {
+        self.number * PAGE_SIZE
+    }
+
+    fn directory_index<M: PagingMode>(&self) -> usize {
+        self.number / (M::ENTRY_COUNT * M::ENTRY_COUNT)
+    }
+
+    fn p2_index<M: PagingMode>(&self) -> usize {
+        (self.number / M::ENTRY_COUNT) % M::ENTRY_COUNT
+    }
+
+    fn p1_index<M: PagingMode>(&self) -> usize {
+        self.number % M::ENTRY_COUNT
+    }
+}
+
+pub enum ActivePageTable {
+    TwoLevel(Mapper<TwoLevel>),
+    Pae(Mapper<Pae>),
+}
+
+macro_rules! with_mapper {
+    ($table:expr, $mapper:ident => $body:expr) => {
+        match $table {
+            ActivePageTable::TwoLevel($mapper) => $body,
+            ActivePageTable::Pae($mapper) => $body,
+        }
+    };
+}
+
+// The page directories are always reached through the recursive mapping,
+// whichever context the kernel runs in.
+unsafe impl Send for ActivePageTable {}
+
+impl ActivePageTable {
+    pub fn mode_name(&self) -> &'static str {
+        match self {
+            ActivePageTable::TwoLevel(_) => TwoLevel::NAME,
+            ActivePageTable::Pae(_) => Pae::NAME,
+        }
+    }
+
+    pub fn translate(&self, virtual_address: VirtualAddress) -> Option<PhysicalAddress> {
+        with_mapper!(self, mapper => mapper.translate(virtual_address))
+    }
+
+    pub fn translate_page(&self, page: Page) -> Option<Frame> {
+        with_mapper!(self, mapper => mapper.translate_page(page))
+    }
+
+    pub fn map_to<A>(&mut self, page: Page, frame: Frame, flags: EntryFlags, allocator: &mut A)
+    where
+        A: FrameAllocator,
+    {
+        with_mapper!(self, mapper => mapper.map_to(page, frame, flags, allocator))
+    }
+
+    pub fn map<A>(&mut self, page: Page, flags: EntryFlags, allocator: &mut A)
+    where
+        A: FrameAllocator,
+    {
+        with_mapper!(self, mapper => mapper.map(page, flags, allocator))
+    }
+
+    pub fn identity_map<A>(&mut self, frame: Frame, flags: EntryFlags, allocator: &mut A)
+    where
+        A: FrameAllocator,
+    {
+        with_mapper!(self, mapper => mapper.identity_map(frame, flags, allocator))
+    }
+
+    pub fn unmap<A>(&mut self, page: Page, allocator: &mut A)
+    where
+        A: FrameAllocator,
+    {
+        with_mapper!(self, mapper => mapper.unmap(page, allocator))
+    }
+
+    pub fn unmap_frame(&mut self, page: Page) -> Frame {
+        with_mapper!(self, mapper => mapper.unmap_frame(page))
+    }
+
+    pub fn share_copy_on_write(
+        &mut self,
+        source: Page,
+        page: Page,
+        allocator: &mut BuddyFrameAllocator,
+    ) {
+        with_mapper!(self, mapper => mapper.share_copy_on_write(source, page, allocator))
+    }
+
+    pub fn mark_copy_on_write(&mut self, page: Page) -> (Frame, EntryFlags) {
+        with_mapper!(self, mapper => mapper.mark_copy_on_write(page))
+    }
+
+    pub fn resolve_copy_on_write(
+        &mut self,
+        page: Page,
+        temporary_page: &mut TemporaryPage,
+        allocator: &mut BuddyFrameAllocator,
+    ) -> bool {
+        with_mapper!(self, mapper => mapper.resolve_copy_on_write(page, temporary_page, allocator))
+    }
+
+    /// Runs `f` with the recursive mapping pointing to `table`, so that `f`
+    /// edits it instead of the active table. Nothing of its user space can be
+    /// accessed, and `temporary_page` can't be used by `f`.
+    pub fn with<F, R>(
+        &mut self,
+        table: &InactivePageTable,
+        temporary_page: &mut TemporaryPage,
+        allocator: &mut BuddyFrameAllocator,
+        f: F,
+    ) -> R
+    where
+        F: FnOnce(&mut ActivePageTable, &mut BuddyFrameAllocator) -> R,
+    {
+        let active =
+            with_mapper!(self, mapper => mapper.redirect(table, temporary_page, allocator));
+        let result = f(self, allocator);
+        with_mapper!(self, mapper => mapper.restore(active, temporary_page));
+        result
+    }
+
+    /// Loads `new_table` in CR3 and returns the previously active table.
+    pub fn switch(&mut self, new_table: InactivePageTable) -> InactivePageTable {
+        let old_table = InactivePageTable {
+            directories: with_mapper!(self, mapper => mapper.directories()),
+            cr3: read_cr3(),
+        };
+        unsafe { write_cr3(new_table.cr3) };
+        old_table
+    }
+
+    /// Frees the user space, the page tables and the directories of `table`,
+    /// which must not be the active table.
+    pub fn free_table(
+        &mut self,
+        table: InactivePageTable,
+        temporary_page: &mut TemporaryPage,
+        allocator: &mut BuddyFrameAllocator,
+    ) {
+        with_mapper!(self, mapper => mapper.free_table(table, temporary_page, allocator))
+    }
+}
+
+/// The page tables of an address space that is not the active one.
+pub struct InactivePageTable {
+    directories: Frame, // the first of the `DIRECTORY_COUNT` page directories
+    cr3: PhysicalAddress,
+}
+
+impl InactivePageTable {
+    /// Creates a new address space, with an empty user space and the kernel
+    /// space of `active_table`.
+    pub fn new(
+        active_table: &mut ActivePageTable,
+        temporary_page: &mut TemporaryPage,
+        allocator: &mut BuddyFrameAllocator,
+    ) -> InactivePageTable {
+        with_mapper!(active_table, mapper => mapper.new_table(temporary_page, allocator))
+    }
+}
+
+pub fn read_cr3() -> PhysicalAddress {
+    let value: PhysicalAddress;
+    unsafe { asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+unsafe fn write_cr3(value: PhysicalAddress) {
+    asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// Fills the PAE page directory pointer table at `pdpt` with the four
+/// consecutive directories starting at `directories`.
+unsafe fn write_pdpt(pdpt: *mut u64, directories: &Frame) {
+    for i in 0..Pae::DIRECTORY_COUNT {
+        let directory = (directories.start_address() + i * PAGE_SIZE) as u64;
+        // the entries only hold the present bit
+        *pdpt.add(i) = directory | EntryFlags::PRESENT.bits();
+    }
+}
+
+const WRITE_PROTECT: usize = 1 << 16;
+
+/// Makes the CPU honor the `WRITABLE` flag in ring 0 too.
+fn enable_write_protect_bit() {
+    unsafe {
+        asm!(
+            "mov {0}, cr0",
+            "or {0}, {1}",
+            "mov cr0, {0}",
+            out(reg) _,
+            const WRITE_PROTECT,
+            options(nostack),
+        );
+    }
+}
+
+/// Replaces the huge-page mappings from `asm/boot.asm` with new page
+/// directories that map every ELF section of the higher half with its own
+/// permissions and the multiboot information structure. The VGA buffer has
+/// to be remapped with `ioremap` right after.
+///
+/// PAE paging is used if `pae` is set and the CPU supports it, in which case
+/// non-executable sections are also mapped `NO_EXECUTE` if the CPU supports it.
+pub fn remap_the_kernel(
+    allocator: &mut BuddyFrameAllocator,
+    boot_info: &BootInformation,
+    pae: bool,
+) -> ActivePageTable {
+    let active_table = if pae && mode::pae_supported() {
+        if mode::no_execute_supported() {
+            mode::enable_no_execute();
+        }
+        ActivePageTable::Pae(remap::<Pae>(allocator, boot_info))
+    } else {
+        if pae {
+            println!("PAE is not supported, falling back to 2-level paging");
+        }
+        ActivePageTable::TwoLevel(remap::<TwoLevel>(allocator, boot_info))
+    };
+    enable_write_protect_bit();
+
+    active_table
+}
+
+fn remap<M: PagingMode>(
+    allocator: &mut BuddyFrameAllocator,
+    boot_info: &BootInformation,
+) -> Mapper<M> {
+    let directories = allocator
+        .allocate_frames(M::DIRECTORY_ORDER)
+        .expect("no more frames");
+    let directories_address = directories.start_address();
+
+    // the lower 3 GiB are still identity mapped by `asm/boot.asm`, so the
+    // new tables are edited through their physical address
+    let p2 = directories_address as *mut Table<M, Level2>;
+    for i in 0..M::DIRECTORY_COUNT {
+        unsafe { (*p2.add(i)).zero() };
+    }
+    unsafe { (*p2.add(M::DIRECTORY_COUNT - 1)).set_recursive_entries(&directories) };
+
+    let mut mapper = unsafe { Mapper::<M>::new(p2) };
+
+    let elf_sections_tag = boot_info
+        .elf_sections_tag()
+        .expect("Elf-sections tag required");
+
+    let mut trampoline = None;
+    for section in elf_sections_tag.sections() {
+        if !section.is_allocated() {
+            // section is not loaded to memory
+            continue;
+        }
+        let start = section.start_address() as usize;
+        let end = section.end_address() as usize;
+        assert!(start % PAGE_SIZE == 0, "sections need to be page aligned");
+
+        if start < KERNEL_OFFSET {
+            // the boot trampoline is only needed to switch to PAE
+            if M::DIRECTORY_COUNT == 1 {
+                continue;
+            }
+            trampoline = Some((start, end));
+        }
+
+        let flags = EntryFlags::from_elf_section_flags(&section);
+        for address in (start..end).step_by(PAGE_SIZE) {
+            let frame = Frame::containing_address(kernel_physical_address(address));
+            mapper.map_to(Page::containing_address(address), frame, flags, allocator);
+        }
+    }
+
+    let multiboot_start = boot_info.start_address() - boot_info.start_address() % PAGE_SIZE;
+    for address in (multiboot_start..boot_info.end_address()).step_by(PAGE_SIZE) {
+        let page = Page::containing_address(address);
+        if mapper.translate_page(page).is_some() {
+            // converted from multiboot1 into the kernel's `.bss`, see `boot::load`
+            continue;
+        }
+        mapper.map_to(
+            page,
+            Frame::containing_address(kernel_physical_address(address)),
+            EntryFlags::PRESENT | EntryFlags::NO_EXECUTE,
+            allocator,
+        );
+    }
+
+    // the writer reaches the VGA buffer at its boot address until
+    // `vga_buffer::map_buffer`, and may print or panic before that
+    mapper.map_to(
+        Page::containing_address(KERNEL_OFFSET + VGA_ADDRESS),
+        Frame::containing_address(VGA_ADDRESS),
+        EntryFlags::WRITABLE
+            | EntryFlags::NO_EXECUTE
+            | EntryFlags::NO_CACHE
+            | EntryFlags::WRITE_THROUGH,
+        allocator,
+    );
+
+    if M::DIRECTORY_COUNT == 1 {
+        unsafe { write_cr3(directories_address) };
+    } else {
+        let pdpt = allocator.allocate_frame().expect("no more frames");
+        unsafe {
+            write_pdpt(pdpt.start_address() as *mut u64, &directories);
+            mode::enable_pae_paging(pdpt.start_address());
+        }
+    }
+
+    let mut mapper = unsafe { Mapper::new(M::P2 as *mut _) };
+    if let Some((start, end)) = trampoline {
+        // the lower 3 GiB are left to user space
+        for address in (start..end).step_by(PAGE_SIZE) {
+            mapper.unmap_frame(Page::containing_address(address));
+        }
+    }
+    mapper
+}
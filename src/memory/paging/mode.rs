@@ -0,0 +1,123 @@
+use super::{PhysicalAddress, VirtualAddress};
+use core::arch::asm;
+use core::arch::x86::__cpuid;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Layout of the page tables in one of the paging modes of the CPU.
+///
+/// In both modes the page directories are mapped recursively: the last
+/// `DIRECTORY_COUNT` entries of the last directory point to every directory,
+/// so the directories appear one after the other at `P2` and every page
+/// table at `P1_BASE + index * PAGE_SIZE`.
+pub trait PagingMode: Sized + 'static {
+    /// Raw page table entry.
+    type Raw: Copy;
+    /// All the entries of one table, which always fills exactly one frame.
+    type Entries: AsRef<[super::entry::Entry<Self>]> + AsMut<[super::entry::Entry<Self>]>;
+
+    const NAME: &'static str;
+    const ENTRY_COUNT: usize;
+    /// log2 of the number of page directories.
+    const DIRECTORY_ORDER: usize;
+    const DIRECTORY_COUNT: usize = 1 << Self::DIRECTORY_ORDER;
+    const P2: VirtualAddress;
+    const P1_BASE: VirtualAddress;
+    /// Bits of an entry holding the address of the frame.
+    const ADDRESS_MASK: u64;
+
+    fn to_bits(raw: Self::Raw) -> u64;
+    fn from_bits(bits: u64) -> Self::Raw;
+}
+
+/// Classic i386 paging: one directory of 1024 32-bit entries.
+pub enum TwoLevel {}
+
+/// Physical Address Extension: 64-bit entries, 512 per table, four page
+/// directories selected by a page directory pointer table.
+pub enum Pae {}
+
+impl PagingMode for TwoLevel {
+    type Raw = u32;
+    type Entries = [super::entry::Entry<Self>; 1024];
+
+    const NAME: &'static str = "2-level";
+    const ENTRY_COUNT: usize = 1024;
+    const DIRECTORY_ORDER: usize = 0;
+    const P2: VirtualAddress = 0xffff_f000;
+    const P1_BASE: VirtualAddress = 0xffc0_0000;
+    const ADDRESS_MASK: u64 = 0xffff_f000;
+
+    fn to_bits(raw: u32) -> u64 {
+        raw as u64
+    }
+
+    fn from_bits(bits: u64) -> u32 {
+        bits as u32
+    }
+}
+
+impl PagingMode for Pae {
+    type Raw = u64;
+    type Entries = [super::entry::Entry<Self>; 512];
+
+    const NAME: &'static str = "PAE";
+    const ENTRY_COUNT: usize = 512;
+    const DIRECTORY_ORDER: usize = 2;
+    const P2: VirtualAddress = 0xffff_c000;
+    const P1_BASE: VirtualAddress = 0xff80_0000;
+    const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+    fn to_bits(raw: u64) -> u64 {
+        raw
+    }
+
+    fn from_bits(bits: u64) -> u64 {
+        bits
+    }
+}
+
+const CPUID_PAE: u32 = 1 << 6; // leaf 1, edx
+const CPUID_NX: u32 = 1 << 20; // leaf 0x8000_0001, edx
+
+const IA32_EFER: u32 = 0xc000_0080;
+const EFER_NXE: u32 = 1 << 11;
+
+static NO_EXECUTE: AtomicBool = AtomicBool::new(false);
+
+/// `check_cpuid` in `asm/check.asm` made sure that the CPUID instruction exists.
+pub fn pae_supported() -> bool {
+    unsafe { __cpuid(1).edx & CPUID_PAE != 0 }
+}
+
+pub fn no_execute_supported() -> bool {
+    unsafe { __cpuid(0x8000_0000).eax >= 0x8000_0001 && __cpuid(0x8000_0001).edx & CPUID_NX != 0 }
+}
+
+/// Whether the `NO_EXECUTE` flag is honored, otherwise it is left out of the entries.
+pub fn no_execute_enabled() -> bool {
+    NO_EXECUTE.load(Ordering::Relaxed)
+}
+
+pub(super) fn enable_no_execute() {
+    unsafe {
+        asm!(
+            "rdmsr",
+            "or eax, {}",
+            "wrmsr",
+            const EFER_NXE,
+            in("ecx") IA32_EFER,
+            out("eax") _,
+            out("edx") _,
+            options(nostack),
+        );
+    }
+    NO_EXECUTE.store(true, Ordering::Relaxed);
+}
+
+extern "C" {
+    /// Loads `pdpt` in CR3 and switches the CPU to PAE paging.
+    ///
+    /// Paging is turned off for the switch, so this lives in the trampoline of
+    /// `asm/boot.asm`, which must be identity mapped in the new tables.
+    pub(super) fn enable_pae_paging(pdpt: PhysicalAddress);
+}
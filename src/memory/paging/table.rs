@@ -1,54 +1,120 @@
+use core::marker::PhantomData;
 use core::ops::{Index, IndexMut};
 
 use super::{
     entry::{Entry, EntryFlags},
-    ENTRY_COUNT,
+    mode::PagingMode,
 };
+use crate::memory::{
+    frame::{Frame, FrameAllocator},
+    PAGE_SIZE,
+};
+
+pub trait TableLevel {}
+
+pub enum Level2 {}
+pub enum Level1 {}
+
+impl TableLevel for Level2 {}
+impl TableLevel for Level1 {}
+
+pub trait HierarchicalLevel: TableLevel {
+    type NextLevel: TableLevel;
+}
+
+impl HierarchicalLevel for Level2 {
+    type NextLevel = Level1;
+}
 
-pub struct Table {
-    entries: [Entry; ENTRY_COUNT],
+pub struct Table<M: PagingMode, L: TableLevel> {
+    entries: M::Entries,
+    level: PhantomData<L>,
 }
 
-impl Index<usize> for Table {
-    type Output = Entry;
+impl<M: PagingMode, L: TableLevel> Index<usize> for Table<M, L> {
+    type Output = Entry<M>;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.entries[index]
+        &self.entries.as_ref()[index]
     }
 }
 
-impl IndexMut<usize> for Table {
+impl<M: PagingMode, L: TableLevel> IndexMut<usize> for Table<M, L> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.entries[index]
+        &mut self.entries.as_mut()[index]
     }
 }
 
-impl Table {
+impl<M: PagingMode, L: TableLevel> Table<M, L> {
     pub fn zero(&mut self) {
-        for entry in self.entries.iter_mut() {
+        for entry in self.entries.as_mut().iter_mut() {
             entry.set_unused()
         }
     }
+}
+
+impl<M: PagingMode> Table<M, Level2> {
+    /// Points the recursive entries of the last page directory to the
+    /// `M::DIRECTORY_COUNT` consecutive directories starting at `directories`.
+    pub fn set_recursive_entries(&mut self, directories: &Frame) {
+        for i in 0..M::DIRECTORY_COUNT {
+            self[M::ENTRY_COUNT - M::DIRECTORY_COUNT + i].set(
+                Frame::containing_address(directories.start_address() + i * PAGE_SIZE),
+                EntryFlags::PRESENT | EntryFlags::WRITABLE,
+            );
+        }
+    }
+}
 
+impl<M: PagingMode, L: HierarchicalLevel> Table<M, L> {
     fn next_table_address(&self, index: usize) -> Option<usize> {
         let entry_flags = self[index].flags();
 
         if entry_flags.contains(EntryFlags::PRESENT) && !entry_flags.contains(EntryFlags::HUGE_PAGE)
         {
             let table_address = self as *const _ as usize;
-            Some((table_address << 10) | (index << 12))
+            if table_address >= M::P2 {
+                // reached through the recursive mapping
+                let directory = (table_address - M::P2) / PAGE_SIZE;
+                Some(M::P1_BASE + (directory * M::ENTRY_COUNT + index) * PAGE_SIZE)
+            } else {
+                // a table being built by `remap_the_kernel`, while everything is identity mapped
+                self[index]
+                    .pointed_frame()
+                    .map(|frame| frame.start_address())
+            }
         } else {
             None
         }
     }
 
-    pub fn next_table(&self, index: usize) -> Option<&Table> {
+    pub fn next_table(&self, index: usize) -> Option<&Table<M, L::NextLevel>> {
         self.next_table_address(index)
             .map(|address| unsafe { &*(address as *const _) })
     }
 
-    pub fn next_table_mut(&mut self, index: usize) -> Option<&mut Table> {
+    pub fn next_table_mut(&mut self, index: usize) -> Option<&mut Table<M, L::NextLevel>> {
         self.next_table_address(index)
             .map(|address| unsafe { &mut *(address as *mut _) })
     }
+
+    pub fn next_table_create<A>(
+        &mut self,
+        index: usize,
+        allocator: &mut A,
+    ) -> &mut Table<M, L::NextLevel>
+    where
+        A: FrameAllocator,
+    {
+        if self.next_table(index).is_none() {
+            assert!(
+                !self[index].flags().contains(EntryFlags::HUGE_PAGE),
+                "mapping code does not support huge pages"
+            );
+            let frame = allocator.allocate_frame().expect("no frames available");
+            self[index].set(frame, EntryFlags::PRESENT | EntryFlags::WRITABLE);
+            self.next_table_mut(index).unwrap().zero();
+        }
+        self.next_table_mut(index).unwrap()
+    }
 }
@@ -0,0 +1,67 @@
+use super::table::{Level2, Table};
+use super::{EntryFlags, Mapper, Page, PagingMode, VirtualAddress};
+use crate::memory::frame::{Frame, FrameAllocator};
+
+/// A page of the active address space that is mapped for a short while to a
+/// frame that is not reachable otherwise, such as a directory of an
+/// `InactivePageTable`.
+pub struct TemporaryPage {
+    page: Page,
+}
+
+impl TemporaryPage {
+    /// `page` must be in kernel space and not used for anything else.
+    pub fn new(page: Page) -> TemporaryPage {
+        TemporaryPage { page }
+    }
+
+    pub fn start_address(&self) -> VirtualAddress {
+        self.page.start_address()
+    }
+
+    /// Maps the temporary page to the given frame in the active table.
+    /// Returns the start address of the temporary page.
+    pub fn map<M, A>(
+        &mut self,
+        frame: Frame,
+        active_table: &mut Mapper<M>,
+        allocator: &mut A,
+    ) -> VirtualAddress
+    where
+        M: PagingMode,
+        A: FrameAllocator,
+    {
+        assert!(
+            active_table.translate_page(self.page).is_none(),
+            "temporary page is already mapped"
+        );
+        active_table.map_to(
+            self.page,
+            frame,
+            EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE,
+            allocator,
+        );
+        self.page.start_address()
+    }
+
+    /// Maps the temporary page to the given page directory frame in the active
+    /// table. Returns a reference to the now mapped directory, which can't be
+    /// used to reach the page tables it points to.
+    pub fn map_table_frame<M, A>(
+        &mut self,
+        frame: Frame,
+        active_table: &mut Mapper<M>,
+        allocator: &mut A,
+    ) -> &mut Table<M, Level2>
+    where
+        M: PagingMode,
+        A: FrameAllocator,
+    {
+        unsafe { &mut *(self.map(frame, active_table, allocator) as *mut Table<M, Level2>) }
+    }
+
+    /// Unmaps the temporary page in the active table, without freeing its frame.
+    pub fn unmap<M: PagingMode>(&mut self, active_table: &mut Mapper<M>) {
+        active_table.unmap_frame(self.page);
+    }
+}
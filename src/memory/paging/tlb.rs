@@ -0,0 +1,23 @@
+use super::VirtualAddress;
+use core::arch::asm;
+
+/// Invalidates the TLB entry of the page containing `address`.
+#[inline]
+pub fn flush(address: VirtualAddress) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) address, options(nostack, preserves_flags));
+    }
+}
+
+/// Invalidates the whole TLB by reloading CR3.
+#[inline]
+pub fn flush_all() {
+    unsafe {
+        asm!(
+            "mov {0}, cr3",
+            "mov cr3, {0}",
+            out(reg) _,
+            options(nostack, preserves_flags),
+        );
+    }
+}
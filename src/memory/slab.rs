@@ -0,0 +1,298 @@
+//! Slab allocator: caches of fixed-size objects carved out of single pages
+//! mapped in the slab region. Small `kmalloc` sizes go through the
+//! `kmalloc-*` caches, other subsystems can create their own with `create_cache`.
+
+use super::paging::{EntryFlags, VirtualAddress};
+use super::{align_up, map_range, unmap_range, PAGE_SIZE};
+use crate::interrupts;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::null_mut;
+use spin::Mutex;
+
+pub const SLAB_START: VirtualAddress = 0xf000_0000;
+pub const SLAB_MAX_SIZE: usize = 0x0400_0000; // 64 MiB
+
+const NB_PAGES: usize = SLAB_MAX_SIZE / PAGE_SIZE;
+const BITS_PER_WORD: usize = u32::BITS as usize;
+
+const OBJECT_ALIGN: usize = 8;
+const MAX_OBJECT_SIZE: usize = PAGE_SIZE - size_of::<Slab>();
+
+// bit set for every page of the slab region that is in use
+static SLAB_PAGES: Mutex<[u32; NB_PAGES / BITS_PER_WORD]> =
+    Mutex::new([0; NB_PAGES / BITS_PER_WORD]);
+
+static KMALLOC_CACHES: [SlabCache; 7] = [
+    SlabCache::new("kmalloc-8", 8),
+    SlabCache::new("kmalloc-16", 16),
+    SlabCache::new("kmalloc-32", 32),
+    SlabCache::new("kmalloc-64", 64),
+    SlabCache::new("kmalloc-128", 128),
+    SlabCache::new("kmalloc-256", 256),
+    SlabCache::new("kmalloc-512", 512),
+];
+
+static CACHES: Mutex<Vec<&'static SlabCache>> = Mutex::new(Vec::new());
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// Stored at the end of every slab page, after the objects.
+#[repr(C)]
+struct Slab {
+    cache: *const SlabCache,
+    next: *mut Slab,
+    free: *mut FreeObject,
+    in_use: usize,
+}
+
+struct Slabs {
+    list: *mut Slab,
+    nb_slabs: usize,
+    in_use: usize,
+}
+
+// Slabs are only reached through their cache's lock.
+unsafe impl Send for Slabs {}
+
+pub struct SlabCache {
+    name: &'static str,
+    object_size: usize,
+    slabs: Mutex<Slabs>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabInfo {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub active_objects: usize,
+    pub total_objects: usize,
+    pub objects_per_slab: usize,
+    pub slabs: usize,
+    /// Bytes of the slabs that can never hold an object.
+    pub wasted: usize,
+}
+
+impl SlabCache {
+    pub const fn new(name: &'static str, object_size: usize) -> SlabCache {
+        let object_size = align_up(object_size, OBJECT_ALIGN);
+        assert!(
+            object_size <= MAX_OBJECT_SIZE,
+            "slab objects must fit in a page"
+        );
+        SlabCache {
+            name,
+            object_size,
+            slabs: Mutex::new(Slabs {
+                list: null_mut(),
+                nb_slabs: 0,
+                in_use: 0,
+            }),
+        }
+    }
+
+    pub fn object_size(&self) -> usize {
+        self.object_size
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        MAX_OBJECT_SIZE / self.object_size
+    }
+
+    /// Returns a free object, or a null pointer if the kernel is out of memory.
+    pub fn allocate(&self) -> *mut u8 {
+        interrupts::without_interrupts(|| unsafe {
+            let mut slabs = self.slabs.lock();
+
+            let mut slab = slabs.list;
+            while !slab.is_null() && (*slab).free.is_null() {
+                slab = (*slab).next;
+            }
+            if slab.is_null() {
+                slab = match self.new_slab() {
+                    Some(slab) => slab,
+                    None => return null_mut(),
+                };
+                (*slab).next = slabs.list;
+                slabs.list = slab;
+                slabs.nb_slabs += 1;
+            }
+
+            let object = (*slab).free;
+            (*slab).free = (*object).next;
+            (*slab).in_use += 1;
+            slabs.in_use += 1;
+            object as *mut u8
+        })
+    }
+
+    unsafe fn new_slab(&self) -> Option<*mut Slab> {
+        let page = allocate_page()?;
+        let slab = slab_of(page);
+        *slab = Slab {
+            cache: self,
+            next: null_mut(),
+            free: null_mut(),
+            in_use: 0,
+        };
+        for i in (0..self.objects_per_slab()).rev() {
+            let object = (page + i * self.object_size) as *mut FreeObject;
+            (*object).next = (*slab).free;
+            (*slab).free = object;
+        }
+        Some(slab)
+    }
+
+    unsafe fn free(&self, ptr: *mut u8, slab: *mut Slab) {
+        let mut slabs = self.slabs.lock();
+
+        let offset = ptr as usize % PAGE_SIZE;
+        assert!(
+            offset % self.object_size == 0 && offset / self.object_size < self.objects_per_slab(),
+            "{:p} is not an object of {}",
+            ptr,
+            self.name
+        );
+        let mut object = (*slab).free;
+        while !object.is_null() {
+            assert!(object as *mut u8 != ptr, "double free of {:p}", ptr);
+            object = (*object).next;
+        }
+
+        let object = ptr as *mut FreeObject;
+        (*object).next = (*slab).free;
+        (*slab).free = object;
+        (*slab).in_use -= 1;
+        slabs.in_use -= 1;
+
+        // give empty slabs back, but keep the last one around
+        if (*slab).in_use == 0 && slabs.nb_slabs > 1 {
+            let mut prev: *mut *mut Slab = &mut slabs.list;
+            while *prev != slab {
+                prev = &mut (**prev).next;
+            }
+            *prev = (*slab).next;
+            slabs.nb_slabs -= 1;
+            free_page(ptr as usize - offset);
+        }
+    }
+
+    pub fn info(&self) -> SlabInfo {
+        let slabs = interrupts::without_interrupts(|| {
+            let slabs = self.slabs.lock();
+            (slabs.nb_slabs, slabs.in_use)
+        });
+        let objects_per_slab = self.objects_per_slab();
+        SlabInfo {
+            name: self.name,
+            object_size: self.object_size,
+            active_objects: slabs.1,
+            total_objects: slabs.0 * objects_per_slab,
+            objects_per_slab,
+            slabs: slabs.0,
+            wasted: slabs.0 * (PAGE_SIZE - objects_per_slab * self.object_size),
+        }
+    }
+}
+
+fn slab_of(address: VirtualAddress) -> *mut Slab {
+    (address - address % PAGE_SIZE + PAGE_SIZE - size_of::<Slab>()) as *mut Slab
+}
+
+fn allocate_page() -> Option<VirtualAddress> {
+    let mut pages = SLAB_PAGES.lock();
+    let word_idx = pages.iter().position(|&word| word != u32::MAX)?;
+    let bit = (!pages[word_idx]).trailing_zeros() as usize;
+    let address = SLAB_START + (word_idx * BITS_PER_WORD + bit) * PAGE_SIZE;
+    if !map_range(
+        address,
+        address + PAGE_SIZE,
+        EntryFlags::WRITABLE | EntryFlags::NO_EXECUTE,
+    ) {
+        return None;
+    }
+    pages[word_idx] |= 1 << bit;
+    Some(address)
+}
+
+fn free_page(address: VirtualAddress) {
+    let number = (address - SLAB_START) / PAGE_SIZE;
+    unmap_range(address, address + PAGE_SIZE);
+    SLAB_PAGES.lock()[number / BITS_PER_WORD] &= !(1 << (number % BITS_PER_WORD));
+}
+
+/// Creates a new named cache, listed by `for_each_cache`.
+pub fn create_cache(name: &'static str, object_size: usize) -> &'static SlabCache {
+    let cache: &'static SlabCache = Box::leak(Box::new(SlabCache::new(name, object_size)));
+    interrupts::without_interrupts(|| CACHES.lock().push(cache));
+    cache
+}
+
+/// Frees a cache returned by `create_cache` along with its slabs. None of
+/// its objects may still be in use, and the cache must not be used afterwards.
+pub unsafe fn destroy_cache(cache: &'static SlabCache) {
+    interrupts::without_interrupts(|| {
+        let mut caches = CACHES.lock();
+        let index = caches
+            .iter()
+            .position(|&other| core::ptr::eq(other, cache))
+            .unwrap_or_else(|| panic!("{} was not created by create_cache", cache.name));
+        caches.remove(index);
+
+        let slabs = cache.slabs.lock();
+        assert!(
+            slabs.in_use == 0,
+            "{} still has {} objects in use",
+            cache.name,
+            slabs.in_use
+        );
+        let mut slab = slabs.list;
+        while !slab.is_null() {
+            let next = (*slab).next;
+            free_page(slab as usize - slab as usize % PAGE_SIZE);
+            slab = next;
+        }
+    });
+    drop(Box::from_raw(cache as *const SlabCache as *mut SlabCache));
+}
+
+pub fn for_each_cache<F>(mut f: F)
+where
+    F: FnMut(&SlabCache),
+{
+    for cache in KMALLOC_CACHES.iter() {
+        f(cache);
+    }
+    let caches = interrupts::without_interrupts(|| CACHES.lock().clone());
+    for cache in caches {
+        f(cache);
+    }
+}
+
+/// The smallest `kmalloc-*` cache whose objects fit `size` bytes aligned on `align`.
+pub fn kmalloc_cache(size: usize, align: usize) -> Option<&'static SlabCache> {
+    let size = size.max(align);
+    KMALLOC_CACHES
+        .iter()
+        .find(|cache| cache.object_size >= size)
+}
+
+pub fn contains(ptr: *const u8) -> bool {
+    (SLAB_START..SLAB_START + SLAB_MAX_SIZE).contains(&(ptr as usize))
+}
+
+/// Frees an object returned by any cache.
+pub unsafe fn free(ptr: *mut u8) {
+    interrupts::without_interrupts(|| {
+        let slab = slab_of(ptr as usize);
+        (*(*slab).cache).free(ptr, slab)
+    })
+}
+
+/// Size of the objects of the cache `ptr` belongs to.
+pub unsafe fn object_size(ptr: *const u8) -> usize {
+    (*(*slab_of(ptr as usize)).cache).object_size
+}
@@ -0,0 +1,139 @@
+//! Virtual memory allocator: areas are contiguous in the `vmalloc` region but
+//! lazily backed page by page by frames that don't need to be physically contiguous.
+
+use super::paging::VirtualAddress;
+use super::{align_up, free_frames, lazy, release_range, GUARD_SIZE, PAGE_SIZE};
+use crate::interrupts;
+use alloc::collections::BTreeMap;
+use core::ptr::null_mut;
+use spin::Mutex;
+
+pub const VMALLOC_START: VirtualAddress = 0xe000_0000;
+pub const VMALLOC_MAX_SIZE: usize = 0x1000_0000; // 256 MiB
+
+struct Vmalloc {
+    brk: VirtualAddress,
+    areas: BTreeMap<VirtualAddress, usize>, // start -> mapped size
+}
+
+static VMALLOC: Mutex<Vmalloc> = Mutex::new(Vmalloc {
+    brk: VMALLOC_START,
+    areas: BTreeMap::new(),
+});
+
+impl Vmalloc {
+    fn allocate(&mut self, size: usize) -> *mut u8 {
+        if size == 0 {
+            return null_mut();
+        }
+        let size = align_up(size, PAGE_SIZE);
+        if size / PAGE_SIZE > free_frames() {
+            return null_mut();
+        }
+
+        let old_brk = self.brk;
+        let start = match self.find_hole(size + GUARD_SIZE) {
+            Some(start) => start,
+            None => {
+                let start = self.end_of_areas();
+                let increment = start + size + GUARD_SIZE - self.brk;
+                if self.brk(increment as isize).is_none() {
+                    return null_mut();
+                }
+                start
+            }
+        };
+
+        if !lazy::register("vmalloc", start, start + size) {
+            // the region doesn't keep growing with failed allocations
+            self.brk = old_brk;
+            return null_mut();
+        }
+        self.areas.insert(start, size);
+        start as *mut u8
+    }
+
+    fn free(&mut self, ptr: *mut u8) {
+        let start = ptr as VirtualAddress;
+        let size = self
+            .areas
+            .remove(&start)
+            .unwrap_or_else(|| panic!("{:p} is not a vmalloc pointer", ptr));
+        lazy::unregister(start);
+        release_range(start, start + size);
+    }
+
+    fn size(&self, ptr: *const u8) -> usize {
+        *self
+            .areas
+            .get(&(ptr as VirtualAddress))
+            .unwrap_or_else(|| panic!("{:p} is not a vmalloc pointer", ptr))
+    }
+
+    /// First address of the region where `size` bytes are free.
+    fn find_hole(&self, size: usize) -> Option<VirtualAddress> {
+        let mut start = VMALLOC_START;
+        for (&area_start, &area_size) in &self.areas {
+            if area_start - start >= size {
+                return Some(start);
+            }
+            start = area_start + area_size + GUARD_SIZE;
+        }
+        if self.brk - start >= size {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    fn end_of_areas(&self) -> VirtualAddress {
+        self.areas
+            .last_key_value()
+            .map_or(VMALLOC_START, |(&start, &size)| start + size + GUARD_SIZE)
+    }
+
+    /// Moves the end of the region by `increment` bytes, rounded up to whole pages.
+    /// The region can only shrink down to the end of its last area.
+    fn brk(&mut self, increment: isize) -> Option<VirtualAddress> {
+        let old_brk = self.brk;
+        let size = align_up(increment.unsigned_abs(), PAGE_SIZE);
+
+        if increment > 0 {
+            if size > VMALLOC_START + VMALLOC_MAX_SIZE - old_brk {
+                return None;
+            }
+            self.brk = old_brk + size;
+        } else if increment < 0 {
+            let new_brk = old_brk.checked_sub(size)?;
+            if new_brk < self.end_of_areas() {
+                return None;
+            }
+            self.brk = new_brk;
+        }
+
+        Some(old_brk)
+    }
+}
+
+/// Allocates `size` bytes, rounded up to whole pages, that are virtually
+/// but not necessarily physically contiguous.
+/// Returns a null pointer if the kernel is out of memory.
+pub fn vmalloc(size: usize) -> *mut u8 {
+    interrupts::without_interrupts(|| VMALLOC.lock().allocate(size))
+}
+
+/// Unmaps an area returned by `vmalloc` and frees its frames.
+pub unsafe fn vfree(ptr: *mut u8) {
+    interrupts::without_interrupts(|| VMALLOC.lock().free(ptr))
+}
+
+/// Returns the size of an area returned by `vmalloc`, rounded up to whole pages.
+pub fn vsize(ptr: *const u8) -> usize {
+    interrupts::without_interrupts(|| VMALLOC.lock().size(ptr))
+}
+
+/// Grows or shrinks the `vmalloc` region by `increment` bytes, rounded up
+/// to whole pages, and returns the previous end of the region.
+pub fn vbrk(increment: isize) -> Option<VirtualAddress> {
+    interrupts::without_interrupts(|| VMALLOC.lock().brk(increment))
+}
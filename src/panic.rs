@@ -0,0 +1,355 @@
+//! The panic screen. The registers, the top of the stack and the return
+//! addresses of the frame pointer chain are saved as soon as the kernel
+//! panics, then drawn along with the message and the symbols of the return
+//! addresses over the current screen without waiting for the writer, and
+//! the CPU is halted with its registers cleared.
+
+use crate::memory::paging::VirtualAddress;
+use crate::vga_buffer::{Color, Writer, VGA_WIDTH, WRITER};
+use crate::{gdt, memory, serial, symbols};
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::ops::Range;
+use core::panic::PanicInfo;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+const STACK_WORDS: usize = 12;
+const STACK_WORDS_PER_LINE: usize = 6;
+/// One frame per line, to fit on the screen with everything else.
+const BACKTRACE_DEPTH: usize = 8;
+
+const TITLE_COLORS: (Color, Color) = (Color::White, Color::Red);
+const HEADING_COLORS: (Color, Color) = (Color::Yellow, Color::Blue);
+const TEXT_COLORS: (Color, Color) = (Color::White, Color::Blue);
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// The general purpose registers, in the order `pushad` saves them. `esp`
+/// is the value it had before `pushad`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct GeneralRegisters {
+    edi: u32,
+    esi: u32,
+    ebp: u32,
+    esp: u32,
+    ebx: u32,
+    edx: u32,
+    ecx: u32,
+    eax: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SegmentRegisters {
+    cs: u16,
+    ds: u16,
+    es: u16,
+    fs: u16,
+    gs: u16,
+    ss: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ControlRegisters {
+    cr0: usize,
+    cr2: usize,
+    cr3: usize,
+    cr4: usize,
+    eflags: usize,
+}
+
+/// The state of the CPU when the kernel panicked, saved before anything is
+/// printed.
+struct Snapshot {
+    general: GeneralRegisters,
+    segments: SegmentRegisters,
+    control: ControlRegisters,
+    /// The words from `esp` up, if `esp` is on one of the kernel stacks.
+    stack: [u32; STACK_WORDS],
+    stack_len: usize,
+    /// The return addresses of the frames, innermost first.
+    backtrace: [VirtualAddress; BACKTRACE_DEPTH],
+    backtrace_len: usize,
+}
+
+/// Left in `.bss` after the panic, so that it can also be read with a debugger.
+static mut SNAPSHOT: Snapshot = Snapshot {
+    general: GeneralRegisters {
+        edi: 0,
+        esi: 0,
+        ebp: 0,
+        esp: 0,
+        ebx: 0,
+        edx: 0,
+        ecx: 0,
+        eax: 0,
+    },
+    segments: SegmentRegisters {
+        cs: 0,
+        ds: 0,
+        es: 0,
+        fs: 0,
+        gs: 0,
+        ss: 0,
+    },
+    control: ControlRegisters {
+        cr0: 0,
+        cr2: 0,
+        cr3: 0,
+        cr4: 0,
+        eflags: 0,
+    },
+    stack: [0; STACK_WORDS],
+    stack_len: 0,
+    backtrace: [0; BACKTRACE_DEPTH],
+    backtrace_len: 0,
+};
+
+impl Snapshot {
+    /// Inlined so that the registers are the ones of the panic handler.
+    #[inline(always)]
+    fn save(&mut self) {
+        unsafe {
+            asm!(
+                "pushad",
+                "mov esi, esp",
+                "mov ecx, 8",
+                "cld",
+                "rep movsd",
+                "popad",
+                in("edi") &mut self.general as *mut GeneralRegisters,
+            );
+            asm!(
+                "mov {0:x}, cs",
+                "mov {1:x}, ds",
+                "mov {2:x}, es",
+                "mov {3:x}, fs",
+                "mov {4:x}, gs",
+                "mov {5:x}, ss",
+                out(reg) self.segments.cs,
+                out(reg) self.segments.ds,
+                out(reg) self.segments.es,
+                out(reg) self.segments.fs,
+                out(reg) self.segments.gs,
+                out(reg) self.segments.ss,
+                options(nomem, nostack, preserves_flags),
+            );
+            asm!(
+                "mov {0}, cr0",
+                "mov {1}, cr2",
+                "mov {2}, cr3",
+                "mov {3}, cr4",
+                out(reg) self.control.cr0,
+                out(reg) self.control.cr2,
+                out(reg) self.control.cr3,
+                out(reg) self.control.cr4,
+                options(nomem, nostack, preserves_flags),
+            );
+            asm!(
+                "pushfd",
+                "pop {}",
+                out(reg) self.control.eflags,
+                options(nomem, preserves_flags),
+            );
+        }
+        self.save_stack();
+        self.save_backtrace();
+    }
+
+    fn save_stack(&mut self) {
+        let esp = self.general.esp as VirtualAddress;
+        let Some(stack) = kernel_stack_containing(esp) else {
+            return;
+        };
+        self.stack_len = STACK_WORDS.min((stack.end - esp) / 4);
+        for i in 0..self.stack_len {
+            self.stack[i] = unsafe { *(esp as *const u32).add(i) };
+        }
+    }
+
+    /// Follows the saved frame pointers, which stay on the stack they start
+    /// on and grow towards its top. The chain ends with the null frame
+    /// pointer pushed by `kernel_main` or by the double fault task.
+    fn save_backtrace(&mut self) {
+        let mut frame = self.general.ebp as VirtualAddress;
+        let Some(stack) = kernel_stack_containing(frame) else {
+            return;
+        };
+        while self.backtrace_len < BACKTRACE_DEPTH {
+            // the saved frame pointer, then the return address
+            if frame % 4 != 0 || !stack.contains(&(frame + 4)) {
+                break;
+            }
+            let (next, return_address) = unsafe {
+                let frame = frame as *const VirtualAddress;
+                (*frame, *frame.add(1))
+            };
+            if return_address == 0 {
+                break;
+            }
+            self.backtrace[self.backtrace_len] = return_address;
+            self.backtrace_len += 1;
+            if next <= frame {
+                break;
+            }
+            frame = next;
+        }
+    }
+}
+
+/// The stack that `address` is on, if any of the kernel: the boot stack or
+/// the one of the double fault task.
+fn kernel_stack_containing(address: VirtualAddress) -> Option<Range<VirtualAddress>> {
+    [memory::kernel_stack(), gdt::double_fault_stack()]
+        .into_iter()
+        .find(|stack| stack.contains(&address))
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    unsafe { asm!("cli", options(nomem, nostack)) };
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        // the panic screen itself panicked
+        halt()
+    }
+    let snapshot = unsafe { &mut *addr_of_mut!(SNAPSHOT) };
+    snapshot.save();
+
+    // nothing else runs anymore, so the locks held at panic time are never released
+    unsafe {
+        WRITER.force_unlock();
+        serial::force_unlock();
+    }
+    let _ = draw(&mut WRITER.lock(), info, snapshot);
+    halt()
+}
+
+fn draw(writer: &mut Writer, info: &PanicInfo, snapshot: &Snapshot) -> fmt::Result {
+    set_colors(writer, TEXT_COLORS);
+    writer.fill_screen();
+    set_colors(writer, TITLE_COLORS);
+    write!(writer, "{:<1$}", " KERNEL PANIC", VGA_WIDTH)?;
+    set_colors(writer, TEXT_COLORS);
+    writeln!(writer, "{}\n", info)?;
+
+    let GeneralRegisters {
+        edi,
+        esi,
+        ebp,
+        esp,
+        ebx,
+        edx,
+        ecx,
+        eax,
+    } = snapshot.general;
+    writeln!(
+        writer,
+        "eax {:08x}  ebx {:08x}  ecx {:08x}  edx {:08x}",
+        eax, ebx, ecx, edx
+    )?;
+    writeln!(
+        writer,
+        "esi {:08x}  edi {:08x}  ebp {:08x}  esp {:08x}",
+        esi, edi, ebp, esp
+    )?;
+    let SegmentRegisters {
+        cs,
+        ds,
+        es,
+        fs,
+        gs,
+        ss,
+    } = snapshot.segments;
+    writeln!(
+        writer,
+        "cs {:04x}  ds {:04x}  es {:04x}  fs {:04x}  gs {:04x}  ss {:04x}",
+        cs, ds, es, fs, gs, ss
+    )?;
+    let ControlRegisters {
+        cr0,
+        cr2,
+        cr3,
+        cr4,
+        eflags,
+    } = snapshot.control;
+    writeln!(
+        writer,
+        "cr0 {:08x}  cr2 {:08x}  cr3 {:08x}  cr4 {:08x}  eflags {:08x}\n",
+        cr0, cr2, cr3, cr4, eflags
+    )?;
+
+    set_colors(writer, HEADING_COLORS);
+    writeln!(writer, "stack:")?;
+    set_colors(writer, TEXT_COLORS);
+    if snapshot.stack_len == 0 {
+        writeln!(writer, "    esp is not on a kernel stack")?;
+    }
+    let stack = &snapshot.stack[..snapshot.stack_len];
+    for (i, line) in stack.chunks(STACK_WORDS_PER_LINE).enumerate() {
+        write!(
+            writer,
+            "{:08x}:",
+            esp as usize + i * STACK_WORDS_PER_LINE * 4
+        )?;
+        for word in line {
+            write!(writer, " {:08x}", word)?;
+        }
+        writeln!(writer)?;
+    }
+    writeln!(writer)?;
+
+    set_colors(writer, HEADING_COLORS);
+    writeln!(writer, "backtrace:")?;
+    set_colors(writer, TEXT_COLORS);
+    if snapshot.backtrace_len == 0 {
+        writeln!(writer, "    no frame found from ebp")?;
+    }
+    for &address in &snapshot.backtrace[..snapshot.backtrace_len] {
+        write!(writer, "    {:08x}", address)?;
+        if let Some((name, offset)) = symbols::symbolize(address) {
+            // "  ", then the name and "+0x" with the digits of the offset
+            let digits = (usize::BITS - offset.leading_zeros()).max(1).div_ceil(4) as usize;
+            let room = VGA_WIDTH - "    00000000  +0x".len() - digits;
+            write!(writer, "  ")?;
+            if name.len() > room {
+                // the end of the path is the function
+                let mut start = name.len() - (room - "...".len());
+                while !name.is_char_boundary(start) {
+                    start += 1;
+                }
+                write!(writer, "...{}", &name[start..])?;
+            } else {
+                write!(writer, "{}", name)?;
+            }
+            write!(writer, "+{:#x}", offset)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+fn set_colors(writer: &mut Writer, (foreground, background): (Color, Color)) {
+    writer.set_color(foreground, background);
+}
+
+/// Stops the CPU for good, with the general purpose registers cleared. Only
+/// a non-maskable interrupt can wake it up, and it halts again.
+fn halt() -> ! {
+    unsafe {
+        asm!(
+            "cli",
+            "xor eax, eax",
+            "xor ebx, ebx",
+            "xor ecx, ecx",
+            "xor edx, edx",
+            "xor esi, esi",
+            "xor edi, edi",
+            "xor ebp, ebp",
+            "2:",
+            "hlt",
+            "jmp 2b",
+            options(noreturn, nomem, nostack),
+        )
+    }
+}
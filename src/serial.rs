@@ -0,0 +1,171 @@
+//! Shell on the first serial port, enabled by the `serial` option.
+//!
+//! Everything written to the screen is also sent to the port, and the bytes
+//! received are decoded into keys for the shell, arrows included.
+
+use crate::keyboard::{DecodedKey, KeyCode};
+use crate::port::Port;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+const COM1: u16 = 0x3f8;
+pub const COM1_IRQ: u8 = 4;
+
+/// 115200 / 3 = 38400 baud.
+const DIVISOR: u16 = 3;
+
+const LINE_DLAB: u8 = 1 << 7;
+const LINE_8N1: u8 = 0x03;
+const FIFO_ENABLE_CLEAR_14: u8 = 0xc7;
+/// DTR, RTS and OUT2, which connects the interrupt line.
+const MODEM_DTR_RTS_OUT2: u8 = 0x0b;
+const INTERRUPT_RECEIVED: u8 = 1 << 0;
+const STATUS_DATA_READY: u8 = 1 << 0;
+const STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+pub struct SerialPort {
+    data: Port<u8>,
+    interrupt_enable: Port<u8>,
+    fifo_control: Port<u8>,
+    line_control: Port<u8>,
+    modem_control: Port<u8>,
+    line_status: Port<u8>,
+}
+
+impl SerialPort {
+    const fn new(base: u16) -> SerialPort {
+        SerialPort {
+            data: Port::new(base),
+            interrupt_enable: Port::new(base + 1),
+            fifo_control: Port::new(base + 2),
+            line_control: Port::new(base + 3),
+            modem_control: Port::new(base + 4),
+            line_status: Port::new(base + 5),
+        }
+    }
+
+    unsafe fn init(&mut self) {
+        self.interrupt_enable.write(0);
+        self.line_control.write(LINE_DLAB);
+        self.data.write(DIVISOR as u8);
+        self.interrupt_enable.write((DIVISOR >> 8) as u8);
+        self.line_control.write(LINE_8N1);
+        self.fifo_control.write(FIFO_ENABLE_CLEAR_14);
+        self.modem_control.write(MODEM_DTR_RTS_OUT2);
+        self.interrupt_enable.write(INTERRUPT_RECEIVED);
+    }
+
+    fn send(&mut self, byte: u8) {
+        unsafe {
+            while self.line_status.read() & STATUS_TRANSMIT_EMPTY == 0 {}
+            self.data.write(byte);
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        unsafe {
+            if self.line_status.read() & STATUS_DATA_READY != 0 {
+                Some(self.data.read())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+static COM1_PORT: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1));
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Starts mirroring the screen to the port. The interrupt of the port still
+/// has to be unmasked.
+pub fn init() {
+    unsafe { COM1_PORT.lock().init() };
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Sends a byte written to the screen, if the port is enabled.
+pub fn write_byte(byte: u8) {
+    if !enabled() {
+        return;
+    }
+    let mut port = COM1_PORT.lock();
+    match byte {
+        b'\n' => {
+            port.send(b'\r');
+            port.send(b'\n');
+        }
+        0x20..=0x7e => port.send(byte),
+        _ => port.send(b'?'),
+    }
+}
+
+/// Releases the port if it was locked when the kernel panicked, so that
+/// the panic screen still reaches it.
+pub unsafe fn force_unlock() {
+    COM1_PORT.force_unlock();
+}
+
+/// Takes a received byte. The lock of the port is released on return, so
+/// that the key can be given to the shell, which writes to the port.
+pub fn receive() -> Option<u8> {
+    COM1_PORT.lock().receive()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+    None,
+    Escape,
+    ControlSequence,
+}
+
+/// Turns the bytes sent by a terminal into keys.
+pub struct SerialDecoder {
+    state: EscapeState,
+}
+
+impl SerialDecoder {
+    pub const fn new() -> SerialDecoder {
+        SerialDecoder {
+            state: EscapeState::None,
+        }
+    }
+
+    pub fn add_byte(&mut self, byte: u8) -> Option<DecodedKey> {
+        match (self.state, byte) {
+            (EscapeState::None, 0x1b) => {
+                self.state = EscapeState::Escape;
+                None
+            }
+            (EscapeState::Escape, b'[') => {
+                self.state = EscapeState::ControlSequence;
+                None
+            }
+            (EscapeState::ControlSequence, b'0'..=b'9' | b';') => None,
+            (EscapeState::ControlSequence, _) => {
+                self.state = EscapeState::None;
+                let code = match byte {
+                    b'A' => KeyCode::ArrowUp,
+                    b'B' => KeyCode::ArrowDown,
+                    b'C' => KeyCode::ArrowRight,
+                    b'D' => KeyCode::ArrowLeft,
+                    b'H' => KeyCode::Home,
+                    b'F' => KeyCode::End,
+                    _ => return None,
+                };
+                Some(DecodedKey::RawKey(code))
+            }
+            (EscapeState::Escape, _) => {
+                self.state = EscapeState::None;
+                None
+            }
+            (EscapeState::None, b'\r' | b'\n') => Some(DecodedKey::Unicode('\n')),
+            (EscapeState::None, 0x08 | 0x7f) => Some(DecodedKey::Unicode('\x08')),
+            (EscapeState::None, 0x20..=0x7e) => Some(DecodedKey::Unicode(byte as char)),
+            (EscapeState::None, _) => None,
+        }
+    }
+}
@@ -1,24 +1,26 @@
-use super::Shell;
+use super::{Shell, SHELL};
 use crate::{
+    cmdline, gdt, interrupts,
+    memory::{self, paging::iounmap, slab},
     port::Port,
     print, println,
+    signal::{self, CallbackId, Signal},
+    symbols, tests,
     vga_buffer::{VGA_WIDTH, WRITER},
 };
 use core::arch::asm;
 use lazy_static::lazy_static;
+use multiboot::{FramebufferType, MemoryAreaType, Rsdp};
+use spin::Mutex;
 
 const HEXDUMP_LINE_SIZE: usize = 16;
 
 extern "C" {
-    static gdt_start: usize;
-    static gdt_pointer: usize;
     static stack_top: usize;
     static stack_bottom: usize;
 }
 
 lazy_static! {
-    static ref GDT_START: usize = unsafe { &gdt_start as *const usize as usize };
-    static ref GDT_POINTER: usize = unsafe { &gdt_pointer as *const usize as usize };
     static ref STACK_TOP: usize = unsafe { &stack_top as *const usize as usize };
     static ref STACK_BOTTOM: usize = unsafe { &stack_bottom as *const usize as usize };
 }
@@ -68,28 +70,377 @@ fn hexdump(start: usize, end: usize) {
     }
 }
 
+fn print_rsdp(rsdp: &Rsdp) {
+    print!(
+        "ACPI: RSDP revision {}, OEM {}, RSDT at {:#x}",
+        rsdp.revision(),
+        rsdp.oem_id(),
+        rsdp.rsdt_address()
+    );
+    if let Some(xsdt) = rsdp.xsdt_address() {
+        print!(", XSDT at {:#x}", xsdt);
+    }
+    if memory::acpi_memory_reclaimed() {
+        print!(" (reclaimed)");
+    }
+    println!(
+        "{}",
+        if rsdp.checksum_is_valid() {
+            ""
+        } else {
+            " (invalid checksum)"
+        }
+    );
+}
+
+fn print_boot_info() {
+    let boot_info = memory::boot_info();
+    if let Some(name) = boot_info.boot_loader_name() {
+        println!("boot loader: {}", name);
+    }
+    if let Some(command_line) = boot_info.command_line() {
+        println!("command line: {}", command_line);
+    }
+    if let Some(address) = boot_info.load_base_address() {
+        println!("load base address: {:#x}", address);
+    }
+    if let Some(info) = boot_info.basic_memory_info() {
+        println!("memory: {} KiB lower, {} KiB upper", info.lower, info.upper);
+    }
+    if let Some(device) = boot_info.boot_device() {
+        println!(
+            "boot device: {:#x}, partition {:#x}, sub-partition {:#x}",
+            device.bios_device, device.partition, device.sub_partition
+        );
+    }
+    for module in boot_info.module_tags() {
+        println!(
+            "module: {:#x}-{:#x} {}",
+            module.start, module.end, module.command_line
+        );
+    }
+    if let Some(framebuffer) = boot_info.framebuffer_info() {
+        print!(
+            "framebuffer: {}x{} at {:#x}, {} bpp, pitch {}, ",
+            framebuffer.width,
+            framebuffer.height,
+            framebuffer.address,
+            framebuffer.bits_per_pixel,
+            framebuffer.pitch
+        );
+        match framebuffer.typ {
+            FramebufferType::Indexed { palette } => {
+                print!("{} colors", palette.len());
+                if let Some(color) = palette.first() {
+                    print!(
+                        ", first #{:02x}{:02x}{:02x}",
+                        color.red, color.green, color.blue
+                    );
+                }
+                println!();
+            }
+            FramebufferType::Rgb { red, green, blue } => println!(
+                "RGB {}@{} {}@{} {}@{}",
+                red.size, red.position, green.size, green.position, blue.size, blue.position
+            ),
+            FramebufferType::Text => println!("text"),
+        }
+    }
+    if let Some(apm) = boot_info.apm_table() {
+        println!(
+            "APM: version {:#x}, flags {:#x}, code {:#x}:{:#x} ({} bytes), \
+             16-bit code {:#x} ({} bytes), data {:#x} ({} bytes)",
+            apm.version,
+            apm.flags,
+            apm.code_segment,
+            apm.offset,
+            apm.code_segment_length,
+            apm.code_segment_16,
+            apm.code_segment_16_length,
+            apm.data_segment,
+            apm.data_segment_length
+        );
+    }
+    if let Some(rsdp) = boot_info.acpi_new_rsdp().or(boot_info.acpi_old_rsdp()) {
+        print_rsdp(&rsdp);
+    }
+}
+
+/// `bytes` in the biggest unit that keeps it above 1.
+fn human_size(bytes: u64) -> (u64, &'static str) {
+    match bytes {
+        0..0x400 => (bytes, "B"),
+        0x400..0x10_0000 => (bytes >> 10, "KiB"),
+        0x10_0000..0x4000_0000 => (bytes >> 20, "MiB"),
+        _ => (bytes >> 30, "GiB"),
+    }
+}
+
+fn memory_area_type_name(typ: MemoryAreaType) -> &'static str {
+    match typ {
+        MemoryAreaType::Available => "available",
+        MemoryAreaType::Reserved => "reserved",
+        MemoryAreaType::AcpiReclaimable if memory::acpi_memory_reclaimed() => "ACPI (reclaimed)",
+        MemoryAreaType::AcpiReclaimable => "ACPI reclaimable",
+        MemoryAreaType::AcpiNvs => "ACPI NVS",
+        MemoryAreaType::BadMemory => "bad memory",
+    }
+}
+
+const MEMORY_AREA_TYPES: [MemoryAreaType; 5] = [
+    MemoryAreaType::Available,
+    MemoryAreaType::Reserved,
+    MemoryAreaType::AcpiReclaimable,
+    MemoryAreaType::AcpiNvs,
+    MemoryAreaType::BadMemory,
+];
+
+fn print_memory_map() {
+    let Some(memory_map_tag) = memory::boot_info().memory_map_tag() else {
+        println!("No memory map.");
+        return;
+    };
+    println!("{:12} {:12} {:>8}     type", "start", "end", "size");
+    for area in memory_map_tag.all_memory_areas() {
+        let (size, unit) = human_size(area.length);
+        println!(
+            "{:#012x} {:#012x} {:>8} {:3} {}",
+            area.base_addr,
+            area.end_address(),
+            size,
+            unit,
+            memory_area_type_name(area.typ())
+        );
+        // the reservations are printed under the areas they are in
+        for reservation in memory::reservations() {
+            let (start, end) = (reservation.start as u64, reservation.end as u64);
+            if start < area.end_address() && end > area.base_addr {
+                let (size, unit) = human_size(end - start);
+                println!(
+                    "  {:#010x}   {:#010x} {:>8} {:3}   {}",
+                    start, end, size, unit, reservation.name
+                );
+            }
+        }
+    }
+
+    println!();
+    for typ in MEMORY_AREA_TYPES {
+        let total: u64 = memory_map_tag
+            .all_memory_areas()
+            .filter(|area| area.typ() == typ)
+            .map(|area| area.length)
+            .sum();
+        if total != 0 {
+            let (size, unit) = human_size(total);
+            println!("{:>16}: {:>8} {}", memory_area_type_name(typ), size, unit);
+        }
+    }
+    memory::with_memory(|_, frame_allocator| {
+        println!(
+            "{:>16}: {} total, {} used, {} free",
+            "frames",
+            frame_allocator.total_frames(),
+            frame_allocator.used_frames(),
+            frame_allocator.free_frames()
+        )
+    });
+}
+
+fn print_cmdline() {
+    let params = cmdline::params();
+    println!("raw: \"{}\"", params.raw);
+    println!("pae: {}", params.pae);
+    println!("keymap: {:?}", params.keymap);
+    println!("screens: {}", params.screens);
+    println!("verbosity: {:?}", params.verbosity);
+    println!("serial: {}", params.serial);
+    println!("pit_hz: {}", params.pit_hz);
+    for warning in params.warnings() {
+        println!("warning: {}", warning);
+    }
+}
+
+fn print_symbol(args: &str) {
+    if !symbols::loaded() {
+        println!("sym: no symbol table, the bootloader didn't load it");
+        return;
+    }
+    let digits = args.strip_prefix("0x").unwrap_or(args);
+    let Ok(address) = usize::from_str_radix(digits, 16) else {
+        println!("usage: sym <address>");
+        return;
+    };
+    match symbols::symbolize(address) {
+        Some((name, offset)) => println!("{:#010x}: {}+{:#x}", address, name, offset),
+        None => println!("{:#010x}: no symbol", address),
+    }
+}
+
+fn print_symbol_addresses(name: &str) {
+    if !symbols::loaded() {
+        println!("addr: no symbol table, the bootloader didn't load it");
+        return;
+    }
+    if name.is_empty() || name.contains(' ') {
+        println!("usage: addr <name>");
+        return;
+    }
+    let mut count = 0;
+    for (symbol, address) in symbols::lookup(name) {
+        println!("{:#010x}: {}", address, symbol);
+        count += 1;
+    }
+    if count == 0 {
+        println!("addr: no symbol named \"{}\"", name);
+    }
+}
+
+const SIGNAL_USAGE: &str = "usage: signal [list | raise <signal> [now] | \
+                            schedule <signal> <ticks> | cancel <signal> | \
+                            handle <signal> | unhandle <signal>]";
+
+/// The callbacks registered by `signal handle`, at most one per signal.
+static HANDLED_SIGNALS: Mutex<[Option<CallbackId>; signal::SIGNAL_COUNT]> =
+    Mutex::new([None; signal::SIGNAL_COUNT]);
+
+/// The callback registered by `signal handle`, which tells when a signal is delivered.
+fn print_delivered_signal(signal: Signal) {
+    let ticks = interrupts::ticks();
+    interrupts::without_interrupts(|| match SHELL.try_lock() {
+        Some(shell) => shell.print_message(format_args!(
+            "signal {} delivered at tick {}",
+            signal, ticks
+        )),
+        // raised by a command, whose output this is part of
+        None => println!("signal {} delivered at tick {}", signal, ticks),
+    });
+}
+
+fn parse_signal(arg: &str) -> Option<Signal> {
+    arg.parse()
+        .ok()
+        .filter(|&signal: &Signal| (signal as usize) < signal::SIGNAL_COUNT)
+}
+
+fn signal_command(args: &str) {
+    let mut words = args.split_whitespace();
+    let words = [words.next(), words.next(), words.next(), words.next()];
+    let signal = words[1].and_then(parse_signal);
+    match (words, signal) {
+        ([None | Some("list"), None, ..], _) => print_signals(),
+        ([Some("raise"), _, None, None], Some(signal)) => {
+            signal::raise_deferred(signal);
+            println!("signal {} pending", signal);
+        }
+        ([Some("raise"), _, Some("now"), None], Some(signal)) => {
+            let count = signal::raise(signal);
+            println!("signal {} delivered to {} callbacks", signal, count);
+        }
+        ([Some("schedule"), _, Some(ticks), None], Some(signal)) => match ticks.parse() {
+            Ok(ticks) if signal::schedule(signal, ticks) => {
+                println!("signal {} scheduled in {} ticks", signal, ticks)
+            }
+            Ok(_) => println!("signal: too many scheduled signals"),
+            Err(_) => println!("{}", SIGNAL_USAGE),
+        },
+        ([Some("cancel"), _, None, None], Some(signal)) => signal::cancel(signal),
+        ([Some("handle"), _, None, None], Some(signal)) => {
+            let mut handled = HANDLED_SIGNALS.lock();
+            if handled[signal as usize].is_some() {
+                println!("signal: signal {} is already handled", signal);
+                return;
+            }
+            match signal::register(signal, "shell", print_delivered_signal) {
+                Some(id) => handled[signal as usize] = Some(id),
+                None => println!("signal: too many callbacks"),
+            }
+        }
+        ([Some("unhandle"), _, None, None], Some(signal)) => {
+            match HANDLED_SIGNALS.lock()[signal as usize].take() {
+                Some(id) => signal::unregister(id),
+                None => println!("signal: signal {} is not handled", signal),
+            }
+        }
+        _ => println!("{}", SIGNAL_USAGE),
+    }
+}
+
+fn print_signals() {
+    let mut count = 0;
+    for signal in 0..signal::SIGNAL_COUNT as Signal {
+        let pending = signal::is_pending(signal);
+        let next = signal::scheduled()
+            .filter(|&(scheduled, _)| scheduled == signal)
+            .map(|(_, ticks)| ticks)
+            .min();
+        let mut callbacks = signal::callbacks()
+            .filter(|&(registered, _)| registered == signal)
+            .map(|(_, name)| name)
+            .peekable();
+        if !pending && next.is_none() && callbacks.peek().is_none() {
+            continue;
+        }
+        if count == 0 {
+            println!(
+                "{:>6}  {:7}  {:13}  callbacks",
+                "signal", "pending", "scheduled in"
+            );
+        }
+        print!("{:>6}  {:7}  ", signal, if pending { "yes" } else { "no" });
+        match next {
+            Some(ticks) => print!("{:>7} ticks  ", ticks),
+            None => print!("{:13}  ", "-"),
+        }
+        for (i, name) in callbacks.enumerate() {
+            print!("{}{}", if i == 0 { "" } else { ", " }, name);
+        }
+        println!();
+        count += 1;
+    }
+    if count == 0 {
+        println!("No signal has callbacks, is pending or is scheduled.");
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct CommandHandler {
     pub name: &'static [u8],
     description: &'static [u8],
-    pub handler: fn(&Shell), // Does it really make sense to take a shell as argument?
+    /// Takes the arguments, the rest of the command line.
+    pub handler: fn(&Shell, &str), // Does it really make sense to take a shell as argument?
 }
 
 pub const COMMAND_HANDLERS: &[CommandHandler] = &[
+    CommandHandler {
+        name: b"addr",
+        description: b"Show the address of the symbols named <name>.",
+        handler: |_: &Shell, args: &str| print_symbol_addresses(args),
+    },
+    CommandHandler {
+        name: b"bootinfo",
+        description: b"Show the information given by the bootloader.",
+        handler: |_: &Shell, _: &str| print_boot_info(),
+    },
     CommandHandler {
         name: b"clear",
         description: b"Clear the screen.",
-        handler: |_: &Shell| WRITER.lock().clear_screen(),
+        handler: |_: &Shell, _: &str| WRITER.lock().clear_screen(),
+    },
+    CommandHandler {
+        name: b"cmdline",
+        description: b"Show the kernel command line options.",
+        handler: |_: &Shell, _: &str| print_cmdline(),
     },
     CommandHandler {
         name: b"exit",
         description: b"Exit the system.",
-        handler: |_: &Shell| exit_qemu(QemuExitCode::Success),
+        handler: |_: &Shell, _: &str| exit_qemu(QemuExitCode::Success),
     },
     CommandHandler {
         name: b"halt",
         description: b"Halt the system.",
-        handler: |_: &Shell| unsafe {
+        handler: |_: &Shell, _: &str| unsafe {
             asm!("cli");
             print!("System halted.");
             WRITER.lock().set_cursor(VGA_WIDTH);
@@ -99,7 +450,7 @@ pub const COMMAND_HANDLERS: &[CommandHandler] = &[
     CommandHandler {
         name: b"help",
         description: b"Show this help message.",
-        handler: |_: &Shell| {
+        handler: |_: &Shell, _: &str| {
             println!("Available commands:");
             let max_length = COMMAND_HANDLERS
                 .iter()
@@ -115,33 +466,134 @@ pub const COMMAND_HANDLERS: &[CommandHandler] = &[
             }
         },
     },
+    CommandHandler {
+        name: b"mmap",
+        description: b"Show the memory map, or reclaim the ACPI memory.",
+        handler: |_: &Shell, args: &str| match args.trim() {
+            "" => print_memory_map(),
+            "reclaim" if memory::acpi_memory_reclaimed() => {
+                println!("mmap: the ACPI memory is already reclaimed")
+            }
+            "reclaim" => {
+                let free_frames = memory::free_frames();
+                memory::reclaim_acpi_memory();
+                println!(
+                    "mmap: {} frames reclaimed, the ACPI tables are gone",
+                    memory::free_frames() - free_frames
+                );
+            }
+            _ => println!("usage: mmap [reclaim]"),
+        },
+    },
+    CommandHandler {
+        name: b"modules",
+        description: b"List the boot modules.",
+        handler: |_: &Shell, _: &str| {
+            let mut count = 0;
+            for module in memory::modules() {
+                println!(
+                    "{:16} {:#010x}-{:#010x} {:>8} bytes  {}",
+                    module.name,
+                    module.start,
+                    module.end,
+                    module.len(),
+                    module.command_line
+                );
+                if !module.is_empty() {
+                    // only what fits on the line, a module can be too big for the ioremap window
+                    let bytes = module.map(VGA_WIDTH - 18);
+                    print!("{:16} ", "");
+                    for &byte in bytes {
+                        WRITER.lock().write_byte(match byte {
+                            0x20..=0x7e => byte,
+                            _ => b'.',
+                        });
+                    }
+                    println!();
+                    iounmap(bytes.as_ptr() as usize);
+                }
+                count += 1;
+            }
+            if count == 0 {
+                println!("No boot modules.");
+            }
+        },
+    },
     CommandHandler {
         name: b"pgdt",
         description: b"Print the GDT.",
-        handler: |_: &Shell| {
-            for address in (*GDT_START..*GDT_POINTER).step_by(8) {
-                print!("{:#07x}:", address);
-                for i in 0..8 {
-                    let value = unsafe { *((address + i) as *const u8) };
-                    print!(" {value:08b}");
-                }
-                println!();
+        handler: |_: &Shell, _: &str| {
+            for (index, descriptor) in gdt::loaded_table().iter().enumerate() {
+                println!("{:#04x}: {}", index * 8, descriptor);
             }
         },
     },
     CommandHandler {
         name: b"pks",
         description: b"Print the kernel stack.",
-        handler: |_: &Shell| hexdump(*STACK_BOTTOM, *STACK_TOP),
+        handler: |_: &Shell, _: &str| hexdump(*STACK_BOTTOM, *STACK_TOP),
     },
     CommandHandler {
         name: b"reboot",
         description: b"Reboot the system.",
-        handler: |_: &Shell| unsafe { Port::new(0x64).write(0xFEu8) },
+        handler: |_: &Shell, _: &str| unsafe { Port::new(0x64).write(0xFEu8) },
+    },
+    CommandHandler {
+        name: b"signal",
+        description: b"List, raise, schedule, cancel, handle or unhandle signals.",
+        handler: |_: &Shell, args: &str| signal_command(args),
+    },
+    CommandHandler {
+        name: b"slabinfo",
+        description: b"Show the slab caches statistics.",
+        handler: |_: &Shell, _: &str| {
+            println!(
+                "{:16} {:>7} {:>7} {:>7} {:>8} {:>6} {:>7}",
+                "name", "objsize", "active", "total", "obj/slab", "slabs", "wasted"
+            );
+            slab::for_each_cache(|cache| {
+                let info = cache.info();
+                println!(
+                    "{:16} {:>7} {:>7} {:>7} {:>8} {:>6} {:>7}",
+                    info.name,
+                    info.object_size,
+                    info.active_objects,
+                    info.total_objects,
+                    info.objects_per_slab,
+                    info.slabs,
+                    info.wasted
+                );
+            });
+        },
+    },
+    CommandHandler {
+        name: b"sym",
+        description: b"Show the symbol that the hexadecimal <address> is in.",
+        handler: |_: &Shell, args: &str| print_symbol(args),
+    },
+    CommandHandler {
+        name: b"test",
+        description: b"Run the kernel tests.",
+        handler: |_: &Shell, _: &str| tests::run_all(),
+    },
+    CommandHandler {
+        name: b"uptime",
+        description: b"Show the time since the timer was started.",
+        handler: |_: &Shell, _: &str| {
+            let ticks = interrupts::ticks();
+            let hz = interrupts::pit_frequency() as usize;
+            println!(
+                "{}.{:03} s ({} ticks at {} Hz)",
+                ticks / hz,
+                ticks % hz * 1000 / hz,
+                ticks,
+                hz
+            );
+        },
     },
     CommandHandler {
         name: b"tty",
         description: b"Show the current screen number.",
-        handler: |shell: &Shell| println!("F{}", shell.screen_idx + 1),
+        handler: |shell: &Shell, _: &str| println!("F{}", shell.screen_idx + 1),
     },
 ];
@@ -1,9 +1,11 @@
 mod command_handlers;
 
+use crate::cmdline::params;
 use crate::keyboard::{DecodedKey, KeyCode};
 use crate::println;
 use crate::vga_buffer::{Color, VGA_SCREENS, VGA_WIDTH, WRITER};
 use command_handlers::{exit_qemu, QemuExitCode, COMMAND_HANDLERS};
+use core::fmt;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
@@ -79,7 +81,7 @@ pub struct Shell {
 
 impl Shell {
     pub fn init(&mut self) {
-        for i in (0..VGA_SCREENS).rev() {
+        for i in (0..params().screens).rev() {
             // TODO: don't write to vga_buffer for screens 1..VGA_SCREENS
             self.screen_idx = i;
             WRITER.lock().switch_screen(i, 0);
@@ -149,7 +151,7 @@ impl Shell {
     }
 
     fn switch_screen(&mut self, screen_idx: usize) {
-        if screen_idx != self.screen_idx && screen_idx < VGA_SCREENS {
+        if screen_idx != self.screen_idx && screen_idx < params().screens {
             self.screen_idx = screen_idx;
             WRITER
                 .lock()
@@ -167,6 +169,20 @@ impl Shell {
         WRITER.lock().reset_foreground_color();
     }
 
+    /// Prints a message that doesn't come from a command, such as one of a
+    /// signal callback, on its own line, then the prompt and the command
+    /// being typed again.
+    pub fn print_message(&self, message: fmt::Arguments) {
+        WRITER.lock().write_byte(b'\n');
+        println!("{}", message);
+        self.print_prompt();
+        let command = &self.commands[self.screen_idx];
+        for &byte in &command.buffer[..command.len] {
+            WRITER.lock().write_byte(byte);
+        }
+        WRITER.lock().set_cursor(PROMPT.len() + command.pos);
+    }
+
     fn print_welcome_line(left: u8, left2: u8, middle: u8, right2: u8, right: u8) {
         WRITER.lock().write_bytes(b' ', WELCOME_MARGIN);
         WRITER.lock().write_byte(left);
@@ -228,20 +244,25 @@ impl Shell {
     }
 
     fn execute_command(&self) {
-        // TODO: split args (right now it's just a single command)
         let command_buffer = self.commands[self.screen_idx].trimmed();
         if command_buffer.is_empty() {
             return;
         }
+        let (name, args) = match command_buffer.iter().position(|&byte| byte == b' ') {
+            Some(space) => (&command_buffer[..space], &command_buffer[space + 1..]),
+            None => (command_buffer, &[][..]),
+        };
+        // only printable ASCII can be typed
+        let args = core::str::from_utf8(args).unwrap_or("").trim_start();
         for handler in COMMAND_HANDLERS.iter() {
-            if handler.name == command_buffer {
-                (handler.handler)(&self);
+            if handler.name == name {
+                (handler.handler)(&self, args);
                 return;
             }
         }
         println!(
             "kfs: command not found: \"{}\"",
-            core::str::from_utf8(command_buffer).unwrap_or("invalid utf-8")
+            core::str::from_utf8(name).unwrap_or("invalid utf-8")
         );
     }
 }
@@ -0,0 +1,199 @@
+//! Numbered signals, which subsystems register callbacks for.
+//!
+//! A signal raised with `raise` runs its callbacks right away, in the
+//! context of the caller. One raised with `raise_deferred`, or scheduled
+//! after a number of timer ticks, is only marked pending, and its callbacks
+//! run at the next safe point: in the idle loop of `kernel_main`, once the
+//! interrupt handlers have returned. As with Unix signals, a signal raised
+//! again while it is pending is only delivered once.
+
+use crate::interrupts;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+
+pub type Signal = u8;
+
+/// One bit of `PENDING` per signal.
+pub const SIGNAL_COUNT: usize = u32::BITS as usize;
+
+const MAX_CALLBACKS: usize = 32;
+const MAX_SCHEDULED: usize = 16;
+
+pub type Callback = fn(Signal);
+
+#[derive(Clone, Copy)]
+struct Registration {
+    signal: Signal,
+    name: &'static str,
+    callback: Callback,
+}
+
+/// A registered callback, to unregister it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallbackId(usize);
+
+#[derive(Clone, Copy)]
+struct Scheduled {
+    signal: Signal,
+    start: usize,
+    ticks: usize,
+}
+
+impl Scheduled {
+    fn remaining_ticks(&self, now: usize) -> usize {
+        self.ticks.saturating_sub(now.wrapping_sub(self.start))
+    }
+}
+
+// only locked with interrupts disabled, as signals can be raised from interrupt handlers
+static CALLBACKS: Mutex<[Option<Registration>; MAX_CALLBACKS]> = Mutex::new([None; MAX_CALLBACKS]);
+static SCHEDULED: Mutex<[Option<Scheduled>; MAX_SCHEDULED]> = Mutex::new([None; MAX_SCHEDULED]);
+static PENDING: AtomicU32 = AtomicU32::new(0);
+
+fn bit(signal: Signal) -> u32 {
+    assert!(
+        (signal as usize) < SIGNAL_COUNT,
+        "invalid signal {}",
+        signal
+    );
+    1 << signal
+}
+
+/// Makes `callback` run whenever `signal` is delivered, after the callbacks
+/// registered before it. `name` tells who registered it.
+/// Returns `None` if there are already too many callbacks.
+pub fn register(signal: Signal, name: &'static str, callback: Callback) -> Option<CallbackId> {
+    bit(signal);
+    interrupts::without_interrupts(|| {
+        let mut callbacks = CALLBACKS.lock();
+        let index = callbacks.iter().position(Option::is_none)?;
+        callbacks[index] = Some(Registration {
+            signal,
+            name,
+            callback,
+        });
+        Some(CallbackId(index))
+    })
+}
+
+pub fn unregister(id: CallbackId) {
+    interrupts::without_interrupts(|| CALLBACKS.lock()[id.0] = None);
+}
+
+/// Runs the callbacks of `signal` now, and returns how many there were.
+pub fn raise(signal: Signal) -> usize {
+    bit(signal);
+    // the callbacks may register or raise signals themselves
+    let callbacks = interrupts::without_interrupts(|| *CALLBACKS.lock());
+    let mut count = 0;
+    for registration in callbacks.iter().flatten() {
+        if registration.signal == signal {
+            (registration.callback)(signal);
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Marks `signal` pending, its callbacks run at the next safe point.
+pub fn raise_deferred(signal: Signal) {
+    PENDING.fetch_or(bit(signal), Ordering::Relaxed);
+}
+
+/// Raises `signal` deferred once `ticks` timer interrupts have passed.
+/// Returns `false` if there are already too many scheduled signals.
+pub fn schedule(signal: Signal, ticks: usize) -> bool {
+    bit(signal);
+    let start = interrupts::ticks();
+    interrupts::without_interrupts(|| {
+        let mut scheduled = SCHEDULED.lock();
+        let Some(entry) = scheduled.iter_mut().find(|entry| entry.is_none()) else {
+            return false;
+        };
+        *entry = Some(Scheduled {
+            signal,
+            start,
+            ticks,
+        });
+        true
+    })
+}
+
+/// Drops the pending and scheduled deliveries of `signal`.
+pub fn cancel(signal: Signal) {
+    PENDING.fetch_and(!bit(signal), Ordering::Relaxed);
+    interrupts::without_interrupts(|| {
+        for entry in SCHEDULED.lock().iter_mut() {
+            if entry.is_some_and(|scheduled| scheduled.signal == signal) {
+                *entry = None;
+            }
+        }
+    });
+}
+
+/// Marks pending the scheduled signals whose time has come.
+fn raise_due_signals() {
+    let now = interrupts::ticks();
+    interrupts::without_interrupts(|| {
+        for entry in SCHEDULED.lock().iter_mut() {
+            if let Some(scheduled) = entry {
+                if scheduled.remaining_ticks(now) == 0 {
+                    raise_deferred(scheduled.signal);
+                    *entry = None;
+                }
+            }
+        }
+    });
+}
+
+/// Whether a signal is waiting for `dispatch`.
+pub fn pending() -> bool {
+    raise_due_signals();
+    PENDING.load(Ordering::Relaxed) != 0
+}
+
+pub fn is_pending(signal: Signal) -> bool {
+    PENDING.load(Ordering::Relaxed) & bit(signal) != 0
+}
+
+/// Delivers the pending signals, lowest number first. Must only be called
+/// at a safe point, outside of interrupt handlers.
+pub fn dispatch() {
+    raise_due_signals();
+    let pending = PENDING.swap(0, Ordering::Relaxed);
+    for signal in 0..SIGNAL_COUNT as Signal {
+        if pending & bit(signal) != 0 {
+            raise(signal);
+        }
+    }
+}
+
+/// If `signal` is pending, clears it and runs its callbacks now, like
+/// `raise`, and returns `true`. The other pending signals are left to `dispatch`.
+pub fn raise_pending(signal: Signal) -> bool {
+    raise_due_signals();
+    let pending = PENDING.fetch_and(!bit(signal), Ordering::Relaxed) & bit(signal) != 0;
+    if pending {
+        raise(signal);
+    }
+    pending
+}
+
+/// The registered callbacks, by the signal and the name they were registered with.
+pub fn callbacks() -> impl Iterator<Item = (Signal, &'static str)> {
+    let callbacks = interrupts::without_interrupts(|| *CALLBACKS.lock());
+    callbacks
+        .into_iter()
+        .flatten()
+        .map(|registration| (registration.signal, registration.name))
+}
+
+/// The scheduled signals, with the number of ticks before they are raised.
+pub fn scheduled() -> impl Iterator<Item = (Signal, usize)> {
+    let now = interrupts::ticks();
+    let scheduled = interrupts::without_interrupts(|| *SCHEDULED.lock());
+    scheduled
+        .into_iter()
+        .flatten()
+        .map(move |scheduled| (scheduled.signal, scheduled.remaining_ticks(now)))
+}
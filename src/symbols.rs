@@ -0,0 +1,719 @@
+//! The function symbols of the kernel, to name the addresses of backtraces
+//! and exception reports. They are read once at boot from the ELF symbol
+//! table, which GRUB loads along with the kernel, and their names are
+//! demangled. Multiboot1 loaders such as `qemu -kernel` don't load it, so
+//! addresses stay unnamed.
+
+use crate::memory::paging::{ioremap, iounmap, CacheMode, VirtualAddress};
+use crate::memory::{self, PAGE_SIZE};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use multiboot::{BootInformation, ElfSection, ElfSectionType};
+use spin::Once;
+
+/// Size of an entry of the symbol table of a 32-bit ELF.
+const SYMBOL_SIZE: usize = 16;
+
+// symbol types, in the low 4 bits of the info byte
+const NO_TYPE: u8 = 0;
+const FUNCTION: u8 = 2;
+const UNDEFINED_SECTION: u16 = 0;
+
+/// The labels of `asm/` have no size, they are taken to span up to the
+/// next symbol, but not past this.
+const MAX_LABEL_SIZE: usize = PAGE_SIZE;
+
+struct Symbol {
+    address: VirtualAddress,
+    /// 0 for the labels of `asm/`.
+    size: usize,
+    name: String,
+}
+
+/// Sorted by address.
+static SYMBOLS: Once<Vec<Symbol>> = Once::new();
+
+/// The symbol table and its string table, if the bootloader loaded them.
+pub fn sections(
+    boot_info: &BootInformation<'static>,
+) -> Option<(ElfSection<'static>, ElfSection<'static>)> {
+    let tag = boot_info.elf_sections_tag()?;
+    // the sections that are not loaded have no address
+    let symbols = tag.sections().find(|section| {
+        section.section_type() == ElfSectionType::LinkerSymbolTable && section.start_address() != 0
+    })?;
+    let strings = tag.section(symbols.link())?;
+    let loaded = strings.section_type() == ElfSectionType::StringTable
+        && strings.start_address() != 0
+        && symbols.entry_size() == SYMBOL_SIZE as u64;
+    loaded.then_some((symbols, strings))
+}
+
+/// Reads the symbol table, once the heap is initialized, then gives its
+/// frames and the ones of its string table to the frame allocator.
+pub fn init() {
+    let Some((symbols, strings)) = sections(&memory::boot_info()) else {
+        return;
+    };
+    let symbols = map(&symbols);
+    let strings = map(&strings);
+
+    let mut table = Vec::new();
+    for entry in symbols.chunks_exact(SYMBOL_SIZE) {
+        let word = |offset: usize| {
+            u32::from_le_bytes(entry[offset..offset + 4].try_into().unwrap()) as usize
+        };
+        let typ = entry[12] & 0xf;
+        let section = u16::from_le_bytes([entry[14], entry[15]]);
+        if (typ != FUNCTION && typ != NO_TYPE) || section == UNDEFINED_SECTION || word(4) == 0 {
+            continue;
+        }
+        let name = strings
+            .get(word(0)..)
+            .and_then(|name| name.split(|&byte| byte == 0).next())
+            .and_then(|name| core::str::from_utf8(name).ok())
+            .unwrap_or("");
+        if !name.is_empty() {
+            table.push(Symbol {
+                address: word(4),
+                size: word(8),
+                name: demangle(name),
+            });
+        }
+    }
+    table.sort_unstable_by_key(|symbol| symbol.address);
+
+    iounmap(symbols.as_ptr() as VirtualAddress);
+    iounmap(strings.as_ptr() as VirtualAddress);
+    memory::release_reservation("symtab");
+    memory::release_reservation("strtab");
+    SYMBOLS.call_once(|| table);
+}
+
+fn map(section: &ElfSection) -> &'static [u8] {
+    // `ioremap` doesn't take empty ranges
+    let len = (section.size() as usize).max(1);
+    let address = ioremap(section.start_address() as usize, len, CacheMode::WriteBack);
+    unsafe { core::slice::from_raw_parts(address as *const u8, section.size() as usize) }
+}
+
+/// Whether the symbol table was loaded.
+pub fn loaded() -> bool {
+    SYMBOLS.r#try().is_some()
+}
+
+/// The name of the symbol that `address` is in, and the offset of `address`
+/// in it. Doesn't take any lock, so it can be used while panicking.
+pub fn symbolize(address: VirtualAddress) -> Option<(&'static str, usize)> {
+    let symbols = SYMBOLS.r#try()?;
+    let index = symbols
+        .partition_point(|symbol| symbol.address <= address)
+        .checked_sub(1)?;
+    let symbol = &symbols[index];
+    let offset = address - symbol.address;
+    let size = match symbol.size {
+        0 => MAX_LABEL_SIZE,
+        size => size,
+    };
+    (offset < size).then_some((&symbol.name, offset))
+}
+
+/// The symbols named `name`, or whose path ends with `::name`, with their address.
+pub fn lookup(name: &str) -> impl Iterator<Item = (&'static str, VirtualAddress)> + '_ {
+    SYMBOLS
+        .r#try()
+        .into_iter()
+        .flatten()
+        .filter(move |symbol| {
+            symbol
+                .name
+                .strip_suffix(name)
+                .is_some_and(|path| path.is_empty() || path.ends_with("::"))
+        })
+        .map(|symbol| (symbol.name.as_str(), symbol.address))
+}
+
+/// An address followed by the symbol it is in, as in
+/// `0xc0101234 <kfs::kernel_main+0x1f>`.
+pub struct Location(pub VirtualAddress);
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#010x}", self.0)?;
+        if let Some((name, offset)) = symbolize(self.0) {
+            write!(f, " <{}+{:#x}>", name, offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// Demangles a Rust symbol of the legacy mangling scheme, such as
+/// `_ZN3kfs5panic5panic17h0123456789abcdefE`, or of the v0 one, such as
+/// `_RNvNtCs1234_3kfs5panic5panic`, into `kfs::panic::panic`.
+/// The other names, such as the ones of `asm/`, are left as they are.
+pub fn demangle(name: &str) -> String {
+    demangle_legacy(name)
+        .or_else(|| demangle_v0(name))
+        .unwrap_or_else(|| String::from(name))
+}
+
+fn demangle_legacy(name: &str) -> Option<String> {
+    let mut rest = name.strip_prefix("_ZN")?;
+    let mut path = String::new();
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = rest[..digits].parse().ok()?;
+        let end = digits.checked_add(len)?;
+        let component = rest.get(digits..end)?;
+        rest = &rest[end..];
+        if rest == "E" && is_hash(component) {
+            break;
+        }
+        if !path.is_empty() {
+            path.push_str("::");
+        }
+        unescape(component, &mut path)?;
+    }
+    (rest == "E").then_some(path)
+}
+
+/// The last component of a legacy symbol, `h` and 16 hexadecimal digits.
+fn is_hash(component: &str) -> bool {
+    component.len() == 17
+        && component.starts_with('h')
+        && component[1..].bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn unescape(component: &str, path: &mut String) -> Option<()> {
+    // the underscore protects a leading `$`
+    let mut rest = if component.starts_with("_$") {
+        &component[1..]
+    } else {
+        component
+    };
+    while let Some(c) = rest.chars().next() {
+        if c == '$' {
+            let end = rest[1..].find('$')? + 1;
+            path.push(match &rest[1..end] {
+                "SP" => '@',
+                "BP" => '*',
+                "RF" => '&',
+                "LT" => '<',
+                "GT" => '>',
+                "LP" => '(',
+                "RP" => ')',
+                "C" => ',',
+                escape => {
+                    let code = u32::from_str_radix(escape.strip_prefix('u')?, 16).ok()?;
+                    char::from_u32(code)?
+                }
+            });
+            rest = &rest[end + 1..];
+        } else if let Some(after) = rest.strip_prefix("..") {
+            path.push_str("::");
+            rest = after;
+        } else {
+            path.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Some(())
+}
+
+/// Deepest nesting of paths and types in a v0 symbol: the kernel stack is
+/// small, and the backreferences of a malformed symbol can loop.
+const MAX_V0_DEPTH: usize = 64;
+
+/// Longest demangled v0 symbol, as backreferences to backreferences can make
+/// a short symbol expand exponentially.
+const MAX_V0_LENGTH: usize = 4096;
+
+/// Demangles a symbol of the v0 scheme, which recent compilers use, without
+/// the crate disambiguators, the types of constants and the instantiating
+/// crate. Punycode identifiers are not supported.
+fn demangle_v0(name: &str) -> Option<String> {
+    // LLVM may add a suffix such as `.llvm.1234`
+    let symbol = name.strip_prefix("_R")?.split('.').next()?;
+    let mut parser = V0Parser {
+        symbol: symbol.as_bytes(),
+        position: 0,
+        depth: 0,
+        bound_lifetimes: 0,
+        out: String::new(),
+    };
+    parser.path(true)?;
+    let len = parser.out.len();
+    if parser.position < symbol.len() {
+        parser.path(false)?;
+    }
+    parser.out.truncate(len);
+    (parser.position == symbol.len()).then_some(parser.out)
+}
+
+struct V0Parser<'a> {
+    /// The symbol after `_R`, which the backreferences are offsets in.
+    symbol: &'a [u8],
+    position: usize,
+    depth: usize,
+    /// Number of lifetimes bound by the `for<...>` the parser is in.
+    bound_lifetimes: usize,
+    out: String,
+}
+
+impl<'a> V0Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.symbol.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let eaten = self.peek() == Some(byte);
+        if eaten {
+            self.position += 1;
+        }
+        eaten
+    }
+
+    fn enter(&mut self) -> Option<()> {
+        self.depth += 1;
+        (self.depth <= MAX_V0_DEPTH && self.out.len() <= MAX_V0_LENGTH).then_some(())
+    }
+
+    fn leave(&mut self) -> Option<()> {
+        self.depth -= 1;
+        Some(())
+    }
+
+    fn decimal(&mut self) -> Option<usize> {
+        let rest = &self.symbol[self.position..];
+        // a number never starts with 0, so a 0 is followed by something else
+        let digits = match rest.first()? {
+            b'0' => 1,
+            b'1'..=b'9' => rest.iter().take_while(|byte| byte.is_ascii_digit()).count(),
+            _ => return None,
+        };
+        self.position += digits;
+        core::str::from_utf8(&rest[..digits]).ok()?.parse().ok()
+    }
+
+    /// `_` for 0, or base-62 digits and `_` for the number they make plus one.
+    fn base62(&mut self) -> Option<usize> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+        let mut value: usize = 0;
+        loop {
+            let digit = match self.next()? {
+                byte @ b'0'..=b'9' => byte - b'0',
+                byte @ b'a'..=b'z' => byte - b'a' + 10,
+                byte @ b'A'..=b'Z' => byte - b'A' + 36,
+                b'_' => return value.checked_add(1),
+                _ => return None,
+            };
+            value = value.checked_mul(62)?.checked_add(digit as usize)?;
+        }
+    }
+
+    /// `s` and a base-62 number, or nothing for 0.
+    fn disambiguator(&mut self) -> Option<usize> {
+        if self.eat(b's') {
+            self.base62()?.checked_add(1)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// An identifier, with its disambiguator.
+    fn identifier(&mut self) -> Option<(usize, &'a str)> {
+        let disambiguator = self.disambiguator()?;
+        Some((disambiguator, self.undisambiguated_identifier()?))
+    }
+
+    fn undisambiguated_identifier(&mut self) -> Option<&'a str> {
+        if self.eat(b'u') {
+            return None;
+        }
+        let len = self.decimal()?;
+        // separates the length from an identifier that starts with a digit or `_`
+        self.eat(b'_');
+        let end = self.position.checked_add(len)?;
+        let name = core::str::from_utf8(self.symbol.get(self.position..end)?).ok()?;
+        self.position = end;
+        Some(name)
+    }
+
+    /// Parses what a backreference points to with `parse`, then goes on
+    /// after the backreference. The `B` is already parsed.
+    fn backref<R>(&mut self, parse: impl FnOnce(&mut Self) -> Option<R>) -> Option<R> {
+        let start = self.position - 1;
+        let target = self.base62()?;
+        if target >= start {
+            return None;
+        }
+        let resume = self.position;
+        self.position = target;
+        let result = parse(self)?;
+        self.position = resume;
+        Some(result)
+    }
+
+    /// Parses a path without printing it.
+    fn skip_path(&mut self) -> Option<()> {
+        let len = self.out.len();
+        self.path(false)?;
+        self.out.truncate(len);
+        Some(())
+    }
+
+    /// A path, whose generic arguments are introduced by `::` in value
+    /// paths, as in `size_of::<T>`, unlike in types.
+    fn path(&mut self, in_value: bool) -> Option<()> {
+        self.enter()?;
+        match self.next()? {
+            b'C' => {
+                let (_, name) = self.identifier()?;
+                self.out.push_str(name);
+            }
+            b'N' => {
+                let namespace = self.next()?;
+                self.path(in_value)?;
+                let (disambiguator, name) = self.identifier()?;
+                match namespace {
+                    // the internal namespaces, only shown when named
+                    b'a'..=b'z' if name.is_empty() => {}
+                    b'a'..=b'z' => write!(self.out, "::{}", name).ok()?,
+                    b'A'..=b'Z' => {
+                        self.out.push_str("::{");
+                        match namespace {
+                            b'C' => self.out.push_str("closure"),
+                            b'S' => self.out.push_str("shim"),
+                            other => self.out.push(other as char),
+                        }
+                        if !name.is_empty() {
+                            write!(self.out, ":{}", name).ok()?;
+                        }
+                        write!(self.out, "#{}}}", disambiguator).ok()?;
+                    }
+                    _ => return None,
+                }
+            }
+            // inherent impl, whose own path is not shown
+            b'M' => {
+                self.disambiguator()?;
+                self.skip_path()?;
+                self.out.push('<');
+                self.typ()?;
+                self.out.push('>');
+            }
+            // trait impl, with or without its own path
+            tag @ (b'X' | b'Y') => {
+                if tag == b'X' {
+                    self.disambiguator()?;
+                    self.skip_path()?;
+                }
+                self.out.push('<');
+                self.typ()?;
+                self.out.push_str(" as ");
+                self.path(false)?;
+                self.out.push('>');
+            }
+            b'I' => {
+                self.path(in_value)?;
+                if in_value {
+                    self.out.push_str("::");
+                }
+                self.out.push('<');
+                self.generic_args()?;
+                self.out.push('>');
+            }
+            b'B' => self.backref(|parser| parser.path(in_value))?,
+            _ => return None,
+        }
+        self.leave()
+    }
+
+    /// The generic arguments up to `E`, without the brackets around them.
+    fn generic_args(&mut self) -> Option<()> {
+        let mut first = true;
+        while !self.eat(b'E') {
+            if !first {
+                self.out.push_str(", ");
+            }
+            first = false;
+            if self.eat(b'L') {
+                let lifetime = self.base62()?;
+                self.lifetime(lifetime)?;
+            } else if self.eat(b'K') {
+                self.constant()?;
+            } else {
+                self.typ()?;
+            }
+        }
+        Some(())
+    }
+
+    /// A lifetime by its index among the bound ones, innermost first, or `'_`
+    /// for 0, the lifetimes that are erased.
+    fn lifetime(&mut self, index: usize) -> Option<()> {
+        if index == 0 {
+            self.out.push_str("'_");
+            return Some(());
+        }
+        let depth = self.bound_lifetimes.checked_sub(index)?;
+        match u8::try_from(depth).ok().filter(|&depth| depth < 26) {
+            Some(depth) => write!(self.out, "'{}", (b'a' + depth) as char).ok(),
+            None => write!(self.out, "'_{}", depth).ok(),
+        }
+    }
+
+    /// The lifetimes bound by `for<...>`, if there are some, which stay bound
+    /// until `bound_lifetimes` is restored.
+    fn binder(&mut self) -> Option<()> {
+        if !self.eat(b'G') {
+            return Some(());
+        }
+        let count = self.base62()?.checked_add(1)?;
+        self.out.push_str("for<");
+        for i in 0..count {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            if self.out.len() > MAX_V0_LENGTH {
+                return None;
+            }
+            self.bound_lifetimes += 1;
+            self.lifetime(1)?;
+        }
+        self.out.push_str("> ");
+        Some(())
+    }
+
+    fn typ(&mut self) -> Option<()> {
+        self.enter()?;
+        let tag = self.peek()?;
+        if let Some(name) = basic_type(tag) {
+            self.position += 1;
+            self.out.push_str(name);
+            return self.leave();
+        }
+        match tag {
+            b'R' | b'Q' => {
+                self.position += 1;
+                self.out.push('&');
+                if self.eat(b'L') {
+                    let lifetime = self.base62()?;
+                    if lifetime != 0 {
+                        self.lifetime(lifetime)?;
+                        self.out.push(' ');
+                    }
+                }
+                if tag == b'Q' {
+                    self.out.push_str("mut ");
+                }
+                self.typ()?;
+            }
+            b'P' | b'O' => {
+                self.position += 1;
+                self.out
+                    .push_str(if tag == b'P' { "*const " } else { "*mut " });
+                self.typ()?;
+            }
+            b'A' | b'S' => {
+                self.position += 1;
+                self.out.push('[');
+                self.typ()?;
+                if tag == b'A' {
+                    self.out.push_str("; ");
+                    self.constant()?;
+                }
+                self.out.push(']');
+            }
+            b'T' => {
+                self.position += 1;
+                self.out.push('(');
+                let mut count = 0;
+                while !self.eat(b'E') {
+                    if count > 0 {
+                        self.out.push_str(", ");
+                    }
+                    count += 1;
+                    self.typ()?;
+                }
+                if count == 1 {
+                    self.out.push(',');
+                }
+                self.out.push(')');
+            }
+            b'F' => {
+                self.position += 1;
+                let bound_lifetimes = self.bound_lifetimes;
+                self.binder()?;
+                self.fn_sig()?;
+                self.bound_lifetimes = bound_lifetimes;
+            }
+            b'D' => {
+                self.position += 1;
+                let bound_lifetimes = self.bound_lifetimes;
+                self.out.push_str("dyn ");
+                self.binder()?;
+                let mut first = true;
+                while !self.eat(b'E') {
+                    if !first {
+                        self.out.push_str(" + ");
+                    }
+                    first = false;
+                    self.dyn_trait()?;
+                }
+                self.bound_lifetimes = bound_lifetimes;
+                if !self.eat(b'L') {
+                    return None;
+                }
+                let lifetime = self.base62()?;
+                if lifetime != 0 {
+                    self.out.push_str(" + ");
+                    self.lifetime(lifetime)?;
+                }
+            }
+            b'B' => {
+                self.position += 1;
+                self.backref(Self::typ)?;
+            }
+            _ => self.path(false)?,
+        }
+        self.leave()
+    }
+
+    /// The signature of a function pointer type, after its binder.
+    fn fn_sig(&mut self) -> Option<()> {
+        if self.eat(b'U') {
+            self.out.push_str("unsafe ");
+        }
+        if self.eat(b'K') {
+            self.out.push_str("extern \"");
+            if self.eat(b'C') {
+                self.out.push('C');
+            } else {
+                for c in self.undisambiguated_identifier()?.chars() {
+                    self.out.push(if c == '_' { '-' } else { c });
+                }
+            }
+            self.out.push_str("\" ");
+        }
+        self.out.push_str("fn(");
+        let mut first = true;
+        while !self.eat(b'E') {
+            if !first {
+                self.out.push_str(", ");
+            }
+            first = false;
+            self.typ()?;
+        }
+        self.out.push(')');
+        if !self.eat(b'u') {
+            self.out.push_str(" -> ");
+            self.typ()?;
+        }
+        Some(())
+    }
+
+    /// A trait of a `dyn` type, with its associated types, as in
+    /// `Fn<(), Output = ()>`.
+    fn dyn_trait(&mut self) -> Option<()> {
+        let mut open = self.path_with_open_generics()?;
+        while self.eat(b'p') {
+            self.out.push_str(if open { ", " } else { "<" });
+            open = true;
+            let name = self.undisambiguated_identifier()?;
+            write!(self.out, "{} = ", name).ok()?;
+            self.typ()?;
+        }
+        if open {
+            self.out.push('>');
+        }
+        Some(())
+    }
+
+    /// A type path whose generic arguments, if it has some, are left open
+    /// for the associated types. Returns whether they are.
+    fn path_with_open_generics(&mut self) -> Option<bool> {
+        if self.eat(b'B') {
+            return self.backref(Self::path_with_open_generics);
+        }
+        if self.eat(b'I') {
+            self.enter()?;
+            self.path(false)?;
+            self.out.push('<');
+            self.generic_args()?;
+            self.leave()?;
+            return Some(true);
+        }
+        self.path(false)?;
+        Some(false)
+    }
+
+    /// An integer, `bool` or `char` constant, given with its type.
+    fn constant(&mut self) -> Option<()> {
+        if self.eat(b'p') {
+            self.out.push('_');
+            return Some(());
+        }
+        if self.eat(b'B') {
+            return self.backref(Self::constant);
+        }
+        let typ = self.next()?;
+        basic_type(typ)?;
+        let negative = self.eat(b'n');
+        let rest = &self.symbol[self.position..];
+        let digits = rest
+            .iter()
+            .take_while(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+            .count();
+        let value = match digits {
+            0 => 0,
+            _ => u64::from_str_radix(core::str::from_utf8(&rest[..digits]).ok()?, 16).ok()?,
+        };
+        self.position += digits;
+        if !self.eat(b'_') {
+            return None;
+        }
+        match typ {
+            b'b' => write!(self.out, "{}", value != 0).ok(),
+            b'c' => write!(self.out, "{:?}", char::from_u32(value.try_into().ok()?)?).ok(),
+            _ if negative => write!(self.out, "-{}", value).ok(),
+            _ => write!(self.out, "{}", value).ok(),
+        }
+    }
+}
+
+fn basic_type(tag: u8) -> Option<&'static str> {
+    Some(match tag {
+        b'a' => "i8",
+        b'b' => "bool",
+        b'c' => "char",
+        b'd' => "f64",
+        b'e' => "str",
+        b'f' => "f32",
+        b'h' => "u8",
+        b'i' => "isize",
+        b'j' => "usize",
+        b'l' => "i32",
+        b'm' => "u32",
+        b'n' => "i128",
+        b'o' => "u128",
+        b'p' => "_",
+        b's' => "i16",
+        b't' => "u16",
+        b'u' => "()",
+        b'v' => "...",
+        b'x' => "i64",
+        b'y' => "u64",
+        b'z' => "!",
+        _ => return None,
+    })
+}
@@ -0,0 +1,43 @@
+use crate::memory::paging::{EntryFlags, Page};
+use crate::memory::{self, PAGE_SIZE};
+
+const USER_ADDRESS: usize = 0x4000_0000;
+
+pub fn user_space_isolation() {
+    let page = Page::containing_address(USER_ADDRESS);
+    let ptr = USER_ADDRESS as *mut u32;
+    let other = memory::new_address_space();
+    memory::with_address_space(&other, |table, frame_allocator| {
+        table.map(page, EntryFlags::WRITABLE, frame_allocator)
+    });
+    assert!(memory::with_memory(|table, _| table.translate_page(page)).is_none());
+
+    let old = memory::switch_address_space(other);
+    unsafe { *ptr = 42 };
+    let other = memory::switch_address_space(old);
+    assert!(memory::with_memory(|table, _| table.translate_page(page)).is_none());
+
+    let old = memory::switch_address_space(other);
+    assert_eq!(unsafe { *ptr }, 42);
+    let other = memory::switch_address_space(old);
+
+    memory::free_address_space(other);
+}
+
+pub fn free_releases_frames() {
+    // the first address space creates the kernel page tables for good
+    memory::free_address_space(memory::new_address_space());
+    let free_frames = memory::free_frames();
+
+    let other = memory::new_address_space();
+    memory::with_address_space(&other, |table, frame_allocator| {
+        for i in 0..3 {
+            let page = Page::containing_address(USER_ADDRESS + i * PAGE_SIZE);
+            table.map(page, EntryFlags::WRITABLE, frame_allocator);
+        }
+    });
+    assert!(memory::free_frames() < free_frames);
+
+    memory::free_address_space(other);
+    assert_eq!(memory::free_frames(), free_frames);
+}
@@ -0,0 +1,67 @@
+use crate::cmdline::{KernelParams, Keymap, Verbosity, Warning};
+
+pub fn options() {
+    let params =
+        KernelParams::parse("pae keymap=azerty screens=2 loglevel=debug serial pit_hz=1000");
+    assert!(params.pae && params.serial);
+    assert_eq!(params.keymap, Keymap::Azerty);
+    assert_eq!(params.screens, 2);
+    assert_eq!(params.verbosity, Verbosity::Debug);
+    assert_eq!(params.pit_hz, 1000);
+    assert_eq!(params.warnings().count(), 0);
+
+    let params = KernelParams::parse("  quiet   ");
+    assert_eq!(params.verbosity, Verbosity::Quiet);
+    assert!(!params.pae);
+    assert_eq!(params.screens, KernelParams::DEFAULT.screens);
+}
+
+pub fn warnings() {
+    let params =
+        KernelParams::parse("foo pae=1 keymap screens=0 screens=9 pit_hz=18 keymap=dvorak");
+    let warnings: [_; 7] = core::array::from_fn(|i| params.warnings().nth(i).copied());
+    assert!(matches!(warnings[0], Some(Warning::UnknownOption("foo"))));
+    assert!(matches!(warnings[1], Some(Warning::UnexpectedValue("pae"))));
+    assert!(matches!(warnings[2], Some(Warning::MissingValue("keymap"))));
+    assert!(matches!(
+        warnings[3],
+        Some(Warning::InvalidValue {
+            option: "screens",
+            value: "0"
+        })
+    ));
+    assert!(matches!(
+        warnings[4],
+        Some(Warning::InvalidValue { value: "9", .. })
+    ));
+    assert!(matches!(
+        warnings[5],
+        Some(Warning::InvalidValue {
+            option: "pit_hz",
+            ..
+        })
+    ));
+    assert!(matches!(
+        warnings[6],
+        Some(Warning::InvalidValue {
+            value: "dvorak",
+            ..
+        })
+    ));
+
+    // the options with warnings keep their defaults
+    assert!(!params.pae);
+    assert_eq!(params.keymap, Keymap::Us104);
+    assert_eq!(params.screens, KernelParams::DEFAULT.screens);
+    assert_eq!(params.pit_hz, KernelParams::DEFAULT.pit_hz);
+
+    // a valid divisor, but far too many interrupts
+    let params = KernelParams::parse("pit_hz=1000000");
+    assert!(matches!(
+        params.warnings().next(),
+        Some(Warning::InvalidValue {
+            option: "pit_hz",
+            ..
+        })
+    ));
+}
@@ -0,0 +1,106 @@
+use crate::memory::paging::{EntryFlags, Page};
+use crate::memory::vmalloc::{vfree, vmalloc};
+use crate::memory::{self, PAGE_SIZE};
+
+fn share(source: *mut u8, destination: *mut u8) {
+    memory::with_memory(|active_table, frame_allocator| {
+        active_table.share_copy_on_write(
+            Page::containing_address(source as usize),
+            Page::containing_address(destination as usize),
+            frame_allocator,
+        )
+    })
+}
+
+fn frame_address(ptr: *mut u8) -> usize {
+    memory::with_memory(|active_table, _| active_table.translate(ptr as usize).unwrap())
+}
+
+fn references(ptr: *mut u8) -> usize {
+    memory::with_memory(|active_table, frame_allocator| {
+        let frame = active_table
+            .translate_page(Page::containing_address(ptr as usize))
+            .unwrap();
+        frame_allocator.references(&frame)
+    })
+}
+
+pub fn write_isolation() {
+    let area = vmalloc(2 * PAGE_SIZE);
+    assert!(!area.is_null());
+    let (first, second) = (area, unsafe { area.add(PAGE_SIZE) });
+
+    unsafe { *first = 42 };
+    share(first, second);
+    assert_eq!(frame_address(first), frame_address(second));
+    assert_eq!(references(first), 2);
+    assert_eq!(unsafe { *second }, 42);
+
+    // the write copies the frame
+    unsafe { *second = 7 };
+    assert_ne!(frame_address(first), frame_address(second));
+    assert_eq!(unsafe { (*first, *second) }, (42, 7));
+    assert_eq!(references(first), 1);
+
+    // the last owner just gets its page back writable
+    let frame = frame_address(first);
+    unsafe { *first = 1 };
+    assert_eq!(frame_address(first), frame);
+    assert_eq!(unsafe { (*first, *second) }, (1, 7));
+
+    unsafe { vfree(area) };
+}
+
+pub fn last_owner_frees() {
+    let area = vmalloc(2 * PAGE_SIZE);
+    assert!(!area.is_null());
+    let (first, second) = (area, unsafe { area.add(PAGE_SIZE) });
+    // touch both pages, so that their page tables exist
+    unsafe { (*first, *second) = (42, 0) };
+    let free_frames = memory::free_frames();
+
+    memory::release_range(second as usize, second as usize + PAGE_SIZE);
+    share(first, second);
+    assert_eq!(memory::free_frames(), free_frames + 1);
+
+    unsafe { vfree(area) };
+    assert_eq!(memory::free_frames(), free_frames + 2);
+}
+
+pub fn address_spaces() {
+    const USER_ADDRESS: usize = 0x4000_0000;
+    let page = Page::containing_address(USER_ADDRESS);
+    let ptr = USER_ADDRESS as *mut u8;
+    memory::with_memory(|active_table, frame_allocator| {
+        active_table.map(page, EntryFlags::WRITABLE, frame_allocator)
+    });
+    unsafe { *ptr = 42 };
+
+    // what fork does to every page
+    let other = memory::new_address_space();
+    let (frame, flags) =
+        memory::with_memory(|active_table, _| active_table.mark_copy_on_write(page));
+    memory::with_address_space(&other, |table, frame_allocator| {
+        frame_allocator.share_frame(&frame);
+        table.map_to(page, frame, flags, frame_allocator);
+    });
+    let shared = frame_address(ptr);
+    assert_eq!(references(ptr), 2);
+
+    // the write copies the frame in the other address space only
+    let old = memory::switch_address_space(other);
+    assert_eq!(frame_address(ptr), shared);
+    assert_eq!(unsafe { *ptr }, 42);
+    unsafe { *ptr = 7 };
+    assert_ne!(frame_address(ptr), shared);
+    assert_eq!(unsafe { *ptr }, 7);
+    let other = memory::switch_address_space(old);
+
+    assert_eq!(unsafe { *ptr }, 42);
+    assert_eq!(references(ptr), 1);
+    unsafe { *ptr = 1 };
+    assert_eq!(frame_address(ptr), shared);
+
+    memory::free_address_space(other);
+    memory::with_memory(|active_table, frame_allocator| active_table.unmap(page, frame_allocator));
+}
@@ -0,0 +1,32 @@
+use crate::interrupts::catch_double_fault;
+use crate::memory::{self, PAGE_SIZE};
+use core::hint::black_box;
+
+#[allow(unconditional_recursion)]
+fn recurse(depth: usize) -> usize {
+    let frame = black_box([depth as u8; 64]);
+    recurse(depth + 1) + frame[0] as usize
+}
+
+extern "C" fn overflow() {
+    black_box(recurse(0));
+}
+
+extern "C" fn nothing() {}
+
+pub fn stack_overflow() {
+    let guard = memory::stack_guard_page();
+    let fault = catch_double_fault(overflow).expect("the overflow didn't double fault");
+    assert!(fault.stack_overflow);
+    // a faulting `push` or `call` leaves esp at the bottom of the stack
+    assert!((guard..=guard + PAGE_SIZE).contains(&(fault.esp as usize)));
+    assert!(fault.eip as usize >= memory::KERNEL_OFFSET);
+
+    // the double fault task starts again from scratch
+    let fault = catch_double_fault(overflow).expect("the overflow didn't double fault");
+    assert!(fault.stack_overflow);
+}
+
+pub fn no_fault() {
+    assert!(catch_double_fault(nothing).is_none());
+}
@@ -0,0 +1,23 @@
+use crate::memory::frame::{Frame, FrameAllocator};
+use crate::memory::{self, PAGE_SIZE};
+
+pub fn double_free() {
+    memory::with_memory(|_, frame_allocator| {
+        let first = frame_allocator.allocate_frames(1).expect("out of memory");
+        let address = first.start_address();
+        let second = Frame::containing_address(address + PAGE_SIZE);
+        assert!(!frame_allocator.is_any_free(&first, 1));
+
+        // with its second frame freed, freeing the block would free it twice
+        frame_allocator.deallocate_frame(second);
+        assert!(frame_allocator.is_any_free(&first, 1));
+        assert!(!frame_allocator.is_any_free(&first, 0));
+
+        // once merged with its buddy, the frame is in a free block of order 1
+        // or more, so freeing it alone again would free it twice
+        frame_allocator.deallocate_frame(first);
+        let second = Frame::containing_address(address + PAGE_SIZE);
+        assert!(frame_allocator.is_any_free(&second, 0));
+        assert!(frame_allocator.is_any_free(&Frame::containing_address(address), 1));
+    });
+}
@@ -0,0 +1,86 @@
+use crate::memory::heap::{self, kfree, kmalloc, ksize};
+use crate::memory::PAGE_SIZE;
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+use core::alloc::Layout;
+
+pub fn kmalloc_ksize() {
+    let ptr = kmalloc(42);
+    assert!(!ptr.is_null());
+    assert!(unsafe { ksize(ptr) } >= 42);
+    unsafe { kfree(ptr) };
+}
+
+pub fn kfree_null() {
+    let free = heap::stats().free;
+    unsafe { kfree(core::ptr::null_mut()) };
+    assert_eq!(heap::stats().free, free);
+}
+
+pub fn reuse() {
+    let first = kmalloc(64);
+    unsafe { kfree(first) };
+    let second = kmalloc(64);
+    assert_eq!(first, second);
+    unsafe { kfree(second) };
+}
+
+pub fn fragmentation() {
+    // bigger than any existing hole, so the blocks are carved one after
+    // the other at the end of the heap, and too big for the slab caches
+    let size = (heap::stats().largest_free_block + 1).max(PAGE_SIZE);
+    let blocks: [*mut u8; 3] = core::array::from_fn(|_| kmalloc(size));
+    assert!(blocks.iter().all(|ptr| !ptr.is_null()));
+
+    unsafe {
+        kfree(blocks[0]);
+        kfree(blocks[2]);
+    }
+    // the first hole is bounded by the middle block, so it is too small
+    let big = kmalloc(2 * size);
+    assert_ne!(big, blocks[0]);
+    unsafe { kfree(big) };
+
+    // freeing the middle block merges all three blocks
+    unsafe { kfree(blocks[1]) };
+    let big = kmalloc(3 * size);
+    assert_eq!(big, blocks[0]);
+    unsafe { kfree(big) };
+}
+
+pub fn kbrk() {
+    let brk = heap::kbrk(0).unwrap();
+    assert_eq!(heap::kbrk(PAGE_SIZE as isize), Some(brk));
+    assert_eq!(heap::kbrk(0), Some(brk + PAGE_SIZE));
+    assert_eq!(heap::kbrk(-(PAGE_SIZE as isize)), Some(brk + PAGE_SIZE));
+    assert_eq!(heap::kbrk(0), Some(brk));
+}
+
+pub fn collections() {
+    let boxed = Box::new(42);
+    assert_eq!(*boxed, 42);
+
+    let vec: Vec<usize> = (0..1000).collect();
+    assert_eq!(vec.iter().sum::<usize>(), 999 * 1000 / 2);
+
+    let mut string = String::new();
+    for _ in 0..100 {
+        string.push_str("kfs");
+    }
+    assert_eq!(string.len(), 300);
+
+    let mut map = BTreeMap::new();
+    for i in 0..100 {
+        map.insert(i, i * i);
+    }
+    assert_eq!(map[&42], 42 * 42);
+}
+
+pub fn alignment() {
+    for align in [16, 64, PAGE_SIZE] {
+        let layout = Layout::from_size_align(100, align).unwrap();
+        let ptr = unsafe { alloc::alloc::alloc(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % align, 0);
+        unsafe { alloc::alloc::dealloc(ptr, layout) };
+    }
+}
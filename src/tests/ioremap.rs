@@ -0,0 +1,38 @@
+use crate::memory::paging::{ioremap, iounmap, CacheMode, IOREMAP_START};
+use crate::memory::{self, PAGE_SIZE};
+use crate::vga_buffer::VGA_ADDRESS;
+use core::ptr::{read_volatile, write_volatile};
+
+pub fn aliases() {
+    // the VGA buffer is already mapped once by the writer
+    let first = ioremap(VGA_ADDRESS + 2, 2, CacheMode::Uncached);
+    let second = ioremap(VGA_ADDRESS, PAGE_SIZE, CacheMode::WriteThrough);
+    assert!(first >= IOREMAP_START && second >= IOREMAP_START);
+    assert_eq!(first % PAGE_SIZE, 2);
+    assert_ne!(first - 2, second);
+    assert_eq!(
+        memory::with_memory(|active_table, _| active_table.translate(first)),
+        Some(VGA_ADDRESS + 2)
+    );
+
+    unsafe {
+        let old = read_volatile((second + 2) as *const u16);
+        write_volatile(first as *mut u16, 0x0f21);
+        assert_eq!(read_volatile((second + 2) as *const u16), 0x0f21);
+        write_volatile(first as *mut u16, old);
+    }
+
+    iounmap(first);
+    iounmap(second);
+}
+
+pub fn reuse() {
+    let address = ioremap(VGA_ADDRESS, PAGE_SIZE, CacheMode::Uncached);
+    iounmap(address);
+    assert!(memory::with_memory(|active_table, _| active_table.translate(address)).is_none());
+    assert_eq!(
+        ioremap(VGA_ADDRESS, PAGE_SIZE, CacheMode::Uncached),
+        address
+    );
+    iounmap(address);
+}
@@ -0,0 +1,40 @@
+use crate::memory::vmalloc::{vfree, vmalloc};
+use crate::memory::{self, heap, lazy, PAGE_SIZE};
+
+fn is_mapped(address: usize) -> bool {
+    memory::with_memory(|active_table, _| active_table.translate(address).is_some())
+}
+
+pub fn vmalloc_on_touch() {
+    let ptr = vmalloc(2 * PAGE_SIZE);
+    assert!(!ptr.is_null());
+    assert_eq!(
+        lazy::find(ptr as usize).map(|region| region.name),
+        Some("vmalloc")
+    );
+    assert!(!is_mapped(ptr as usize));
+
+    let second_page = unsafe { ptr.add(PAGE_SIZE) };
+    assert_eq!(unsafe { *second_page }, 0);
+    assert!(is_mapped(second_page as usize));
+    assert!(!is_mapped(ptr as usize));
+
+    unsafe { vfree(ptr) };
+    assert!(lazy::find(ptr as usize).is_none());
+    assert!(!is_mapped(second_page as usize));
+}
+
+pub fn heap_on_touch() {
+    let brk = heap::kbrk(2 * PAGE_SIZE as isize).unwrap();
+    let last_page = brk + PAGE_SIZE;
+    assert!(!is_mapped(last_page));
+
+    unsafe { (last_page as *mut u32).write(42) };
+    assert!(is_mapped(last_page));
+
+    assert_eq!(
+        heap::kbrk(-2 * PAGE_SIZE as isize),
+        Some(brk + 2 * PAGE_SIZE)
+    );
+    assert!(!is_mapped(last_page));
+}
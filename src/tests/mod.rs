@@ -0,0 +1,173 @@
+//! In-kernel tests, run from the shell with the `test` command.
+//! A failing test panics, which stops the kernel on the failed assertion.
+
+mod address_space;
+mod cmdline;
+mod cow;
+mod double_fault;
+mod frame;
+mod heap;
+mod ioremap;
+mod lazy;
+mod signal;
+mod slab;
+mod symbols;
+mod vmalloc;
+
+use crate::vga_buffer::{Color, WRITER};
+use crate::{print, println};
+
+struct Test {
+    name: &'static str,
+    function: fn(),
+}
+
+const TESTS: &[Test] = &[
+    Test {
+        name: "address_space::user_space_isolation",
+        function: address_space::user_space_isolation,
+    },
+    Test {
+        name: "address_space::free_releases_frames",
+        function: address_space::free_releases_frames,
+    },
+    Test {
+        name: "cmdline::options",
+        function: cmdline::options,
+    },
+    Test {
+        name: "cmdline::warnings",
+        function: cmdline::warnings,
+    },
+    Test {
+        name: "cow::write_isolation",
+        function: cow::write_isolation,
+    },
+    Test {
+        name: "cow::last_owner_frees",
+        function: cow::last_owner_frees,
+    },
+    Test {
+        name: "cow::address_spaces",
+        function: cow::address_spaces,
+    },
+    Test {
+        name: "double_fault::stack_overflow",
+        function: double_fault::stack_overflow,
+    },
+    Test {
+        name: "double_fault::no_fault",
+        function: double_fault::no_fault,
+    },
+    Test {
+        name: "frame::double_free",
+        function: frame::double_free,
+    },
+    Test {
+        name: "heap::kmalloc_ksize",
+        function: heap::kmalloc_ksize,
+    },
+    Test {
+        name: "heap::kfree_null",
+        function: heap::kfree_null,
+    },
+    Test {
+        name: "heap::reuse",
+        function: heap::reuse,
+    },
+    Test {
+        name: "heap::fragmentation",
+        function: heap::fragmentation,
+    },
+    Test {
+        name: "heap::kbrk",
+        function: heap::kbrk,
+    },
+    Test {
+        name: "heap::collections",
+        function: heap::collections,
+    },
+    Test {
+        name: "heap::alignment",
+        function: heap::alignment,
+    },
+    Test {
+        name: "ioremap::aliases",
+        function: ioremap::aliases,
+    },
+    Test {
+        name: "ioremap::reuse",
+        function: ioremap::reuse,
+    },
+    Test {
+        name: "lazy::vmalloc_on_touch",
+        function: lazy::vmalloc_on_touch,
+    },
+    Test {
+        name: "lazy::heap_on_touch",
+        function: lazy::heap_on_touch,
+    },
+    Test {
+        name: "signal::immediate",
+        function: signal::immediate,
+    },
+    Test {
+        name: "signal::deferred",
+        function: signal::deferred,
+    },
+    Test {
+        name: "signal::scheduled",
+        function: signal::scheduled,
+    },
+    Test {
+        name: "slab::named_cache",
+        function: slab::named_cache,
+    },
+    Test {
+        name: "slab::many_slabs",
+        function: slab::many_slabs,
+    },
+    Test {
+        name: "slab::kmalloc_size_classes",
+        function: slab::kmalloc_size_classes,
+    },
+    Test {
+        name: "symbols::demangling",
+        function: symbols::demangling,
+    },
+    Test {
+        name: "symbols::demangling_v0",
+        function: symbols::demangling_v0,
+    },
+    Test {
+        name: "symbols::symbolize",
+        function: symbols::symbolize,
+    },
+    Test {
+        name: "vmalloc::vsize_rounding",
+        function: vmalloc::vsize_rounding,
+    },
+    Test {
+        name: "vmalloc::big_buffer",
+        function: vmalloc::big_buffer,
+    },
+    Test {
+        name: "vmalloc::reuse",
+        function: vmalloc::reuse,
+    },
+    Test {
+        name: "vmalloc::vbrk_grow_shrink",
+        function: vmalloc::vbrk_grow_shrink,
+    },
+];
+
+pub fn run_all() {
+    for test in TESTS {
+        print!("{}... ", test.name);
+        (test.function)();
+        WRITER.lock().set_foreground_color(Color::LightGreen);
+        println!("[ok]");
+        WRITER.lock().reset_foreground_color();
+    }
+    println!("{} tests passed", TESTS.len());
+}
@@ -0,0 +1,89 @@
+use crate::interrupts;
+use crate::signal::{self, Signal, SIGNAL_COUNT};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+const TEST_SIGNAL: Signal = SIGNAL_COUNT as Signal - 1;
+
+static DELIVERED: AtomicUsize = AtomicUsize::new(0);
+
+fn count(signal: Signal) {
+    assert_eq!(signal, TEST_SIGNAL);
+    DELIVERED.fetch_add(1, Ordering::Relaxed);
+}
+
+fn delivered() -> usize {
+    DELIVERED.load(Ordering::Relaxed)
+}
+
+pub fn immediate() {
+    DELIVERED.store(0, Ordering::Relaxed);
+    let first = signal::register(TEST_SIGNAL, "test", count).expect("too many callbacks");
+    let second = signal::register(TEST_SIGNAL, "test", count).expect("too many callbacks");
+    signal::raise(TEST_SIGNAL);
+    assert_eq!(delivered(), 2);
+
+    signal::unregister(first);
+    signal::raise(TEST_SIGNAL);
+    assert_eq!(delivered(), 3);
+    signal::unregister(second);
+    signal::raise(TEST_SIGNAL);
+    assert_eq!(delivered(), 3);
+}
+
+pub fn deferred() {
+    DELIVERED.store(0, Ordering::Relaxed);
+    let id = signal::register(TEST_SIGNAL, "test", count).expect("too many callbacks");
+    signal::raise_deferred(TEST_SIGNAL);
+    signal::raise_deferred(TEST_SIGNAL);
+    assert_eq!(delivered(), 0);
+    assert!(signal::is_pending(TEST_SIGNAL));
+
+    // raised twice while pending, delivered once; the tests run from the
+    // keyboard handler, so only `TEST_SIGNAL` is delivered, not everything
+    // that `dispatch` would deliver
+    assert!(signal::raise_pending(TEST_SIGNAL));
+    assert_eq!(delivered(), 1);
+    assert!(!signal::is_pending(TEST_SIGNAL));
+    assert!(!signal::raise_pending(TEST_SIGNAL));
+    assert_eq!(delivered(), 1);
+    signal::unregister(id);
+}
+
+pub fn scheduled() {
+    const TICKS: usize = 3;
+
+    DELIVERED.store(0, Ordering::Relaxed);
+    let id = signal::register(TEST_SIGNAL, "test", count).expect("too many callbacks");
+    assert!(signal::schedule(TEST_SIGNAL, 0));
+    assert!(signal::raise_pending(TEST_SIGNAL));
+    assert_eq!(delivered(), 1);
+
+    // the tests run from the keyboard handler with interrupts disabled, so
+    // no tick passes until they are enabled
+    assert!(signal::schedule(TEST_SIGNAL, TICKS));
+    let start = interrupts::ticks();
+    assert!(signal::scheduled().any(|(signal, ticks)| signal == TEST_SIGNAL && ticks == TICKS));
+
+    // the timer has a higher priority than the keyboard, so it still ticks
+    // once interrupts are enabled; the check runs after every interrupt, with
+    // interrupts disabled, so the tick the signal is delivered on is exact
+    let mut elapsed = None;
+    interrupts::with_interrupts(|| {
+        while elapsed.is_none() {
+            interrupts::halt_unless(|| {
+                if signal::raise_pending(TEST_SIGNAL) {
+                    elapsed = Some(interrupts::ticks().wrapping_sub(start));
+                }
+                elapsed.is_some()
+            });
+        }
+    });
+    assert_eq!(elapsed, Some(TICKS));
+    assert_eq!(delivered(), 2);
+    assert!(signal::scheduled().all(|(signal, _)| signal != TEST_SIGNAL));
+
+    assert!(signal::schedule(TEST_SIGNAL, TICKS));
+    signal::cancel(TEST_SIGNAL);
+    assert!(signal::scheduled().all(|(signal, _)| signal != TEST_SIGNAL));
+    signal::unregister(id);
+}
@@ -0,0 +1,59 @@
+use crate::memory::heap::{kfree, kmalloc, ksize};
+use crate::memory::slab;
+use alloc::vec::Vec;
+
+pub fn named_cache() {
+    let cache = slab::create_cache("test-24", 20);
+    assert_eq!(cache.object_size(), 24);
+
+    let mut found = false;
+    slab::for_each_cache(|other| found |= core::ptr::eq(other, cache));
+    assert!(found);
+
+    let first = cache.allocate();
+    let second = cache.allocate();
+    assert!(!first.is_null() && !second.is_null());
+    assert_eq!(second as usize - first as usize, 24);
+    unsafe {
+        slab::free(first);
+        slab::free(second);
+    }
+    assert_eq!(cache.info().active_objects, 0);
+
+    unsafe { slab::destroy_cache(cache) };
+    let mut found = false;
+    slab::for_each_cache(|other| found |= other.info().name == "test-24");
+    assert!(!found);
+}
+
+pub fn many_slabs() {
+    let cache = slab::create_cache("test-many", 256);
+    let per_slab = cache.info().objects_per_slab;
+
+    let objects: Vec<*mut u8> = (0..3 * per_slab).map(|_| cache.allocate()).collect();
+    assert!(objects.iter().all(|ptr| !ptr.is_null()));
+    let info = cache.info();
+    assert_eq!(info.active_objects, 3 * per_slab);
+    assert_eq!(info.slabs, 3);
+    assert_eq!(info.wasted, 3 * (crate::memory::PAGE_SIZE - per_slab * 256));
+
+    for &ptr in &objects {
+        unsafe { slab::free(ptr) };
+    }
+    let info = cache.info();
+    assert_eq!(info.active_objects, 0);
+    assert_eq!(info.slabs, 1);
+    unsafe { slab::destroy_cache(cache) };
+}
+
+pub fn kmalloc_size_classes() {
+    for (size, class) in [(1, 8), (8, 8), (9, 16), (100, 128), (512, 512)] {
+        let ptr = kmalloc(size);
+        assert!(slab::contains(ptr));
+        assert_eq!(unsafe { ksize(ptr) }, class);
+        unsafe { kfree(ptr) };
+    }
+    let ptr = kmalloc(513);
+    assert!(!slab::contains(ptr));
+    unsafe { kfree(ptr) };
+}
@@ -0,0 +1,84 @@
+use crate::memory::paging::VirtualAddress;
+use crate::symbols::{self, demangle};
+
+pub fn demangling() {
+    assert_eq!(
+        demangle("_ZN3kfs5panic5panic17h0123456789abcdefE"),
+        "kfs::panic::panic"
+    );
+    assert_eq!(
+        demangle(
+            "_ZN60_$LT$kfs..vga_buffer..Writer$u20$as$u20$core..fmt..Write$GT$\
+             9write_str17hfedcba9876543210E"
+        ),
+        "<kfs::vga_buffer::Writer as core::fmt::Write>::write_str"
+    );
+    assert_eq!(
+        demangle("_ZN4core3ptr45drop_in_place$LT$$RF$mut$u20$$u5b$u8$u5d$$GT$E"),
+        "core::ptr::drop_in_place<&mut [u8]>"
+    );
+    // not mangled, or malformed
+    assert_eq!(demangle("kernel_main"), "kernel_main");
+    assert_eq!(demangle("_ZN3kfs"), "_ZN3kfs");
+    assert_eq!(demangle("_ZN9kfsE"), "_ZN9kfsE");
+    assert_eq!(demangle("_ZN4294967295kfsE"), "_ZN4294967295kfsE");
+}
+
+pub fn demangling_v0() {
+    assert_eq!(
+        demangle("_RNvCsjMaewn57Afh_3kfs11kernel_main"),
+        "kfs::kernel_main"
+    );
+    assert_eq!(
+        demangle("_RNvCsjMaewn57Afh_3kfs11kernel_main.llvm.1234"),
+        "kfs::kernel_main"
+    );
+    // with backreferences and an instantiating crate
+    assert_eq!(
+        demangle("_RNvXs1G_NtCsgEmfK2I1SDS_4core3fmtThxENtB6_5Debug3fmtCsjMaewn57Afh_3kfs"),
+        "<(u8, i64) as core::fmt::Debug>::fmt"
+    );
+    assert_eq!(
+        demangle("_RNCINvNtCsjrHSEGnQ3l9_3std2rt10lang_startuE0CsjMaewn57Afh_3kfs"),
+        "std::rt::lang_start::<()>::{closure#0}"
+    );
+    assert_eq!(
+        demangle(
+            "_RINvNtCsgEmfK2I1SDS_4core3ptr13drop_in_placeINtNtCslNYArtu3iFV_5alloc3vec\
+             3VecINtNtBL_5boxed3BoxSINtNtB4_6option6OptionReEEEE"
+        ),
+        "core::ptr::drop_in_place::<alloc::vec::Vec<alloc::boxed::Box<[core::option::Option<&str>]>>>"
+    );
+    assert_eq!(
+        demangle(
+            "_RINvNtCsgEmfK2I1SDS_4core3ptr13drop_in_placeINtNtCslNYArtu3iFV_5alloc5boxed\
+             3BoxDG0_INtNtNtB4_3ops8function2FnTRL1_INtNtCsjrHSEGnQ3l9_3std5panic\
+             13PanicHookInfoL0_EEEp6OutputuNtNtB4_6marker4SyncNtB2N_4SendEL_EE"
+        ),
+        "core::ptr::drop_in_place::<alloc::boxed::Box<dyn for<'a, 'b> \
+         core::ops::function::Fn<(&'a std::panic::PanicHookInfo<'b>,), Output = ()> \
+         + core::marker::Sync + core::marker::Send>>"
+    );
+    // a backreference to itself, and a missing identifier
+    assert_eq!(demangle("_RNvB_4main"), "_RNvB_4main");
+    assert_eq!(demangle("_RNvCs1234_3kfs"), "_RNvCs1234_3kfs");
+}
+
+pub fn symbolize() {
+    if !symbols::loaded() {
+        // booted by a multiboot1 loader, which doesn't load the symbol table
+        return;
+    }
+    let address = symbolize as fn() as VirtualAddress;
+    assert_eq!(
+        symbols::symbolize(address),
+        Some(("kfs::tests::symbols::symbolize", 0))
+    );
+    assert_eq!(
+        symbols::symbolize(address + 1),
+        Some(("kfs::tests::symbols::symbolize", 1))
+    );
+    assert!(symbols::lookup("tests::symbols::symbolize").any(|(_, a)| a == address));
+    assert_eq!(symbols::lookup("ymbols::symbolize").count(), 0);
+    assert_eq!(symbols::symbolize(0), None);
+}
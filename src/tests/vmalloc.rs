@@ -0,0 +1,38 @@
+use crate::memory::vmalloc::{vbrk, vfree, vmalloc, vsize};
+use crate::memory::PAGE_SIZE;
+
+pub fn vsize_rounding() {
+    let ptr = vmalloc(1);
+    assert!(!ptr.is_null());
+    assert_eq!(vsize(ptr), PAGE_SIZE);
+    unsafe { vfree(ptr) };
+}
+
+pub fn big_buffer() {
+    const SIZE: usize = 1 << 20;
+    let ptr = vmalloc(SIZE);
+    assert!(!ptr.is_null());
+    assert_eq!(vsize(ptr), SIZE);
+
+    let buffer = unsafe { core::slice::from_raw_parts_mut(ptr, SIZE) };
+    for (i, byte) in buffer.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    assert!(buffer.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+    unsafe { vfree(ptr) };
+}
+
+pub fn reuse() {
+    let first = vmalloc(3 * PAGE_SIZE);
+    unsafe { vfree(first) };
+    let second = vmalloc(3 * PAGE_SIZE);
+    assert_eq!(first, second);
+    unsafe { vfree(second) };
+}
+
+pub fn vbrk_grow_shrink() {
+    let brk = vbrk(0).unwrap();
+    assert_eq!(vbrk(PAGE_SIZE as isize), Some(brk));
+    assert_eq!(vbrk(-(PAGE_SIZE as isize)), Some(brk + PAGE_SIZE));
+    assert_eq!(vbrk(0), Some(brk));
+}
@@ -1,6 +1,10 @@
 use crate::interrupts;
+use crate::memory::paging::{ioremap, CacheMode, Page};
+use crate::memory::{self, KERNEL_OFFSET};
 use crate::port::Port;
+use crate::serial;
 use core::fmt;
+use core::mem::size_of;
 use lazy_static::lazy_static;
 use spin::Mutex;
 use volatile::Volatile;
@@ -76,7 +80,7 @@ fn hide_cursor() {
     update_cursor(VGA_HEIGHT + 1, 0);
 }
 
-const VGA_ADDRESS: usize = 0xb8000;
+pub const VGA_ADDRESS: usize = 0xb8000;
 pub const VGA_WIDTH: usize = 80;
 pub const VGA_HEIGHT: usize = 25;
 pub const VGA_HISTORY: usize = 200; // TODO: assert!(VGA_HISTORY >= VGA_HEIGHT)
@@ -104,6 +108,7 @@ pub struct Writer {
 
 impl Writer {
     pub fn write_byte(&mut self, byte: u8) {
+        serial::write_byte(byte);
         match byte {
             b'\n' => self.new_line(),
             byte => {
@@ -141,6 +146,10 @@ impl Writer {
         self.set_foreground_color(Color::White);
     }
 
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+    }
+
     // TODO: with_foreground_color similar to without_interrupts
 
     pub fn set_cursor(&mut self, col: usize) {
@@ -180,6 +189,21 @@ impl Writer {
         self.redraw();
     }
 
+    /// Like `clear_screen`, but the screen is filled with the background
+    /// color of the writer.
+    pub fn fill_screen(&mut self) {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        let screen = &mut self.screens[self.screen_idx];
+        screen.history = 0;
+        screen.scroll_up = 0;
+        screen.bytes = [[blank; VGA_WIDTH]; VGA_HISTORY];
+        self.column_position = 0;
+        self.redraw();
+    }
+
     pub fn move_up(&mut self) {
         if self.screens[self.screen_idx].scroll_up < self.screens[self.screen_idx].history {
             self.screens[self.screen_idx].scroll_up += 1;
@@ -262,7 +286,9 @@ lazy_static! {
     pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
         column_position: 0,
         color_code: ColorCode::new(Color::White, Color::Black),
-        buffer: unsafe { &mut *(VGA_ADDRESS as *mut Buffer) },
+        // reached through the boot page tables, then the ones of
+        // `remap_the_kernel`, until `map_buffer`
+        buffer: unsafe { &mut *((KERNEL_OFFSET + VGA_ADDRESS) as *mut Buffer) },
         screen_idx: 0,
         screens: core::array::from_fn(|_| Screen {
             bytes: [[ScreenChar::black_space(); VGA_WIDTH]; VGA_HISTORY],
@@ -272,6 +298,18 @@ lazy_static! {
     });
 }
 
+/// Maps the text buffer in the ioremap window, once the kernel has its own page tables.
+pub fn map_buffer() {
+    let address = ioremap(VGA_ADDRESS, size_of::<Buffer>(), CacheMode::Uncached);
+    interrupts::without_interrupts(|| {
+        WRITER.lock().buffer = unsafe { &mut *(address as *mut Buffer) };
+    });
+    // mapped by `remap_the_kernel` for the writer until now
+    memory::with_memory(|active_table, _| {
+        active_table.unmap_frame(Page::containing_address(KERNEL_OFFSET + VGA_ADDRESS))
+    });
+}
+
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
@@ -283,6 +321,26 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+/// Like `println!`, unless the `quiet` option is given.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::cmdline::params().verbosity >= $crate::cmdline::Verbosity::Info {
+            $crate::println!($($arg)*);
+        }
+    };
+}
+
+/// Like `println!`, only with the `verbose` option.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::cmdline::params().verbosity >= $crate::cmdline::Verbosity::Debug {
+            $crate::println!($($arg)*);
+        }
+    };
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;